@@ -9,7 +9,8 @@ export default function BlockpipeEditor(props: {defaultValue: string, expectValu
     const [editorTwoValue, setEditorTwoValue] = useState("");
 
     const handlePlayClick = () => {
-        let res = blockpipe.wasm_interpret_from_string(editorOneValue, [], true);
+        // a step limit keeps an endless loop from freezing the page
+        let res = blockpipe.wasm_interpret_from_string(editorOneValue, [], true, undefined, 10_000_000, undefined, undefined);
         setEditorTwoValue(res);
     };
 
@@ -0,0 +1,38 @@
+use std::{env, path::PathBuf, process::Command};
+
+// cargo only produces the runtime's static library when the runtime crate
+// itself is built, not when it's a dependency, so we build it ourselves and
+// hand its location to the linker.
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let runtime = manifest_dir.join("../runtime");
+    let target_dir =
+        PathBuf::from(env::var("OUT_DIR").unwrap()).join("runtime");
+
+    println!("cargo:rerun-if-changed={}", runtime.join("src").display());
+    // the runtime is built on the language crate, so it changes along with it
+    let language = manifest_dir.join("../language");
+    println!("cargo:rerun-if-changed={}", language.join("src").display());
+    println!(
+        "cargo:rerun-if-changed={}",
+        runtime.join("Cargo.toml").display()
+    );
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args(["build", "--release", "--lib", "--manifest-path"])
+        .arg(runtime.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        // clippy and friends wrap rustc for the workspace we're building
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .env_remove("RUSTC_WRAPPER")
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "failed to build the runtime library");
+
+    println!(
+        "cargo:rustc-env=BLOCKPIPE_RUNTIME_LIBRARY={}",
+        target_dir.join("release").join("libruntime.a").display()
+    );
+}
@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, FuncRef, InstBuilder, Signature, Type, Value,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{
+    default_libcall_names, DataDescription, DataId, FuncId, Linkage, Module,
+};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use language::{bound_names, captures, ASTNode, Arm, LiteralVariant, PipeType};
+
+use crate::CompileError;
+
+fn codegen_error(error: impl ToString) -> CompileError {
+    CompileError::Codegen(error.to_string())
+}
+
+/// the functions of the runtime library that compiled code calls into, along
+/// with their parameter and return types. `P` stands in for pointers (and
+/// lengths and spans), whose size depends on the target.
+const RUNTIME_FUNCTIONS: &[(&str, &[Type], &[Type])] = &[
+    ("bp_unit", &[], &[P]),
+    ("bp_integer", &[types::I64], &[P]),
+    ("bp_float", &[types::F64], &[P]),
+    ("bp_boolean", &[types::I8], &[P]),
+    ("bp_string", &[P, P], &[P]),
+    ("bp_drop", &[P], &[]),
+    ("bp_tuple", &[], &[P]),
+    ("bp_tuple_push", &[P, P], &[]),
+    ("bp_record", &[], &[P]),
+    ("bp_field", &[P, P, P, P], &[]),
+    ("bp_paste", &[P, P, P, P, P], &[]),
+    ("bp_last", &[P], &[P]),
+    ("bp_lookup", &[P, P, P, P, P], &[P]),
+    ("bp_bind", &[P, P, P, P], &[P]),
+    ("bp_declare", &[P, P, P], &[P]),
+    ("bp_closure", &[P, P, P, P], &[P]),
+    ("bp_capture", &[P, P, P, P], &[]),
+    ("bp_pipe", &[P, P, P, types::I8, types::I8, P, P], &[P]),
+    ("bp_access", &[P, P, P, P], &[P]),
+    ("bp_annotate", &[P, P, P, P], &[P]),
+    ("bp_type", &[P], &[P]),
+    ("bp_match", &[P, P, P, P, P], &[P]),
+    ("bp_arm", &[P, P, P, P, P, P, P, types::I8], &[P]),
+    ("bp_no_match", &[P, P, P, P], &[P]),
+    ("bp_failed", &[P], &[types::I8]),
+    ("bp_main", &[P, P, P, types::I32, P], &[types::I32]),
+];
+const P: Type = types::INVALID;
+
+/// lowers programs to an object file for the host, in which every block is
+/// a native function and every operation on values is a call into the
+/// runtime library.
+pub struct Compiler {
+    module: ObjectModule,
+    runtime: HashMap<&'static str, FuncId>,
+    // the data objects holding string constants, by contents
+    strings: HashMap<String, DataId>,
+    // blocks that have been declared but whose bodies are still to come,
+    // along with whether they're blocks of the program (rather than its
+    // root), whose last statement is in tail position
+    pending_blocks: Vec<(FuncId, Vec<ASTNode>, bool)>,
+}
+
+impl Compiler {
+    pub fn new() -> Result<Compiler, CompileError> {
+        let mut flags = settings::builder();
+        // executables are position independent by default nowadays
+        flags.set("is_pic", "true").map_err(codegen_error)?;
+        flags.set("opt_level", "speed").map_err(codegen_error)?;
+        let isa = cranelift_native::builder()
+            .map_err(codegen_error)?
+            .finish(settings::Flags::new(flags))
+            .map_err(codegen_error)?;
+
+        let builder =
+            ObjectBuilder::new(isa, "blockpipe", default_libcall_names())
+                .map_err(codegen_error)?;
+        let mut module = ObjectModule::new(builder);
+
+        let mut runtime = HashMap::new();
+        for (name, params, returns) in RUNTIME_FUNCTIONS {
+            let signature = Self::signature(&module, params, returns);
+            let id = module
+                .declare_function(name, Linkage::Import, &signature)
+                .map_err(codegen_error)?;
+            runtime.insert(*name, id);
+        }
+
+        Ok(Compiler {
+            module,
+            runtime,
+            strings: HashMap::new(),
+            pending_blocks: vec![],
+        })
+    }
+
+    fn signature(
+        module: &ObjectModule,
+        params: &[Type],
+        returns: &[Type],
+    ) -> Signature {
+        let pointer = module.target_config().pointer_type();
+        let abi_param = |ty: &Type| match *ty {
+            P => AbiParam::new(pointer),
+            types::I8 => AbiParam::new(types::I8).uext(),
+            ty => AbiParam::new(ty),
+        };
+
+        let mut signature = module.make_signature();
+        signature.params.extend(params.iter().map(abi_param));
+        signature.returns.extend(returns.iter().map(abi_param));
+        signature
+    }
+
+    /// the signature shared by every block: context in, value out
+    fn block_signature(&self) -> Signature {
+        Self::signature(&self.module, &[P], &[P])
+    }
+
+    /// compile
+    ///
+    /// Compiles a program into an object file defining `main`, which runs the
+    /// program through the runtime's entry point. The source is embedded in
+    /// the object, since the runtime reads the parts of the program that
+    /// aren't code - patterns, annotations and the like - back from it.
+    pub fn compile(mut self, source: &str) -> Result<Vec<u8>, CompileError> {
+        let root = language::parse_from_string(source)?;
+        let root_id = self.declare_block(vec![root], false)?;
+
+        while let Some((id, body, tail)) = self.pending_blocks.pop() {
+            self.define_block(id, &body, tail)?;
+        }
+
+        self.define_main(root_id, source)?;
+
+        let product = self.module.finish();
+        product.emit().map_err(codegen_error)
+    }
+
+    fn declare_block(
+        &mut self,
+        body: Vec<ASTNode>,
+        tail: bool,
+    ) -> Result<FuncId, CompileError> {
+        let signature = self.block_signature();
+        let id = self
+            .module
+            .declare_anonymous_function(&signature)
+            .map_err(codegen_error)?;
+        self.pending_blocks.push((id, body, tail));
+        Ok(id)
+    }
+
+    fn define_block(
+        &mut self,
+        id: FuncId,
+        body: &[ASTNode],
+        tail: bool,
+    ) -> Result<(), CompileError> {
+        let mut context = self.module.make_context();
+        context.func.signature = self.block_signature();
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder =
+            FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let mut translator = Translator {
+            context: builder.block_params(entry)[0],
+            builder,
+            compiler: self,
+            bound: bound_names(body),
+            func_refs: HashMap::new(),
+            unwind: None,
+        };
+
+        // the value that the last statement evaluates to is the one that we
+        // return, and the rest are freed. empty blocks just evaluate to the
+        // empty tuple.
+        let mut last_value = None;
+        for (index, expression) in body.iter().enumerate() {
+            if let Some(value) = last_value.take() {
+                translator.call_runtime("bp_drop", &[value]);
+            }
+            let tail = tail && index + 1 == body.len();
+            last_value = Some(translator.statement(expression, tail)?);
+        }
+        let last_value = match last_value {
+            Some(value) => value,
+            None => translator.call_runtime("bp_unit", &[]),
+        };
+
+        translator.builder.ins().return_(&[last_value]);
+        translator.finish_unwind();
+        translator.builder.seal_all_blocks();
+        translator.builder.finalize();
+
+        self.define_function(id, &mut context)
+    }
+
+    fn define_main(
+        &mut self,
+        root_id: FuncId,
+        source: &str,
+    ) -> Result<(), CompileError> {
+        let pointer = self.module.target_config().pointer_type();
+        let signature =
+            Self::signature(&self.module, &[types::I32, P], &[types::I32]);
+        let id = self
+            .module
+            .declare_function("main", Linkage::Export, &signature)
+            .map_err(codegen_error)?;
+
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder =
+            FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let (argc, argv) = (
+            builder.block_params(entry)[0],
+            builder.block_params(entry)[1],
+        );
+
+        let root = self.module.declare_func_in_func(root_id, builder.func);
+        let root = builder.ins().func_addr(pointer, root);
+        let source_id = self.string_data(source)?;
+        let source_global =
+            self.module.declare_data_in_func(source_id, builder.func);
+        let source_address = builder.ins().symbol_value(pointer, source_global);
+        let source_length = builder.ins().iconst(pointer, source.len() as i64);
+        let bp_main = self
+            .module
+            .declare_func_in_func(self.runtime["bp_main"], builder.func);
+        let call = builder
+            .ins()
+            .call(bp_main, &[root, source_address, source_length, argc, argv]);
+        let status = builder.inst_results(call)[0];
+        builder.ins().return_(&[status]);
+        builder.seal_all_blocks();
+        builder.finalize();
+
+        self.define_function(id, &mut context)
+    }
+
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        context: &mut Context,
+    ) -> Result<(), CompileError> {
+        self.module
+            .define_function(id, context)
+            .map_err(codegen_error)?;
+        self.module.clear_context(context);
+        Ok(())
+    }
+
+    /// the data object holding a string constant, which is shared between
+    /// every use of the same string
+    fn string_data(&mut self, string: &str) -> Result<DataId, CompileError> {
+        if let Some(id) = self.strings.get(string) {
+            return Ok(*id);
+        }
+
+        let id = self
+            .module
+            .declare_anonymous_data(false, false)
+            .map_err(codegen_error)?;
+        let mut description = DataDescription::new();
+        // empty data objects aren't a thing, so strings are nul terminated
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+        description.define(bytes.into_boxed_slice());
+        self.module
+            .define_data(id, &description)
+            .map_err(codegen_error)?;
+
+        self.strings.insert(string.to_string(), id);
+        Ok(id)
+    }
+}
+
+/// translates the expressions of a single block into the body of its
+/// function
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    compiler: &'a mut Compiler,
+    // the context that the block's function was handed
+    context: Value,
+    // the names the block binds, which closures created in it capture
+    bound: Vec<String>,
+    func_refs: HashMap<FuncId, FuncRef>,
+    // where the block returns from once the runtime has raised an error,
+    // if it can raise any
+    unwind: Option<Block>,
+}
+
+impl Translator<'_> {
+    fn pointer(&self) -> Type {
+        self.compiler.module.target_config().pointer_type()
+    }
+
+    fn func_ref(&mut self, id: FuncId) -> FuncRef {
+        *self.func_refs.entry(id).or_insert_with(|| {
+            self.compiler
+                .module
+                .declare_func_in_func(id, self.builder.func)
+        })
+    }
+
+    /// calls a runtime function, returning its result (or the unit value's
+    /// placeholder, for functions that return nothing)
+    fn call_runtime(&mut self, name: &str, args: &[Value]) -> Value {
+        let func_ref = self.func_ref(self.compiler.runtime[name]);
+        let call = self.builder.ins().call(func_ref, args);
+        match self.builder.inst_results(call) {
+            [result] => *result,
+            _ => self.builder.ins().iconst(types::I8, 0),
+        }
+    }
+
+    /// calls a runtime function that can raise an error, returning from the
+    /// block if it did. the values the block was holding on to are left
+    /// behind, as the program is about to stop anyway.
+    fn call_fallible(&mut self, name: &str, args: &[Value]) -> Value {
+        let result = self.call_runtime(name, args);
+        let failed = self.call_runtime("bp_failed", &[self.context]);
+        let unwind = *self
+            .unwind
+            .get_or_insert_with(|| self.builder.create_block());
+        let next = self.builder.create_block();
+        self.builder.ins().brif(failed, unwind, &[], next, &[]);
+        self.builder.switch_to_block(next);
+        result
+    }
+
+    /// returns null from the block once it has raised an error, leaving the
+    /// error for its caller
+    fn finish_unwind(&mut self) {
+        if let Some(unwind) = self.unwind {
+            let pointer = self.pointer();
+            self.builder.switch_to_block(unwind);
+            let null = self.builder.ins().iconst(pointer, 0);
+            self.builder.ins().return_(&[null]);
+        }
+    }
+
+    /// a pointer to a string constant, along with its length
+    fn string(&mut self, string: &str) -> Result<(Value, Value), CompileError> {
+        let pointer = self.pointer();
+        let id = self.compiler.string_data(string)?;
+        let global = self
+            .compiler
+            .module
+            .declare_data_in_func(id, self.builder.func);
+        let address = self.builder.ins().symbol_value(pointer, global);
+        let length = self.builder.ins().iconst(pointer, string.len() as i64);
+        Ok((address, length))
+    }
+
+    /// the bounds of a span, which is how compiled code refers to the
+    /// runtime's copy of the node it belongs to
+    fn span(&mut self, span: &Range<usize>) -> (Value, Value) {
+        let pointer = self.pointer();
+        let start = self.builder.ins().iconst(pointer, span.start as i64);
+        let end = self.builder.ins().iconst(pointer, span.end as i64);
+        (start, end)
+    }
+
+    fn flag(&mut self, flag: bool) -> Value {
+        self.builder.ins().iconst(types::I8, flag as i64)
+    }
+
+    /// a statement of a block. pipes and matches in tail position leave
+    /// their last call for the caller of the block to make.
+    fn statement(
+        &mut self,
+        node: &ASTNode,
+        tail: bool,
+    ) -> Result<Value, CompileError> {
+        match node {
+            ASTNode::Pipe(stages, pipe_types, _) => {
+                self.pipe(stages, pipe_types, tail)
+            }
+            ASTNode::Match(subject, arms, span) => {
+                self.match_arms(subject, arms, span, tail)
+            }
+            _ => self.expression(node),
+        }
+    }
+
+    fn expression(&mut self, node: &ASTNode) -> Result<Value, CompileError> {
+        match node {
+            ASTNode::Literal(literal, _) => self.literal(literal),
+            ASTNode::Tuple(elements, span) => self.tuple(elements, span),
+            ASTNode::Identifier(id, span) => {
+                let (name, length) = self.string(id)?;
+                let (start, end) = self.span(span);
+                Ok(self.call_fallible(
+                    "bp_lookup",
+                    &[self.context, name, length, start, end],
+                ))
+            }
+            ASTNode::Record(fields, _) => {
+                let record = self.call_runtime("bp_record", &[]);
+                for (label, node) in fields {
+                    let value = self.expression(node)?;
+                    let (label, length) = self.string(label)?;
+                    self.call_runtime(
+                        "bp_field",
+                        &[record, label, length, value],
+                    );
+                }
+                Ok(record)
+            }
+            ASTNode::Block(body, span) => {
+                let id = self.compiler.declare_block(body.clone(), true)?;
+                let func_ref = self.func_ref(id);
+                let pointer = self.pointer();
+                let function = self.builder.ins().func_addr(pointer, func_ref);
+                let (start, end) = self.span(span);
+                let closure = self.call_runtime(
+                    "bp_closure",
+                    &[self.context, function, start, end],
+                );
+
+                // like interpreted closures, they capture the names the
+                // block uses, along with everything bound where they're
+                // created
+                let mut names = captures(body);
+                for name in &self.bound {
+                    if !names.contains(name) {
+                        names.push(name.clone());
+                    }
+                }
+                for id in names {
+                    let (name, length) = self.string(&id)?;
+                    self.call_runtime(
+                        "bp_capture",
+                        &[self.context, closure, name, length],
+                    );
+                }
+                Ok(closure)
+            }
+            ASTNode::Binding((id, value), _) => {
+                let value = self.expression(value)?;
+                let (name, length) = self.string(id)?;
+                Ok(self.call_runtime(
+                    "bp_bind",
+                    &[self.context, name, length, value],
+                ))
+            }
+            ASTNode::Parameters(_, span) => {
+                let (start, end) = self.span(span);
+                Ok(self
+                    .call_fallible("bp_declare", &[self.context, start, end]))
+            }
+            ASTNode::Pipe(stages, pipe_types, _) => {
+                self.pipe(stages, pipe_types, false)
+            }
+            ASTNode::Match(subject, arms, span) => {
+                self.match_arms(subject, arms, span, false)
+            }
+            ASTNode::Access(operand, _, span) => {
+                let value = self.expression(operand)?;
+                let (start, end) = self.span(span);
+                Ok(self.call_fallible(
+                    "bp_access",
+                    &[self.context, value, start, end],
+                ))
+            }
+            ASTNode::Annotated(operand, _, span) => {
+                let value = self.expression(operand)?;
+                let (start, end) = self.span(span);
+                Ok(self.call_fallible(
+                    "bp_annotate",
+                    &[self.context, value, start, end],
+                ))
+            }
+            ASTNode::Type(operand, _) => {
+                // `type (x)` describes x itself rather than the single
+                // element tuple wrapped around it
+                let value = match operand.as_ref() {
+                    ASTNode::Tuple(elements, _) if elements.len() == 1 => {
+                        self.expression(&elements[0])?
+                    }
+                    _ => self.expression(operand)?,
+                };
+                Ok(self.call_runtime("bp_type", &[value]))
+            }
+            ASTNode::Paste(operand, span) => {
+                let tuple = self.call_runtime("bp_tuple", &[]);
+                self.paste(tuple, operand, span)?;
+                Ok(self.call_runtime("bp_last", &[tuple]))
+            }
+            ASTNode::Import(_, span) => Err(CompileError::Unsupported {
+                construct: "import".to_string(),
+                span: span.clone(),
+            }),
+            ASTNode::Error(span) => Err(CompileError::Unsupported {
+                construct: "Source that failed to parse".to_string(),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    fn literal(
+        &mut self,
+        literal: &LiteralVariant,
+    ) -> Result<Value, CompileError> {
+        let value = match literal {
+            LiteralVariant::IntegerLiteral(i) => {
+                let i = self.builder.ins().iconst(types::I64, *i);
+                self.call_runtime("bp_integer", &[i])
+            }
+            LiteralVariant::FloatLiteral(f) => {
+                let f = self.builder.ins().f64const(*f);
+                self.call_runtime("bp_float", &[f])
+            }
+            LiteralVariant::BooleanLiteral(b) => {
+                let b = self.builder.ins().iconst(types::I8, *b as i64);
+                self.call_runtime("bp_boolean", &[b])
+            }
+            LiteralVariant::StringLiteral(s) => {
+                let (string, length) = self.string(s)?;
+                self.call_runtime("bp_string", &[string, length])
+            }
+        };
+        Ok(value)
+    }
+
+    fn pipe(
+        &mut self,
+        stages: &[ASTNode],
+        pipe_types: &[PipeType],
+        tail: bool,
+    ) -> Result<Value, CompileError> {
+        let mut value = self.expression(&stages[0])?;
+        for (index, (stage, pipe_type)) in
+            stages[1..].iter().zip(pipe_types).enumerate()
+        {
+            let callee = self.expression(stage)?;
+            let destructure =
+                self.flag(matches!(pipe_type, PipeType::Destructure));
+            let tail = self.flag(tail && index + 1 == pipe_types.len());
+            let (start, end) = self.span(&stage.span());
+            value = self.call_fallible(
+                "bp_pipe",
+                &[self.context, value, callee, destructure, tail, start, end],
+            );
+        }
+        Ok(value)
+    }
+
+    /// tries the arms of a match in turn. an arm whose pattern matches has
+    /// its guard and body evaluated to blocks, which the runtime runs; if the
+    /// guard turns the arm down, the next arm is up.
+    fn match_arms(
+        &mut self,
+        subject: &ASTNode,
+        arms: &[Arm],
+        span: &Range<usize>,
+        tail: bool,
+    ) -> Result<Value, CompileError> {
+        let pointer = self.pointer();
+        let subject = self.expression(subject)?;
+        let done = self.builder.create_block();
+        let result = self.builder.append_block_param(done, pointer);
+
+        for (index, arm) in arms.iter().enumerate() {
+            let matched = self.builder.create_block();
+            let next = self.builder.create_block();
+            let (start, end) = self.span(span);
+            let index = self.builder.ins().iconst(pointer, index as i64);
+            let captured = self.call_runtime(
+                "bp_match",
+                &[self.context, subject, start, end, index],
+            );
+            self.builder.ins().brif(captured, matched, &[], next, &[]);
+
+            self.builder.switch_to_block(matched);
+            let guard = match &arm.guard {
+                Some(guard) => self.expression(guard)?,
+                None => self.builder.ins().iconst(pointer, 0),
+            };
+            let body = self.expression(&arm.body)?;
+            let tail = self.flag(tail);
+            let value = self.call_fallible(
+                "bp_arm",
+                &[self.context, captured, guard, body, start, end, index, tail],
+            );
+            self.builder.ins().brif(value, done, &[value], next, &[]);
+
+            self.builder.switch_to_block(next);
+        }
+
+        // bp_no_match always raises an error, so the block returns before
+        // the jump - but it still needs an end
+        let (start, end) = self.span(span);
+        let value = self
+            .call_fallible("bp_no_match", &[self.context, subject, start, end]);
+        self.builder.ins().jump(done, &[value]);
+
+        self.builder.switch_to_block(done);
+        self.call_runtime("bp_drop", &[subject]);
+        Ok(result)
+    }
+
+    fn tuple(
+        &mut self,
+        elements: &[ASTNode],
+        span: &Range<usize>,
+    ) -> Result<Value, CompileError> {
+        let tuple = self.call_runtime("bp_tuple", &[]);
+        for element in elements {
+            if let ASTNode::Paste(operand, _) = element {
+                self.paste(tuple, operand, span)?;
+            } else {
+                let value = self.expression(element)?;
+                self.call_runtime("bp_tuple_push", &[tuple, value]);
+            }
+        }
+        Ok(tuple)
+    }
+
+    /// splices everything in the operand of `paste (...)` into the tuple.
+    /// errors point at the span given, which is the tuple's - or the paste's
+    /// own, outside of a tuple.
+    fn paste(
+        &mut self,
+        tuple: Value,
+        operand: &ASTNode,
+        span: &Range<usize>,
+    ) -> Result<(), CompileError> {
+        let pasted = match operand {
+            ASTNode::Tuple(elements, _) => elements.as_slice(),
+            _ => std::slice::from_ref(operand),
+        };
+
+        for node in pasted {
+            let value = self.expression(node)?;
+            let (start, end) = self.span(span);
+            self.call_fallible(
+                "bp_paste",
+                &[self.context, tuple, value, start, end],
+            );
+        }
+        Ok(())
+    }
+}
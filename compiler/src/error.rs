@@ -0,0 +1,60 @@
+use core::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use language::{EvaluationError, SyntaxError};
+
+/// the different ways in which compiling a program can go wrong
+#[derive(Debug, Clone)]
+pub enum CompileError {
+    Syntax(SyntaxError),
+    // a construct that the native backend can't handle (yet)
+    Unsupported {
+        construct: String,
+        span: Range<usize>,
+    },
+    Codegen(String),
+    Link(String),
+}
+
+impl CompileError {
+    /// renders the error against the source being compiled, pointing at
+    /// where it went wrong when that's known - in the same format as the
+    /// interpreter's errors
+    pub fn render(&self, source: &str) -> String {
+        match self {
+            CompileError::Syntax(error) => {
+                EvaluationError::from(error.clone()).render(source)
+            }
+            CompileError::Unsupported { span, .. } => {
+                language::render_error(&self.to_string(), source, span)
+            }
+            CompileError::Codegen(_) | CompileError::Link(_) => {
+                format!("error: {}\n", self)
+            }
+        }
+    }
+}
+
+impl From<SyntaxError> for CompileError {
+    fn from(error: SyntaxError) -> Self {
+        CompileError::Syntax(error)
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Syntax(error) => write!(f, "{}", error),
+            CompileError::Unsupported { construct, .. } => {
+                write!(f, "{} cannot be compiled yet", construct)
+            }
+            CompileError::Codegen(message) => {
+                write!(f, "Code generation failed: {}", message)
+            }
+            CompileError::Link(message) => {
+                write!(f, "Linking failed: {}", message)
+            }
+        }
+    }
+}
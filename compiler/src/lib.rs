@@ -0,0 +1,26 @@
+mod codegen;
+mod error;
+mod link;
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+pub use codegen::Compiler;
+pub use error::CompileError;
+pub use link::{link, runtime_library};
+
+/// compiles a program into an object file for the host
+pub fn compile_to_object(input: &str) -> Result<Vec<u8>, CompileError> {
+    Compiler::new()?.compile(input)
+}
+
+/// compiles a program into a standalone executable at the given path. its
+/// output matches that of interpreting the program and executing its root.
+pub fn compile_from_string(
+    input: &str,
+    output: &Path,
+) -> Result<(), CompileError> {
+    let object = compile_to_object(input)?;
+    link(&object, output)
+}
@@ -0,0 +1,71 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::CompileError;
+
+/// the system libraries that the (rust) runtime library depends on
+#[cfg(target_os = "macos")]
+const SYSTEM_LIBRARIES: &[&str] = &["-lSystem", "-lc", "-lm"];
+#[cfg(not(target_os = "macos"))]
+const SYSTEM_LIBRARIES: &[&str] = &[
+    "-lgcc_s",
+    "-lutil",
+    "-lrt",
+    "-lpthread",
+    "-lm",
+    "-ldl",
+    "-lc",
+];
+
+/// the runtime library that compiled programs link against: wherever
+/// $BLOCKPIPE_RUNTIME says it is, or otherwise the one built alongside the
+/// compiler.
+pub fn runtime_library() -> PathBuf {
+    env::var_os("BLOCKPIPE_RUNTIME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("BLOCKPIPE_RUNTIME_LIBRARY")))
+}
+
+/// links an object file produced by the compiler against the runtime
+/// library, using the system's C compiler ($CC, or cc) as the linker.
+pub fn link(object: &[u8], output: &Path) -> Result<(), CompileError> {
+    let runtime = runtime_library();
+    if !runtime.exists() {
+        return Err(CompileError::Link(format!(
+            "Could not find the runtime library at {}",
+            runtime.display()
+        )));
+    }
+
+    let link_error =
+        |error: std::io::Error| CompileError::Link(error.to_string());
+
+    let mut object_path = output.as_os_str().to_owned();
+    object_path.push(".o");
+    let object_path = PathBuf::from(object_path);
+    fs::write(&object_path, object).map_err(link_error)?;
+
+    let linker = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(linker)
+        .arg(&object_path)
+        .arg(&runtime)
+        .args(SYSTEM_LIBRARIES)
+        .arg("-o")
+        .arg(output)
+        .output();
+    let _ = fs::remove_file(&object_path);
+
+    let result = result.map_err(link_error)?;
+    if !result.status.success() {
+        return Err(CompileError::Link(
+            String::from_utf8_lossy(&result.stderr)
+                .trim_end()
+                .to_string(),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::{env, fs, path::Path, process};
+
+use indoc::indoc;
+
+use super::*;
+
+/// compiles the program and runs it with the given arguments
+fn run_compiled(
+    name: &str,
+    source: &str,
+    arguments: &[&str],
+) -> process::Output {
+    let executable = env::temp_dir().join(format!(
+        "blockpipe-compiled-{}-{}",
+        name,
+        process::id()
+    ));
+    compile_from_string(source, &executable).unwrap();
+
+    let output = process::Command::new(&executable)
+        .args(arguments)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&executable);
+    output
+}
+
+/// compiles the program, runs it with the given arguments and returns what
+/// it printed
+fn compile_and_run(name: &str, source: &str, arguments: &[&str]) -> String {
+    let output = run_compiled(name, source, arguments);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// what interpret-execute prints for the program
+fn interpret(source: &str, arguments: &[&str]) -> String {
+    let arguments = arguments.iter().map(|arg| arg.to_string()).collect();
+    let result = language::interpret_from_string(source, Some(arguments), true);
+    format!("{:?}\n", result)
+}
+
+fn assert_matches_interpreter(name: &str, source: &str, arguments: &[&str]) {
+    assert_eq!(
+        compile_and_run(name, source, arguments),
+        interpret(source, arguments)
+    );
+}
+
+#[test]
+fn test_compile_literals() {
+    assert_matches_interpreter(
+        "literals",
+        r#"{ (1 -2.5 T "hello\tworld" ()) }"#,
+        &[],
+    );
+}
+
+#[test]
+fn test_compile_parameters() {
+    assert_matches_interpreter("parameters", "{ ($n $1 $0) }", &["a", "b"]);
+}
+
+#[test]
+fn test_compile_pipes() {
+    let source = indoc! {r#"
+        {
+            add: {
+                (($0 $1 "+") "binop_arith") |* plz
+            }
+            swap: { ($1 $0) }
+
+            (1 2) |* swap |* add | { ($0 type ($0) type ($0 $0)) }
+        }
+    "#};
+    assert_matches_interpreter("pipes", source, &[]);
+}
+
+#[test]
+fn test_compile_recursion() {
+    let source = indoc! {r#"
+        {
+            sub: { (($0 $1 "-") "binop_arith") |* plz }
+            add: { (($0 $1 "+") "binop_arith") |* plz }
+            lt: { (($0 $1 "<") "binop_cmp") |* plz }
+
+            if: { (($0 $1 $2) "if") |* plz }
+
+            fib: {
+                n: $0
+                fib_rec: rec
+                (
+                    (n 2) |* lt
+                    { n }
+                    { ((n 1) |* sub | fib_rec  (n 2) |* sub | fib_rec) |* add }
+                ) |* if
+            }
+
+            10 | fib
+        }
+    "#};
+    assert_matches_interpreter("recursion", source, &[]);
+}
+
+#[test]
+fn test_compile_paste() {
+    let source = indoc! {r#"
+        {
+            point: () | {
+                x: 1
+                y: 2
+                {}
+            }
+            xs: (2 3)
+
+            () | {
+                x: 5
+                paste (point)
+                (x y paste (xs () (4)) paste ((5 6)))
+            }
+        }
+    "#};
+    assert_matches_interpreter("paste", source, &[]);
+}
+
+#[test]
+fn test_compile_runtime_error() {
+    let executable = env::temp_dir()
+        .join(format!("blockpipe-compiled-error-{}", process::id()));
+    compile_from_string(
+        "{ (1 0) |* { (($0 $1 \"/\") \"binop_arith\") |* plz } }",
+        &executable,
+    )
+    .unwrap();
+
+    let output = process::Command::new(&executable).output().unwrap();
+    let _ = fs::remove_file(&executable);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        indoc! {r#"
+            error: Division by zero
+             --> 1:14
+              |
+            1 | { (1 0) |* { (($0 $1 "/") "binop_arith") |* plz } }
+              |              ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+              = in plz "binop_arith" at 1:45
+              = in anonymous block at 1:12
+        "#}
+    );
+}
+
+/// every program in test_src behaves the same compiled as it does
+/// interpreted: it prints the same result, or fails with the same error,
+/// reported the same way
+#[test]
+fn test_compile_fixtures() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test_src");
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+
+        let output = run_compiled(name, &source, &["argument"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let arguments = Some(vec!["argument".to_string()]);
+        match language::interpret_from_string(&source, arguments, true) {
+            Ok(value) => {
+                assert_eq!(stdout, format!("Ok({:?})\n", value), "{}", name)
+            }
+            Err(error) => {
+                assert_eq!(output.status.code(), Some(1), "{}", name);
+                assert_eq!(stderr, error.render(&source), "{}", name);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compile_unsupported() {
+    let source = r#"{ lib: import "lib.blkp" }"#;
+    let error = compile_to_object(source).unwrap_err();
+    assert!(matches!(
+        &error,
+        CompileError::Unsupported { construct, span }
+            if construct == "import" && *span == (7..24)
+    ));
+    assert_eq!(
+        error.render(source),
+        indoc! {r#"
+            error: import cannot be compiled yet
+             --> 1:8
+              |
+            1 | { lib: import "lib.blkp" }
+              |        ^^^^^^^^^^^^^^^^^
+        "#}
+    );
+    assert!(matches!(
+        compile_to_object("{ (1 }"),
+        Err(CompileError::Syntax(_))
+    ));
+}
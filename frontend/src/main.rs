@@ -1,54 +1,192 @@
 use clap::{Parser, ValueEnum};
-use std::{fs, process::{exit, Command}};
-use language::{self, interpret_from_string};
+use language::EvaluationError;
+use std::{fmt::Debug, fs, path::{Path, PathBuf}, process::exit};
+
+mod repl;
 
 /// Processes files based on the given command
 #[derive(Parser, Debug)]
 #[clap(name = "blockpipe", version = "1.0", author = "Varun Ramani <varun.ramani@gmail.com>")]
 struct BlockPipe {
     command: Commands,
-    filename: String,
-    parameters: Vec<String>
+    /// required by every command but repl, which optionally loads it
+    filename: Option<String>,
+    parameters: Vec<String>,
+    /// where compile puts the executable (by default, next to the file)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// what interpret and interpret-execute run the program with
+    #[arg(short, long, value_enum, default_value_t = Engine::Tree)]
+    engine: Engine,
+    /// how many block invocations may be in progress at once (256 by default)
+    #[arg(long)]
+    max_call_depth: Option<usize>,
+    /// how many evaluation steps the program may take
+    #[arg(long)]
+    max_steps: Option<usize>,
+    /// how many elements a tuple may have
+    #[arg(long)]
+    max_tuple_size: Option<usize>,
+    /// how many bytes a string may have
+    #[arg(long)]
+    max_string_length: Option<usize>
+}
+
+impl BlockPipe {
+    /// the limits interpret and interpret-execute run the program with,
+    /// falling back on the defaults for any that weren't given
+    fn limits(&self) -> language::Limits {
+        let defaults = language::Limits::default();
+        language::Limits {
+            max_call_depth: self.max_call_depth.or(defaults.max_call_depth),
+            max_steps: self.max_steps.or(defaults.max_steps),
+            max_tuple_size: self.max_tuple_size.or(defaults.max_tuple_size),
+            max_string_length: self.max_string_length.or(defaults.max_string_length),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Engine {
+    /// walk the syntax tree
+    Tree,
+    /// compile to bytecode and run it on a virtual machine
+    Vm
+}
+
+impl From<Engine> for language::Engine {
+    fn from(engine: Engine) -> Self {
+        match engine {
+            Engine::Tree => language::Engine::TreeWalking,
+            Engine::Vm => language::Engine::Bytecode,
+        }
+    }
 }
 
 #[derive(Parser, Debug, Clone, ValueEnum)]
 enum Commands {
+    /// print the tokens of the file
     Lex,
+    /// print the syntax tree of the file
     Parse,
+    /// evaluate the file and print what it comes to
     Interpret,
+    /// evaluate the file, then run the block it comes to with the parameters
     InterpretExecute,
-    Compile
+    /// report the errors that can be found without running the file
+    Check,
+    /// compile the file to a native executable. imports can't be compiled
+    /// yet, and are reported where they appear
+    Compile,
+    /// evaluate expressions as they are entered
+    Repl
 }
 
 fn main() {
     let opts: BlockPipe = BlockPipe::parse();
-    let file_data = match fs::read_to_string(&opts.filename) {
+    let limits = opts.limits();
+    if let Commands::Repl = opts.command {
+        repl::run(opts.filename.as_deref(), limits);
+        return;
+    }
+
+    let Some(filename) = opts.filename else {
+        println!("A filename is required");
+        exit(-1);
+    };
+    let file_data = match fs::read_to_string(&filename) {
         Ok(data) => data,
         Err(_) => {
-            println!("Failed to read {}", opts.filename);
+            println!("Failed to read {}", filename);
             exit(-1);
         }
     };
 
+    // imports are resolved relative to the file being run
+    let directory = Path::new(&filename).parent();
+
     match opts.command {
         Commands::Lex => {
-            let result = language::lex_from_string(&file_data);
-            println!("{:?}", result);
-        }, 
+            let mut failed = false;
+            for (token, span) in language::lex_from_string(&file_data) {
+                match token {
+                    Ok(token) => println!("{:?} {:?}", token, span),
+                    Err(error) => {
+                        let error = language::SyntaxError::from(error);
+                        eprint!("{}", EvaluationError::from(error).render(&file_data));
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                exit(1);
+            }
+        },
         Commands::Parse => {
-            let result = language::parse_from_string(&file_data);
-            println!("{:?}", result);
+            let (ast, errors) = language::parse_program_from_string(&file_data);
+            if errors.is_empty() {
+                println!("{:?}", ast);
+            } else {
+                for error in errors {
+                    eprint!("{}", EvaluationError::from(error).render(&file_data));
+                }
+                exit(1);
+            }
         },
         Commands::Interpret => {
-            let result = language::interpret_from_string(&file_data, None, false);
-            println!("{:?}", result);
+            warn(&file_data);
+            let result = language::interpret_with_limits(opts.engine.into(), limits, &file_data, directory, None, false);
+            report(&result, &file_data);
         },
         Commands::InterpretExecute => {
-            let result = language::interpret_from_string(&file_data, Some(opts.parameters), true);
-            println!("{:?}", result);
+            warn(&file_data);
+            let result = language::interpret_with_limits(opts.engine.into(), limits, &file_data, directory, Some(opts.parameters), true);
+            report(&result, &file_data);
+        },
+        Commands::Check => {
+            warn(&file_data);
+            let errors = language::check_from_string(&file_data);
+            if errors.is_empty() {
+                println!("No errors found in {}", filename);
+            } else {
+                for error in errors {
+                    eprint!("{}", error.render(&file_data));
+                }
+                exit(1);
+            }
+        },
+        Commands::Compile => {
+            let output = opts.output.map_or_else(
+                || Path::new(&filename).with_extension(""),
+                PathBuf::from,
+            );
+            match compiler::compile_from_string(&file_data, &output) {
+                Ok(()) => println!("Compiled {}", output.display()),
+                Err(error) => {
+                    eprint!("{}", error.render(&file_data));
+                    exit(1);
+                }
+            }
         },
-        _ => {
-            println!("unimplemented");
+        Commands::Repl => unreachable!(),
+    }
+}
+
+/// prints any warnings about the source before it runs
+fn warn(source: &str) {
+    for warning in language::warnings_from_string(source) {
+        eprint!("{}", warning.render(source));
+    }
+}
+
+/// prints the outcome of an interpretation, rendering errors against the
+/// source that produced them.
+fn report<T: Debug>(result: &Result<T, EvaluationError>, source: &str) {
+    match result {
+        Ok(_) => println!("{:?}", result),
+        Err(error) => {
+            eprint!("{}", error.render(source));
+            exit(1);
         }
     }
 }
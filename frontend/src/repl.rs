@@ -0,0 +1,300 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use language::{
+    ASTNode, EvaluationError, Interpreter, Limits, Source, TypeDescriptor,
+    Value,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+const HELP: &str = "\
+Enter an expression to evaluate it. Bindings persist between inputs, and
+unclosed tuples, blocks and strings continue onto the next line.
+
+  :type <expr>    show the type of an expression
+  :ast <expr>     show the syntax tree of an expression
+  :env            list the current bindings
+  :load <file>    evaluate the bindings of a file into this session
+  :help           show this message
+  :quit           leave the repl (as does ctrl-d)";
+
+#[cfg(test)]
+mod tests;
+
+/// an interactive session - the interpreter (and therefore every binding
+/// made so far) lives for as long as the session does. results are written
+/// to `out` and errors to `err`.
+pub struct Repl<O: Write, E: Write> {
+    interpreter: Interpreter,
+    limits: Limits,
+    // how many inputs have been evaluated, which names their sources
+    inputs: usize,
+    // the lines of an input that isn't complete yet
+    buffer: String,
+    out: O,
+    err: E,
+}
+
+impl Repl<io::Stdout, io::Stderr> {
+    pub fn new() -> Self {
+        Repl::with_output(io::stdout(), io::stderr())
+    }
+}
+
+impl<O: Write, E: Write> Repl<O, E> {
+    pub fn with_output(out: O, err: E) -> Self {
+        Repl {
+            interpreter: language::toplevel_interpreter(ASTNode::Block(
+                vec![],
+                0..0,
+            )),
+            limits: Limits::default(),
+            inputs: 0,
+            buffer: String::new(),
+            out,
+            err,
+        }
+    }
+
+    /// caps what each input may use. every input starts afresh, so that a
+    /// long session doesn't use up its steps.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// whether the lines read so far are only part of an input
+    pub fn is_continuing(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// adds a line to the input being read, giving back the whole input once
+    /// it's complete - that is, once every tuple, block and string it opens
+    /// is closed. blank inputs are skipped.
+    pub fn accumulate(&mut self, line: &str) -> Option<String> {
+        if !self.buffer.is_empty() {
+            self.buffer.push('\n');
+        }
+        self.buffer.push_str(line);
+
+        if language::input_is_incomplete(&self.buffer) {
+            return None;
+        }
+
+        let input = std::mem::take(&mut self.buffer);
+        (!input.trim().is_empty()).then_some(input)
+    }
+
+    /// drops the lines read so far, as ctrl-c does
+    pub fn abandon(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// handles a single complete input. returns false once the user asks to
+    /// leave.
+    pub fn handle(&mut self, input: &str) -> bool {
+        self.interpreter.set_limits(self.limits);
+        let Some(command) = input.trim().strip_prefix(':') else {
+            self.evaluate_and_print(input);
+            return true;
+        };
+
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+
+        match name {
+            "quit" | "q" => return false,
+            "help" | "h" => self.print(&format!("{}\n", HELP)),
+            "type" | "t" => {
+                if let Some(value) = self.evaluate(argument) {
+                    self.print(&format!("{}\n", TypeDescriptor::of(&value)));
+                }
+            }
+            "ast" => match language::parse_from_string(argument) {
+                Ok(ast) => self.print(&format!("{:#?}\n", ast)),
+                Err(error) => {
+                    self.eprint(&EvaluationError::from(error).render(argument))
+                }
+            },
+            "env" => self.print_env(),
+            "load" | "l" => self.load(argument.trim()),
+            _ => {
+                self.eprint(&format!("Unknown command :{} (try :help)\n", name))
+            }
+        }
+
+        true
+    }
+
+    fn evaluate_and_print(&mut self, input: &str) {
+        let ast = match language::parse_from_string(input) {
+            Ok(ast) => ast,
+            Err(error) => {
+                self.eprint(&EvaluationError::from(error).render(input));
+                return;
+            }
+        };
+        self.enter_input(input);
+
+        match self.interpreter.evaluate(&ast) {
+            // a binding only ever evaluates to (), which isn't worth echoing
+            Ok(_) if matches!(ast, ASTNode::Binding(..)) => {}
+            Ok(value) => self.print(&format!("{}\n", value)),
+            Err(error) => self.eprint(&error.render(input)),
+        }
+    }
+
+    fn evaluate(&mut self, input: &str) -> Option<Value> {
+        self.enter_input(input);
+        let result = language::parse_from_string(input)
+            .map_err(EvaluationError::from)
+            .and_then(|ast| self.interpreter.evaluate(&ast));
+
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.eprint(&error.render(input));
+                None
+            }
+        }
+    }
+
+    /// evaluates a file into the session. a file whose root is a block has
+    /// each of its statements evaluated at the top level, so that its
+    /// bindings stick around afterwards.
+    pub fn load(&mut self, filename: &str) {
+        let source = match fs::read_to_string(filename) {
+            Ok(source) => source,
+            Err(error) => {
+                self.eprint(&format!(
+                    "Failed to read {}: {}\n",
+                    filename, error
+                ));
+                return;
+            }
+        };
+
+        let mut ast = match language::parse_from_string(&source) {
+            Ok(ast) => ast,
+            Err(error) => {
+                self.eprint(&EvaluationError::from(error).render(&source));
+                return;
+            }
+        };
+        if let Some(directory) = Path::new(filename).parent() {
+            language::resolve_imports(&mut ast, directory);
+        }
+
+        let statements = match ast {
+            ASTNode::Block(statements, _) => statements,
+            other => vec![other],
+        };
+        self.interpreter.source = Rc::new(Source::new(filename, &*source));
+
+        for statement in &statements {
+            if let Err(error) = self.interpreter.evaluate(statement) {
+                self.eprint(&error.render(&source));
+                return;
+            }
+        }
+
+        self.print(&format!("Loaded {}\n", filename));
+    }
+
+    /// the blocks an input creates can be called by later ones, whose errors
+    /// may then lie in it - so each input is a source of its own
+    fn enter_input(&mut self, input: &str) {
+        self.inputs += 1;
+        let name = format!("<input {}>", self.inputs);
+        self.interpreter.source = Rc::new(Source::new(name, input));
+    }
+
+    fn print_env(&mut self) {
+        let mut bindings: Vec<(String, Value)> = self
+            .interpreter
+            .env
+            .image()
+            .into_iter()
+            .filter(|(name, _)| name != "plz")
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (name, value) in bindings {
+            self.print(&format!(
+                "{}: {} ({})\n",
+                name,
+                value,
+                TypeDescriptor::of(&value)
+            ));
+        }
+    }
+
+    // output that fails to be written has nowhere else to go, so it's dropped
+
+    fn print(&mut self, text: &str) {
+        let _ = self.out.write_all(text.as_bytes());
+    }
+
+    fn eprint(&mut self, text: &str) {
+        let _ = self.err.write_all(text.as_bytes());
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".blockpipe_history"))
+}
+
+/// runs the repl until the user leaves, optionally loading a file first
+pub fn run(filename: Option<&str>, limits: Limits) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("Failed to start the repl: {}", error);
+            return;
+        }
+    };
+
+    let history = history_path();
+    if let Some(history) = &history {
+        // there's no history the first time around
+        let _ = editor.load_history(history);
+    }
+
+    let mut repl = Repl::new();
+    repl.set_limits(limits);
+    if let Some(filename) = filename {
+        repl.load(filename);
+    }
+
+    loop {
+        let prompt = if repl.is_continuing() { "... " } else { "bp> " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let Some(input) = repl.accumulate(&line) else {
+                    continue;
+                };
+
+                let _ = editor.add_history_entry(input.as_str());
+                if !repl.handle(&input) {
+                    break;
+                }
+            }
+            // ctrl-c abandons the current input, but not the session
+            Err(ReadlineError::Interrupted) => repl.abandon(),
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{}", error);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{env, fs, process};
+
+use super::*;
+
+/// what a session writes to its output and its errors, given its input one
+/// line at a time. the session ends at the end of the input or at `:quit`.
+fn session(input: &str) -> (String, String) {
+    let mut repl = Repl::with_output(vec![], vec![]);
+    for line in input.lines() {
+        if let Some(input) = repl.accumulate(line) {
+            if !repl.handle(&input) {
+                break;
+            }
+        }
+    }
+
+    (
+        String::from_utf8(repl.out).unwrap(),
+        String::from_utf8(repl.err).unwrap(),
+    )
+}
+
+#[test]
+fn test_handle_expressions() {
+    let (out, err) = session("1\n(\"a\" T)\n:type (1 2.0)");
+    assert_eq!(out, "1\n(a true)\n(Integer Float)\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn test_bindings_persist() {
+    let (out, err) = session("x: 3\ny: (x x)\ny");
+    assert_eq!(out, "(3 3)\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn test_handle_errors() {
+    let (out, err) = session("nope\n:bogus\n1");
+    assert_eq!(out, "1\n");
+    assert!(err.contains("Unbound symbol 'nope'"), "{}", err);
+    assert!(
+        err.contains("Unknown command :bogus (try :help)\n"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_quit() {
+    let (out, _) = session("1\n:quit\n2");
+    assert_eq!(out, "1\n");
+}
+
+#[test]
+fn test_env() {
+    let (out, _) = session("b: \"x\"\na: 1\n:env");
+    assert_eq!(out, "a: 1 (Integer)\nb: x (String)\n");
+}
+
+#[test]
+fn test_multiline_input() {
+    let mut repl = Repl::with_output(vec![], vec![]);
+    assert_eq!(repl.accumulate("{"), None);
+    assert!(repl.is_continuing());
+    assert_eq!(repl.accumulate("  \"a }"), None);
+    assert_eq!(
+        repl.accumulate("b\" }"),
+        Some("{\n  \"a }\nb\" }".to_string())
+    );
+    assert!(!repl.is_continuing());
+
+    // blank inputs aren't handed back at all
+    assert_eq!(repl.accumulate("   "), None);
+    assert!(!repl.is_continuing());
+
+    assert_eq!(repl.accumulate("(1"), None);
+    repl.abandon();
+    assert_eq!(repl.accumulate("2"), Some("2".to_string()));
+
+    let (out, err) = session("(1\n2\n)\n{ (\n$0) } | { 3 }");
+    assert_eq!(out, "(1 2)\n3\n");
+    assert_eq!(err, "");
+}
+
+#[test]
+fn test_load() {
+    let path = env::temp_dir()
+        .join(format!("blockpipe-repl-load-{}.blkp", process::id()));
+    fs::write(&path, "{\n  a: 1\n  double: { ($0 $0) }\n}").unwrap();
+
+    let (out, err) = session(&format!(":load {}\n2 | double", path.display()));
+    assert_eq!(out, format!("Loaded {}\n(2 2)\n", path.display()));
+    assert_eq!(err, "");
+
+    let (_, err) = session(":load /nonexistent/file.blkp");
+    assert!(err.starts_with("Failed to read /nonexistent/file.blkp"));
+}
+
+#[test]
+fn test_limits_apply_to_each_input() {
+    let mut repl = Repl::with_output(vec![], vec![]);
+    repl.set_limits(Limits {
+        max_steps: Some(8),
+        ..Limits::default()
+    });
+    for input in ["(1 2 3 4 5 6 7 8 9)", "1", "2", "3", "4", "5", "6", "7"] {
+        repl.handle(input);
+    }
+
+    let err = String::from_utf8(repl.err).unwrap();
+    assert_eq!(err.matches("limit").count(), 1, "{}", err);
+    assert_eq!(
+        String::from_utf8(repl.out).unwrap(),
+        "1\n2\n3\n4\n5\n6\n7\n"
+    );
+}
+
+#[test]
+fn test_errors_render_against_their_input() {
+    let (_, err) = session(
+        "f: { \"éééééééééééééééé\" zz }\n\"éééééééééééééééééééééééé\" | f",
+    );
+    assert_eq!(
+        err,
+        "error: Unbound symbol 'zz'\n \
+         --> <input 1>:1:25\n  \
+         |\n\
+         1 | f: { \"éééééééééééééééé\" zz }\n  \
+         |                         ^^\n  \
+         = in block `f` at 1:30\n"
+    );
+
+    let path = env::temp_dir()
+        .join(format!("blockpipe-repl-source-{}.blkp", process::id()));
+    fs::write(
+        &path,
+        "{\n  fail: { ((1 0 \"/\") \"binop_arith\") |* plz }\n}",
+    )
+    .unwrap();
+    let (_, err) = session(&format!(":load {}\n() | fail", path.display()));
+    assert!(
+        err.contains(&format!("--> {}:2:11", path.display())),
+        "{}",
+        err
+    );
+    assert!(err.contains("  = in block `fail` at 1:6\n"), "{}", err);
+}
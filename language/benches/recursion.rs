@@ -0,0 +1,77 @@
+//! times recursive programs on both engines. run with `cargo bench -p
+//! language`.
+
+use std::time::{Duration, Instant};
+
+use language::{interpret_with_engine, Engine, Value};
+
+const FACTORIAL: &str = r#"
+    {
+        if: { (($0 $1 $2) "if") |* plz }
+        leq: { (($0 $1 "<=") "binop_cmp") |* plz }
+        sub: { (($0 $1 "-") "binop_arith") |* plz }
+        mul: { (($0 $1 "*") "binop_arith") |* plz }
+
+        factorial: {
+            x: $0
+            fact_rec: rec
+            (
+                (x 1) |* leq
+                {1}
+                { (x 1) |* sub | fact_rec | {(x $0)} |* mul }
+            ) |* if
+        }
+
+        20 | factorial
+    }
+"#;
+
+const FIBONACCI: &str = r#"
+    {
+        if: { (($0 $1 $2) "if") |* plz }
+        lt: { (($0 $1 "<") "binop_cmp") |* plz }
+        add: { (($0 $1 "+") "binop_arith") |* plz }
+        sub: { (($0 $1 "-") "binop_arith") |* plz }
+
+        fib: {
+            n: $0
+            fib_rec: rec
+            (
+                (n 2) |* lt
+                { n }
+                { ((n 1) |* sub | fib_rec  (n 2) |* sub | fib_rec) |* add }
+            ) |* if
+        }
+
+        12 | fib
+    }
+"#;
+
+fn time(engine: Engine, source: &str, iterations: u32) -> (Duration, Value) {
+    let start = Instant::now();
+    let mut result = None;
+    for _ in 0..iterations {
+        result = Some(interpret_with_engine(engine, source, None, None, true));
+    }
+    let value = result.unwrap().expect("benchmark program failed");
+    (start.elapsed() / iterations, value)
+}
+
+fn main() {
+    for (name, source, iterations) in [
+        ("factorial 20", FACTORIAL, 200),
+        ("fibonacci 12", FIBONACCI, 5),
+    ] {
+        let (tree, expected) = time(Engine::TreeWalking, source, iterations);
+        let (bytecode, value) = time(Engine::Bytecode, source, iterations);
+        assert_eq!(value, expected, "{} differs between the engines", name);
+
+        println!(
+            "{:<14} tree walking {:>10.3?}   bytecode {:>10.3?}   {:.1}x",
+            name,
+            tree,
+            bytecode,
+            tree.as_secs_f64() / bytecode.as_secs_f64()
+        );
+    }
+}
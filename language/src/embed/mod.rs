@@ -0,0 +1,194 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::interpreter::{
+    run_root_block, ErrorKind, EvaluateResult, EvaluationError, Interpreter,
+    Limits, Meter, ProgramState, RuntimeCall, RuntimeRegistry, SharedProgram,
+    Value,
+};
+use crate::parser::ASTNode;
+use crate::{
+    parse_from_string, toplevel_interpreter, toplevel_machine, Engine,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// runs BlockPipe programs on behalf of a host application. the host picks
+/// the engine they run on and the limits they run under, the runtime calls
+/// they can make through `plz` (its own functions among them), and values to
+/// bind globally, under names every program can use.
+///
+/// neither it nor the programs it loads can be sent to another thread: values
+/// share their parts through `Rc`, and so do the host's runtime calls. a host
+/// that runs programs on several threads sets up a BlockPipe on each.
+#[derive(Debug, Default)]
+pub struct BlockPipe {
+    engine: Engine,
+    limits: Limits,
+    runtime: RuntimeRegistry,
+    globals: Vec<(String, Value)>,
+}
+
+impl BlockPipe {
+    pub fn new() -> BlockPipe {
+        BlockPipe::default()
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// caps what each program may use, including every call made into it
+    /// once it's loaded
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// replaces the runtime calls programs can make, which are the builtin
+    /// ones by default
+    pub fn set_runtime(&mut self, runtime: RuntimeRegistry) {
+        self.runtime = runtime;
+    }
+
+    /// lets programs make the given call through `plz`, replacing any of the
+    /// same name
+    pub fn register(&mut self, call: RuntimeCall) {
+        self.runtime.register(call);
+    }
+
+    /// binds a value globally, replacing any binding of the same name
+    pub fn bind(&mut self, name: &str, value: impl Into<Value>) {
+        let value = value.into();
+        match self.globals.iter_mut().find(|(bound, _)| bound == name) {
+            Some((_, bound)) => *bound = value,
+            None => self.globals.push((name.to_string(), value)),
+        }
+    }
+
+    /// evaluate
+    ///
+    /// Evaluates the source and gives back what it comes to. A program
+    /// whose root is a block comes to a closure, which isn't run - use
+    /// [BlockPipe::load] to run it.
+    pub fn evaluate(&self, source: &str) -> EvaluateResult {
+        let root = parse_from_string(source)?;
+        let state = self.state();
+
+        match self.engine {
+            Engine::TreeWalking => {
+                let mut interpreter = toplevel_interpreter(root);
+                interpreter.program = state;
+                for (name, value) in &self.globals {
+                    interpreter.env.bind(name.clone(), value.clone());
+                }
+                interpreter.evaluate_from_root(None)
+            }
+            Engine::Bytecode => {
+                let mut machine = toplevel_machine(root);
+                machine.program = state;
+                for (name, value) in &self.globals {
+                    machine.env.bind(name.clone(), value.clone());
+                }
+                machine.evaluate_from_root(None)
+            }
+        }
+    }
+
+    /// load
+    ///
+    /// Runs the root block of the source, the way an import would, and
+    /// gives back the program with every binding it made - so that the
+    /// blocks it defines can be called from the host.
+    pub fn load(&self, source: &str) -> Result<Program, EvaluationError> {
+        let root = parse_from_string(source)?;
+        let statements = match &root {
+            ASTNode::Block(statements, _) => statements.clone(),
+            other => {
+                return Err(EvaluationError::new(ErrorKind::TypeMismatch(
+                    "The root of a program must be a block".to_string(),
+                ))
+                .with_span(other.span()))
+            }
+        };
+
+        // the host renders errors against the source it loaded, so that's
+        // where spans in no other source lie
+        let state = self.state();
+        let source = Rc::default();
+        let bindings =
+            run_root_block(&state, root, &statements, &self.globals, &source)?;
+        Ok(Program { bindings, state })
+    }
+
+    /// call_value
+    ///
+    /// Pipes the given arguments into a closure, such as one a program
+    /// evaluated to, and gives back what it comes to. The call runs under
+    /// this BlockPipe's limits and runtime calls, and sees the globals only
+    /// if the closure captured them.
+    pub fn call_value(
+        &self,
+        closure: &Value,
+        arguments: Vec<Value>,
+    ) -> EvaluateResult {
+        Interpreter::execute_closure_with(
+            &self.state(),
+            arguments.into(),
+            closure,
+        )
+    }
+
+    /// the state a program starts out with
+    fn state(&self) -> SharedProgram {
+        let mut state = ProgramState::with_engine(self.engine);
+        state.meter = Meter::new(self.limits);
+        state.runtime = self.runtime.clone();
+        Rc::new(RefCell::new(state))
+    }
+}
+
+/// a program loaded by [BlockPipe::load], along with the bindings its root
+/// block made. like the BlockPipe that loaded it, it stays on the thread it
+/// was loaded on.
+#[derive(Debug)]
+pub struct Program {
+    bindings: HashMap<String, Value>,
+    // shared by every call into the program, so they're metered together
+    state: SharedProgram,
+}
+
+impl Program {
+    /// the value bound to the given name by the program's root block, or
+    /// globally
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.bindings.get(name)
+    }
+
+    /// call
+    ///
+    /// Pipes the given arguments into the block bound to the given name,
+    /// and gives back what it comes to.
+    pub fn call(&self, name: &str, arguments: Vec<Value>) -> EvaluateResult {
+        let block = self
+            .get(name)
+            .ok_or_else(|| ErrorKind::UnboundSymbol(name.to_string()))?;
+        self.call_value(block, arguments)
+    }
+
+    /// call_value
+    ///
+    /// Pipes the given arguments into a closure, such as one a block of the
+    /// program gave back, and gives back what it comes to. It's metered
+    /// along with every other call into the program.
+    pub fn call_value(
+        &self,
+        closure: &Value,
+        arguments: Vec<Value>,
+    ) -> EvaluateResult {
+        Interpreter::execute_closure_with(
+            &self.state,
+            arguments.into(),
+            closure,
+        )
+    }
+}
@@ -0,0 +1,197 @@
+use super::*;
+use crate::parser::Pattern;
+use crate::TypeDescriptor;
+
+const ENGINES: [Engine; 2] = [Engine::TreeWalking, Engine::Bytecode];
+
+fn blockpipe(engine: Engine) -> BlockPipe {
+    let mut blockpipe = BlockPipe::new();
+    blockpipe.set_engine(engine);
+    blockpipe
+}
+
+#[test]
+fn test_conversions() {
+    assert_eq!(Value::from(3), Value::Integer(3));
+    assert_eq!(Value::from("a"), Value::String("a".to_string()));
+    assert_eq!(Value::from(()), Value::Tuple(vec![]));
+    assert_eq!(
+        Value::from(vec![Value::from(true), Value::from(1.5)]),
+        Value::Tuple(vec![Value::Boolean(true), Value::Float(1.5)])
+    );
+
+    assert_eq!(i64::try_from(Value::Integer(3)), Ok(3));
+    assert_eq!(String::try_from(Value::from("a")), Ok("a".to_string()));
+    assert_eq!(
+        Vec::<(String, Value)>::try_from(Value::Record(vec![(
+            "x".to_string(),
+            Value::Integer(1)
+        )])),
+        Ok(vec![("x".to_string(), Value::Integer(1))])
+    );
+    assert_eq!(
+        bool::try_from(Value::Integer(1)),
+        Err(ErrorKind::AnnotationMismatch {
+            expected: Pattern::Type("Boolean".to_string()),
+            found: TypeDescriptor::Integer,
+        })
+    );
+}
+
+#[test]
+fn test_evaluate_with_globals() {
+    for engine in ENGINES {
+        let mut blockpipe = blockpipe(engine);
+        blockpipe.bind("greeting", "hi ");
+        blockpipe.bind("name", "bob");
+        blockpipe.bind("name", "alice");
+
+        assert_eq!(
+            blockpipe.evaluate(r#"((greeting name) "strcat") |* plz"#),
+            Ok(Value::from("hi alice"))
+        );
+    }
+}
+
+#[test]
+fn test_host_functions() {
+    for engine in ENGINES {
+        let mut blockpipe = blockpipe(engine);
+        blockpipe.register(
+            RuntimeCall::new(
+                "double",
+                vec![Pattern::Type("Integer".to_string())],
+                |parameters| {
+                    let n = i64::try_from(parameters[0].clone())?;
+                    Ok(Value::from(n * 2))
+                },
+            )
+            .returning(Pattern::Type("Integer".to_string())),
+        );
+
+        assert_eq!(
+            blockpipe.evaluate(r#"((21) "double") |* plz"#),
+            Ok(Value::Integer(42))
+        );
+        assert_eq!(
+            blockpipe
+                .evaluate(r#"(("x") "double") |* plz"#)
+                .unwrap_err()
+                .kind,
+            ErrorKind::AnnotationMismatch {
+                expected: Pattern::Type("Integer".to_string()),
+                found: TypeDescriptor::String,
+            }
+        );
+
+        // leaving the builtins out keeps programs from making them
+        blockpipe.set_runtime(RuntimeRegistry::empty());
+        assert_eq!(
+            blockpipe
+                .evaluate(r#"(("x") "print") |* plz"#)
+                .unwrap_err()
+                .kind,
+            ErrorKind::UnknownRuntimeCall("print".to_string())
+        );
+    }
+}
+
+#[test]
+fn test_call_named_block() {
+    for engine in ENGINES {
+        let mut blockpipe = blockpipe(engine);
+        blockpipe.bind("offset", 10);
+        let program = blockpipe
+            .load(
+                r#"{
+                    add: { |a b| ((a b "+") "binop_arith") |* plz }
+                    shift: { |x| (x offset) |* add }
+                    limit: 100
+                }"#,
+            )
+            .unwrap();
+
+        assert_eq!(
+            program.call("shift", vec![Value::from(5)]),
+            Ok(Value::Integer(15))
+        );
+        assert_eq!(program.get("limit"), Some(&Value::Integer(100)));
+        assert_eq!(
+            program.call("missing", vec![]).unwrap_err().kind,
+            ErrorKind::UnboundSymbol("missing".to_string())
+        );
+        assert_eq!(
+            program.call("add", vec![Value::from(1)]).unwrap_err().kind,
+            ErrorKind::ArityMismatch {
+                call: "Block".to_string(),
+                expected: 2,
+                found: 1,
+            }
+        );
+    }
+}
+
+#[test]
+fn test_call_closure_value() {
+    for engine in ENGINES {
+        let mut blockpipe = blockpipe(engine);
+        blockpipe.bind("offset", 10);
+        let shift = blockpipe
+            .evaluate(r#"{ |x| ((x offset "+") "binop_arith") |* plz }"#)
+            .unwrap();
+        assert_eq!(
+            blockpipe.call_value(&shift, vec![Value::from(5)]),
+            Ok(Value::Integer(15))
+        );
+
+        let program = blockpipe
+            .load(
+                r#"{
+                    adder: { |a| { |b| ((a b "+") "binop_arith") |* plz } }
+                }"#,
+            )
+            .unwrap();
+        let add_two = program.call("adder", vec![Value::from(2)]).unwrap();
+        assert_eq!(
+            program.call_value(&add_two, vec![Value::from(3)]),
+            Ok(Value::Integer(5))
+        );
+        assert_eq!(
+            program
+                .call_value(&Value::from(1), vec![])
+                .unwrap_err()
+                .kind,
+            ErrorKind::TypeMismatch(
+                "Cannot pipe into a value of type Integer".to_string()
+            )
+        );
+    }
+}
+
+#[test]
+fn test_load_non_block() {
+    assert_eq!(
+        BlockPipe::new().load("1").unwrap_err().kind,
+        ErrorKind::TypeMismatch(
+            "The root of a program must be a block".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_limits_span_calls() {
+    let mut blockpipe = BlockPipe::new();
+    blockpipe.set_limits(Limits {
+        max_steps: Some(200),
+        ..Limits::default()
+    });
+    let program = blockpipe
+        .load(r#"{ add: { ((1 2 "+") "binop_arith") |* plz } }"#)
+        .unwrap();
+
+    let error = (0..100)
+        .map(|_| program.call("add", vec![]))
+        .find_map(Result::err)
+        .unwrap();
+    assert!(matches!(error.kind, ErrorKind::LimitExceeded { .. }));
+}
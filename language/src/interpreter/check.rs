@@ -0,0 +1,1156 @@
+use core::fmt;
+use std::{collections::HashMap, ptr, rc::Rc};
+
+use logos::Span;
+
+use super::interp_runtime::{
+    arity_mismatch, check_binop_arith, check_binop_cmp, check_json_parse,
+    check_json_stringify, check_strcat, type_mismatch,
+};
+use super::shape::unindexable;
+use super::{
+    captures, Arguments, Elements, ErrorKind, EvaluationError, Interpreter,
+    RuntimeCall, RuntimeRegistry, Shape, TypeDescriptor,
+};
+use crate::parser::{
+    ASTNode, Accessor, Arm, LiteralVariant, Parameters, Pattern, PipeType,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// what the checker knows about a value ahead of time. anything it can't work
+/// out is `Unknown`, which is never reported as a mismatch.
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, PartialEq, Clone)]
+enum Type<'a> {
+    Unknown,
+    Integer,
+    Float,
+    // along with its contents, when they're known - as they are for the names
+    // of runtime calls and operations
+    String(Option<String>),
+    Boolean,
+    Tuple(Vec<Type<'a>>),
+    Record(Vec<(String, Type<'a>)>),
+    Block(Rc<Closure<'a>>),
+    RuntimeInvocation,
+    Type,
+}
+
+/// a block, along with everything that was in scope where it was created
+#[derive(Debug)]
+struct Closure<'a> {
+    statements: &'a [ASTNode],
+    captured: Rc<Locals<'a>>,
+}
+
+impl PartialEq for Closure<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.statements, other.statements)
+            && self.captured == other.captured
+    }
+}
+
+impl<'a> Type<'a> {
+    fn of_literal(literal: &LiteralVariant) -> Type<'a> {
+        match literal {
+            LiteralVariant::IntegerLiteral(_) => Type::Integer,
+            LiteralVariant::FloatLiteral(_) => Type::Float,
+            LiteralVariant::StringLiteral(s) => Type::String(Some(s.clone())),
+            LiteralVariant::BooleanLiteral(_) => Type::Boolean,
+        }
+    }
+
+    /// a type that values of either type have
+    fn join(self, other: Type<'a>) -> Type<'a> {
+        match (self, other) {
+            (Type::Tuple(left), Type::Tuple(right))
+                if left.len() == right.len() =>
+            {
+                Type::Tuple(
+                    left.into_iter()
+                        .zip(right)
+                        .map(|(l, r)| l.join(r))
+                        .collect(),
+                )
+            }
+            (Type::Record(left), Type::Record(right))
+                if left
+                    .iter()
+                    .map(|(label, _)| label)
+                    .eq(right.iter().map(|(label, _)| label)) =>
+            {
+                Type::Record(
+                    left.into_iter()
+                        .zip(right)
+                        .map(|((label, l), (_, r))| (label, l.join(r)))
+                        .collect(),
+                )
+            }
+            (Type::String(left), Type::String(right)) => {
+                Type::String(left.filter(|left| Some(left) == right.as_ref()))
+            }
+            (left, right) if left == right => left,
+            _ => Type::Unknown,
+        }
+    }
+
+    /// the type of a field, element or slice of a value of this type, or the
+    /// error accessing it is sure to raise. mirrors [Value::access].
+    ///
+    /// [Value::access]: super::Value::access
+    fn access(&self, accessor: &Accessor) -> Result<Type<'a>, ErrorKind> {
+        match (self, accessor) {
+            (Type::Unknown, _) => Ok(Type::Unknown),
+            // what a block captured isn't tracked
+            (Type::Block(_), Accessor::Field(_)) => Ok(Type::Unknown),
+            (Type::Record(fields), Accessor::Field(label)) => fields
+                .iter()
+                .find(|(field, _)| field == label)
+                .map(|(_, ty)| ty.clone())
+                .ok_or_else(|| missing_field(label, self)),
+            (_, Accessor::Field(label)) => Err(missing_field(label, self)),
+            _ => match self.elements() {
+                Some(elements) => elements.access(accessor),
+                None => Err(unindexable(self)),
+            },
+        }
+    }
+
+    /// what's known about a value of this type once it's been found to have
+    /// the type it was annotated with. records only need to have the fields
+    /// an annotation mentions, so which others they have is still unknown.
+    fn refine(self, annotation: &Pattern) -> Type<'a> {
+        match (annotation, self) {
+            (Pattern::Type(name), Type::Unknown) => match name.as_str() {
+                "Integer" => Type::Integer,
+                "Float" => Type::Float,
+                "String" => Type::String(None),
+                "Boolean" => Type::Boolean,
+                "RuntimeInvocation" => Type::RuntimeInvocation,
+                "Type" => Type::Type,
+                _ => Type::Unknown,
+            },
+            (Pattern::Tuple(patterns), Type::Unknown) => Type::Tuple(
+                patterns
+                    .iter()
+                    .map(|pattern| Type::Unknown.refine(pattern))
+                    .collect(),
+            ),
+            (Pattern::Tuple(patterns), Type::Tuple(elements))
+                if patterns.len() == elements.len() =>
+            {
+                Type::Tuple(
+                    elements
+                        .into_iter()
+                        .zip(patterns)
+                        .map(|(ty, pattern)| ty.refine(pattern))
+                        .collect(),
+                )
+            }
+            (Pattern::Record(patterns), Type::Record(fields)) => Type::Record(
+                fields
+                    .into_iter()
+                    .map(|(label, ty)| {
+                        let pattern =
+                            patterns.iter().find(|(field, _)| *field == label);
+                        let ty = match pattern {
+                            Some((_, pattern)) => ty.refine(pattern),
+                            None => ty,
+                        };
+                        (label, ty)
+                    })
+                    .collect(),
+            ),
+            (_, ty) => ty,
+        }
+    }
+}
+
+impl<'a> Shape for Type<'a> {
+    fn tuple(elements: Vec<Type<'a>>) -> Type<'a> {
+        Type::Tuple(elements)
+    }
+
+    fn record(fields: Vec<(String, Type<'a>)>) -> Type<'a> {
+        Type::Record(fields)
+    }
+
+    fn count(_: usize) -> Type<'a> {
+        Type::Integer
+    }
+
+    fn type_name(&self) -> Option<&'static str> {
+        type_name(self)
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Type::String(text) => text.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn descriptor(&self) -> Option<TypeDescriptor> {
+        Some(match self {
+            Type::Unknown => return None,
+            Type::Integer => TypeDescriptor::Integer,
+            Type::Float => TypeDescriptor::Float,
+            Type::String(_) => TypeDescriptor::String,
+            Type::Boolean => TypeDescriptor::Boolean,
+            Type::Tuple(elements) => TypeDescriptor::Tuple(
+                elements
+                    .iter()
+                    .map(Type::descriptor)
+                    .collect::<Option<_>>()?,
+            ),
+            Type::Record(fields) => TypeDescriptor::Record(
+                fields
+                    .iter()
+                    .map(|(label, ty)| Some((label.clone(), ty.descriptor()?)))
+                    .collect::<Option<_>>()?,
+            ),
+            Type::Block(_) => TypeDescriptor::Closure,
+            Type::RuntimeInvocation => TypeDescriptor::RuntimeInvocation,
+            Type::Type => TypeDescriptor::Type,
+        })
+    }
+
+    fn conform(&self, annotation: &Pattern) -> Result<(), ErrorKind> {
+        if capture(annotation, self, &mut Arguments::default()) {
+            Ok(())
+        } else {
+            Err(annotation_mismatch(annotation, self))
+        }
+    }
+
+    fn elements(&self) -> Option<Elements<'_, Type<'a>>> {
+        match self {
+            Type::Tuple(elements) => Some(Elements::Tuple(elements)),
+            Type::Record(fields) => Some(Elements::Record(fields)),
+            _ => None,
+        }
+    }
+
+    fn destructure(self) -> Result<Arguments<Type<'a>>, Type<'a>> {
+        match self {
+            Type::Tuple(values) => Ok(values.into()),
+            Type::Record(fields) => {
+                let (labels, values) = fields.into_iter().unzip();
+                Ok(Arguments { values, labels })
+            }
+            other => Err(other),
+        }
+    }
+}
+
+/// the type annotation on the output of a block, if it has one
+fn output(statements: &[ASTNode]) -> Option<&(Pattern, Span)> {
+    match statements.first() {
+        Some(ASTNode::Parameters(parameters, _)) => parameters.output.as_ref(),
+        _ => None,
+    }
+}
+
+/// the name a type pattern uses for values of the given type
+fn type_name(ty: &Type) -> Option<&'static str> {
+    Some(match ty {
+        Type::Unknown => return None,
+        Type::Integer => "Integer",
+        Type::Float => "Float",
+        Type::String(_) => "String",
+        Type::Boolean => "Boolean",
+        Type::Tuple(_) => "Tuple",
+        Type::Record(_) => "Record",
+        Type::Block(_) => "Closure",
+        Type::RuntimeInvocation => "RuntimeInvocation",
+        Type::Type => "Type",
+    })
+}
+
+/// the error for a field missing from a value of the given type
+fn missing_field(field: &str, ty: &Type) -> ErrorKind {
+    match ty.descriptor() {
+        Some(found) => ErrorKind::MissingField {
+            field: field.to_string(),
+            found,
+        },
+        None => ErrorKind::TypeMismatch(format!(
+            "No field '{}' in value of type {}",
+            field, ty
+        )),
+    }
+}
+
+/// what a block is invoked with, as far as the checker knows - `None` when
+/// not even the number of arguments is known
+#[derive(Debug, PartialEq, Clone)]
+struct Given<'a>(Option<Arguments<Type<'a>>>);
+
+impl<'a> Given<'a> {
+    fn unknown() -> Self {
+        Given(None)
+    }
+
+    fn known(values: Vec<Type<'a>>) -> Self {
+        Given(Some(values.into()))
+    }
+
+    /// what a pipe passes to the block it pipes into
+    fn piped(input: Type<'a>, destructure: bool) -> Result<Self, ErrorKind> {
+        match input {
+            Type::Unknown if destructure => Ok(Given::unknown()),
+            input => Arguments::piped(input, destructure).map(Some).map(Given),
+        }
+    }
+
+    /// what an arm's blocks are given when its pattern matches a value of the
+    /// given type, or `None` if it can't match any
+    fn captured(pattern: &Pattern, ty: &Type<'a>) -> Option<Self> {
+        let mut captured = Arguments::default();
+        capture(pattern, ty, &mut captured).then_some(Given(Some(captured)))
+    }
+
+    /// the types of the values given, if even how many there are is known
+    fn values(&self) -> Option<&[Type<'a>]> {
+        self.0.as_ref().map(|arguments| arguments.values.as_slice())
+    }
+
+    /// the type of a parameter, if the block is given it
+    fn parameter(&self, name: &str) -> Option<Type<'a>> {
+        match &self.0 {
+            Some(arguments) => arguments.parameter(name),
+            None if name == "$n" => Some(Type::Integer),
+            None => name.starts_with('$').then_some(Type::Unknown),
+        }
+    }
+
+    /// the type of each parameter a block declares, or the error its
+    /// invocation is sure to raise
+    fn declare(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Vec<(String, Type<'a>)>, ErrorKind> {
+        match &self.0 {
+            Some(arguments) => arguments.assign(parameters),
+            None => Ok(parameters
+                .declared()
+                .map(|(name, _)| (name.clone(), Type::Unknown))
+                .collect()),
+        }
+    }
+}
+
+/// whether a pattern could match a value of the given type, noting the types
+/// of what it would capture
+fn capture<'a>(
+    pattern: &Pattern,
+    ty: &Type<'a>,
+    captured: &mut Arguments<Type<'a>>,
+) -> bool {
+    match (pattern, ty) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Capture(name), ty) => {
+            captured.values.push(ty.clone());
+            captured.labels.push(name.clone());
+            true
+        }
+        (
+            Pattern::Literal(LiteralVariant::StringLiteral(literal)),
+            Type::String(Some(known)),
+        ) => literal == known,
+        (Pattern::Literal(literal), ty) => type_name(ty).is_none_or(|name| {
+            type_name(&Type::of_literal(literal)) == Some(name)
+        }),
+        (Pattern::Type(name), ty) => type_name(ty).is_none_or(|ty| ty == name),
+        (Pattern::Tuple(patterns), Type::Tuple(types)) => {
+            patterns.len() == types.len()
+                && patterns
+                    .iter()
+                    .zip(types)
+                    .all(|(pattern, ty)| capture(pattern, ty, captured))
+        }
+        (Pattern::Record(patterns), Type::Record(fields)) => {
+            patterns.iter().all(|(label, pattern)| {
+                fields
+                    .iter()
+                    .find(|(field, _)| field == label)
+                    .is_some_and(|(_, ty)| capture(pattern, ty, captured))
+            })
+        }
+        (Pattern::Tuple(patterns), Type::Unknown) => patterns
+            .iter()
+            .all(|pattern| capture(pattern, &Type::Unknown, captured)),
+        (Pattern::Record(patterns), Type::Unknown) => patterns
+            .iter()
+            .all(|(_, pattern)| capture(pattern, &Type::Unknown, captured)),
+        (Pattern::Tuple(_) | Pattern::Record(_), _) => false,
+    }
+}
+
+/// the names in scope while checking a block (or the top level): what the
+/// block was given, what it bound itself, and what was in scope where it
+/// was created
+#[derive(Debug, PartialEq, Clone)]
+struct Locals<'a> {
+    given: Given<'a>,
+    bound: HashMap<String, Type<'a>>,
+    captured: Option<Rc<Locals<'a>>>,
+    // whether something of unknown type was pasted, which could have bound
+    // any name at all
+    opaque: bool,
+}
+
+impl<'a> Locals<'a> {
+    /// the type of a name, or `None` if it isn't bound. `$0`, `$1`, ... are
+    /// taken from where the block was created when it isn't given them.
+    fn lookup(&self, name: &str) -> Option<Type<'a>> {
+        if let Some(ty) = self.given.parameter(name) {
+            return Some(ty);
+        }
+        if let Some(ty) = self.bound.get(name) {
+            return Some(ty.clone());
+        }
+        if self.opaque && !name.starts_with('$') {
+            return Some(Type::Unknown);
+        }
+        self.captured.as_ref()?.lookup(name)
+    }
+
+    /// whether a parameter is known to be in scope, given to the block or to
+    /// one it was created in. blocks given who knows how many arguments
+    /// aren't counted on to have been given it.
+    fn has_parameter(&self, name: &str) -> bool {
+        self.given.values().is_some() && self.given.parameter(name).is_some()
+            || self.bound.contains_key(name)
+            || self
+                .captured
+                .as_ref()
+                .is_some_and(|captured| captured.has_parameter(name))
+    }
+}
+
+/// the positional parameters that a block's body uses itself, rather than in
+/// the blocks within it, which run whenever the block does
+fn positional(node: &ASTNode, used: &mut Vec<usize>) {
+    match node {
+        ASTNode::Identifier(name, _) => {
+            if let Some(index) =
+                name.strip_prefix('$').and_then(|index| index.parse().ok())
+            {
+                used.push(index);
+            }
+        }
+        ASTNode::Tuple(nodes, _) | ASTNode::Pipe(nodes, _, _) => {
+            for node in nodes {
+                positional(node, used);
+            }
+        }
+        ASTNode::Record(fields, _) => {
+            for (_, value) in fields {
+                positional(value, used);
+            }
+        }
+        ASTNode::Binding((_, operand), _)
+        | ASTNode::Annotated(operand, _, _)
+        | ASTNode::Type(operand, _)
+        | ASTNode::Paste(operand, _)
+        | ASTNode::Access(operand, _, _)
+        | ASTNode::Match(operand, _, _) => positional(operand, used),
+        ASTNode::Block(..)
+        | ASTNode::Parameters(..)
+        | ASTNode::Literal(..)
+        | ASTNode::Import(..)
+        | ASTNode::Error(_) => {}
+    }
+}
+
+struct Checker<'a> {
+    // the runtime calls the program can make
+    runtime: RuntimeRegistry,
+    errors: Vec<EvaluationError>,
+    // the blocks being checked, innermost last
+    checking: Vec<&'a [ASTNode]>,
+    // what checking each invocation so far came to
+    checked: Vec<(Rc<Closure<'a>>, Given<'a>, Type<'a>)>,
+    // every block created along the way
+    created: Vec<Rc<Closure<'a>>>,
+}
+
+/// check
+///
+/// Looks for the type errors a program could run into, without running it.
+/// The checker is conservative: it follows every path through the program,
+/// whether or not it's taken - both branches of an `if`, each arm that could
+/// match, blocks that are never invoked - so an error is reported if the
+/// program raises it whenever that path runs. The types of bindings, tuples and records are inferred from the
+/// values they're made of, while blocks are checked as invoked with the
+/// arguments they're piped - so what a block comes to depends on what it's
+/// given, and blocks that are never invoked are checked with arguments of
+/// unknown type. Nothing is reported about values whose type can't be
+/// worked out ahead of time, like those of imports or of recursive calls.
+/// Pipes into `plz` are checked against the calls in the given registry.
+pub fn check(
+    root: &ASTNode,
+    runtime: &RuntimeRegistry,
+) -> Vec<EvaluationError> {
+    let mut checker = Checker {
+        runtime: runtime.clone(),
+        errors: vec![],
+        checking: vec![],
+        checked: vec![],
+        created: vec![],
+    };
+    let mut locals = Locals {
+        given: Given::unknown(),
+        bound: HashMap::from([("plz".to_string(), Type::RuntimeInvocation)]),
+        captured: None,
+        opaque: false,
+    };
+    checker.expression(root, &mut locals);
+
+    while let Some(closure) = checker.uninvoked() {
+        checker.invoke(&closure, Given::unknown());
+    }
+
+    // a block invoked in different ways can run into the same error more
+    // than once, which is only reported the first time
+    let mut errors: Vec<EvaluationError> = vec![];
+    for error in checker.errors {
+        if !errors.iter().any(|existing| {
+            existing.kind == error.kind && existing.span == error.span
+        }) {
+            errors.push(error);
+        }
+    }
+    errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+    errors
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, kind: ErrorKind, span: &Span) {
+        self.errors
+            .push(EvaluationError::new(kind).with_span(span.clone()));
+    }
+
+    /// checks an invocation, recording that the errors found in it
+    /// propagated out of the given callee
+    fn within<T>(
+        &mut self,
+        callee: &str,
+        span: Span,
+        check: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let outer = std::mem::take(&mut self.errors);
+        let result = check(self);
+        let inner = std::mem::replace(&mut self.errors, outer);
+        self.errors.extend(
+            inner
+                .into_iter()
+                .map(|error| error.in_call(callee, Some(span.clone()))),
+        );
+        result
+    }
+
+    /// a block that was created but hasn't been checked yet
+    fn uninvoked(&self) -> Option<Rc<Closure<'a>>> {
+        self.created
+            .iter()
+            .find(|closure| {
+                !self.checked.iter().any(|(checked, _, _)| {
+                    ptr::eq(checked.statements, closure.statements)
+                })
+            })
+            .cloned()
+    }
+
+    fn expression(
+        &mut self,
+        node: &'a ASTNode,
+        locals: &mut Locals<'a>,
+    ) -> Type<'a> {
+        match node {
+            ASTNode::Literal(literal, _) => Type::of_literal(literal),
+            ASTNode::Tuple(elements, span) => {
+                self.tuple(elements, span, locals)
+            }
+            ASTNode::Record(fields, _) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(label, node)| {
+                        (label.clone(), self.expression(node, locals))
+                    })
+                    .collect(),
+            ),
+            // names that aren't bound are left to the resolver to report
+            ASTNode::Identifier(name, _) => {
+                locals.lookup(name).unwrap_or(Type::Unknown)
+            }
+            ASTNode::Access(operand, accessor, span) => {
+                let ty = self.expression(operand, locals);
+                ty.access(accessor).unwrap_or_else(|kind| {
+                    self.report(kind, span);
+                    Type::Unknown
+                })
+            }
+            ASTNode::Block(statements, _) => {
+                let closure = Rc::new(Closure {
+                    statements,
+                    captured: Rc::new(locals.clone()),
+                });
+                self.created.push(closure.clone());
+                Type::Block(closure)
+            }
+            ASTNode::Binding((name, value), _) => {
+                let ty = self.expression(value, locals);
+                locals.bound.insert(name.clone(), ty);
+                Type::Tuple(vec![])
+            }
+            ASTNode::Annotated(value, annotation, span) => {
+                let ty = self.expression(value, locals);
+                self.conform(annotation, ty, span)
+            }
+            ASTNode::Parameters(declared, span) => {
+                self.declare(declared, span, locals)
+            }
+            ASTNode::Pipe(stages, pipe_types, span) => {
+                self.pipe(stages, pipe_types, span, locals)
+            }
+            ASTNode::Match(subject, arms, span) => {
+                self.matching(subject, arms, span, locals)
+            }
+            ASTNode::Type(operand, _) => {
+                self.expression(operand, locals);
+                Type::Type
+            }
+            ASTNode::Paste(operand, span) => self
+                .paste(operand, span, locals)
+                .map_or(Type::Unknown, |mut spliced| {
+                    spliced.pop().unwrap_or(Type::Tuple(vec![]))
+                }),
+            ASTNode::Import(..) | ASTNode::Error(_) => Type::Unknown,
+        }
+    }
+
+    /// a tuple's type is only known if the number of its elements is
+    fn tuple(
+        &mut self,
+        elements: &'a [ASTNode],
+        span: &Span,
+        locals: &mut Locals<'a>,
+    ) -> Type<'a> {
+        let mut types = Some(vec![]);
+        for node in elements {
+            let spliced = match node {
+                ASTNode::Paste(operand, _) => self.paste(operand, span, locals),
+                _ => Some(vec![self.expression(node, locals)]),
+            };
+            types = types.zip(spliced).map(|(mut types, spliced)| {
+                types.extend(spliced);
+                types
+            });
+        }
+        types.map_or(Type::Unknown, Type::Tuple)
+    }
+
+    /// the types of the values a paste splices in, or `None` if not even how
+    /// many there are is known. pasted blocks bring the names they captured
+    /// into scope instead.
+    fn paste(
+        &mut self,
+        operand: &'a ASTNode,
+        span: &Span,
+        locals: &mut Locals<'a>,
+    ) -> Option<Vec<Type<'a>>> {
+        let pasted = match operand {
+            ASTNode::Tuple(elements, _) => elements.as_slice(),
+            _ => std::slice::from_ref(operand),
+        };
+
+        let mut spliced = Some(vec![]);
+        for node in pasted {
+            match self.expression(node, locals) {
+                Type::Tuple(values) => {
+                    if let Some(spliced) = &mut spliced {
+                        spliced.extend(values);
+                    }
+                }
+                Type::Record(fields) => {
+                    if let Some(spliced) = &mut spliced {
+                        spliced.extend(fields.into_iter().map(|(_, ty)| ty));
+                    }
+                }
+                Type::Block(closure) => {
+                    for name in captures(closure.statements) {
+                        if name.starts_with('$') || name == "rec" {
+                            continue;
+                        }
+                        if let Some(ty) = closure.captured.lookup(&name) {
+                            locals.bound.insert(name, ty);
+                        }
+                    }
+                }
+                Type::Unknown => {
+                    for (name, ty) in locals.bound.iter_mut() {
+                        if name != "rec" {
+                            *ty = Type::Unknown;
+                        }
+                    }
+                    locals.opaque = true;
+                    spliced = None;
+                }
+                other => {
+                    self.report(
+                        ErrorKind::TypeMismatch(format!(
+                            "Can only paste tuples and closures, not {}",
+                            other
+                        )),
+                        span,
+                    );
+                    return None;
+                }
+            }
+        }
+
+        spliced
+    }
+
+    /// checks a value of the given type against the type it's annotated
+    /// with, giving what's known about the value once it has passed
+    fn conform(
+        &mut self,
+        annotation: &Pattern,
+        ty: Type<'a>,
+        span: &Span,
+    ) -> Type<'a> {
+        match ty.conform(annotation) {
+            Ok(()) => ty.refine(annotation),
+            Err(kind) => {
+                self.report(kind, span);
+                Type::Unknown.refine(annotation)
+            }
+        }
+    }
+
+    /// binds the parameters a block declares, as given, and as they're
+    /// annotated
+    fn declare(
+        &mut self,
+        declared: &Parameters,
+        span: &Span,
+        locals: &mut Locals<'a>,
+    ) -> Type<'a> {
+        let types = locals.given.declare(declared).unwrap_or_else(|kind| {
+            self.report(kind, span);
+            Given::unknown()
+                .declare(declared)
+                .expect("unknown arguments suit any parameters")
+        });
+        for ((name, ty), (_, span)) in
+            types.into_iter().zip(declared.declared())
+        {
+            let ty = match declared.annotation(&name) {
+                Some(annotation) => self.conform(annotation, ty, span),
+                None => ty,
+            };
+            locals.bound.insert(name, ty);
+        }
+        Type::Tuple(vec![])
+    }
+
+    fn pipe(
+        &mut self,
+        stages: &'a [ASTNode],
+        pipe_types: &[PipeType],
+        span: &Span,
+        locals: &mut Locals<'a>,
+    ) -> Type<'a> {
+        let mut current = self.expression(&stages[0], locals);
+
+        for (stage, pipe_type) in stages[1..].iter().zip(pipe_types) {
+            let callee = self.expression(stage, locals);
+            let destructure = matches!(pipe_type, PipeType::Destructure);
+            let given = match Given::piped(current, destructure) {
+                Ok(given) => given,
+                Err(kind) => {
+                    self.report(kind, span);
+                    return Type::Unknown;
+                }
+            };
+            current = match callee {
+                Type::RuntimeInvocation => self.runtime(given, stage, span),
+                Type::Block(closure) => self.call(&closure, given, stage),
+                Type::Unknown => Type::Unknown,
+                other => {
+                    self.errors.push(
+                        EvaluationError::new(ErrorKind::TypeMismatch(format!(
+                            "Cannot pipe into a value of type {}",
+                            other
+                        )))
+                        .in_call(
+                            &Interpreter::describe_callee(stage),
+                            Some(stage.span()),
+                        )
+                        .with_span(span.clone()),
+                    );
+                    return Type::Unknown;
+                }
+            };
+        }
+
+        current
+    }
+
+    /// pipes into a block. besides what it declares, a block needs to be
+    /// given every positional parameter its body uses, unless it captured
+    /// them where it was created.
+    fn call(
+        &mut self,
+        closure: &Rc<Closure<'a>>,
+        given: Given<'a>,
+        stage: &'a ASTNode,
+    ) -> Type<'a> {
+        if let Some(values) = given.values() {
+            let mut used = vec![];
+            for statement in closure.statements {
+                positional(statement, &mut used);
+            }
+            let missing = used
+                .into_iter()
+                .filter(|index| {
+                    *index >= values.len()
+                        && !closure
+                            .captured
+                            .has_parameter(&format!("${}", index))
+                })
+                .max();
+            if let Some(index) = missing {
+                self.report(
+                    ErrorKind::MissingArguments {
+                        expected: index + 1,
+                        found: values.len(),
+                    },
+                    &stage.span(),
+                );
+            }
+        }
+
+        let callee = Interpreter::describe_callee(stage);
+        self.within(&callee, stage.span(), |checker| {
+            checker.invoke(closure, given)
+        })
+    }
+
+    /// invoke
+    ///
+    /// Checks the body of a block as invoked with the given arguments, giving
+    /// the type of what it comes to. A block invoked from within itself comes
+    /// to `Unknown` - or what its output is annotated with, if it is - so
+    /// that recursion doesn't go on forever.
+    fn invoke(
+        &mut self,
+        closure: &Rc<Closure<'a>>,
+        given: Given<'a>,
+    ) -> Type<'a> {
+        let output = output(closure.statements);
+        if self
+            .checking
+            .iter()
+            .any(|statements| ptr::eq(*statements, closure.statements))
+        {
+            return output.map_or(Type::Unknown, |(annotation, _)| {
+                Type::Unknown.refine(annotation)
+            });
+        }
+        if let Some((_, _, ty)) =
+            self.checked.iter().find(|(checked, checked_given, _)| {
+                checked == closure && *checked_given == given
+            })
+        {
+            return ty.clone();
+        }
+
+        let mut locals = Locals {
+            given: given.clone(),
+            bound: HashMap::from([(
+                "rec".to_string(),
+                Type::Block(closure.clone()),
+            )]),
+            captured: Some(closure.captured.clone()),
+            opaque: false,
+        };
+
+        self.checking.push(closure.statements);
+        let mut ty = Type::Tuple(vec![]);
+        for statement in closure.statements {
+            ty = self.expression(statement, &mut locals);
+        }
+        if let Some((annotation, span)) = output {
+            ty = self.conform(annotation, ty, span);
+        }
+        self.checking.pop();
+
+        self.checked.push((closure.clone(), given, ty.clone()));
+        ty
+    }
+
+    /// pipes into `plz`. the runtime calls are checked against what they
+    /// accept when the name of the call is known.
+    fn runtime(
+        &mut self,
+        given: Given<'a>,
+        stage: &ASTNode,
+        span: &Span,
+    ) -> Type<'a> {
+        let Some(values) = given.values() else {
+            return Type::Unknown;
+        };
+        if values.len() != 2 {
+            self.report(
+                ErrorKind::ArityMismatch {
+                    call: "Runtime invocation".to_string(),
+                    expected: 2,
+                    found: values.len(),
+                },
+                span,
+            );
+            return Type::Unknown;
+        }
+
+        let (parameters, call) = match (&values[0], &values[1]) {
+            (Type::Tuple(parameters), Type::String(Some(call))) => {
+                (parameters, call)
+            }
+            (
+                Type::Tuple(_) | Type::Unknown,
+                Type::String(_) | Type::Unknown,
+            ) => return Type::Unknown,
+            _ => {
+                self.report(
+                    ErrorKind::TypeMismatch(
+                        "Runtime parameters should be tuple and runtime call \
+                         should be string"
+                            .to_string(),
+                    ),
+                    span,
+                );
+                return Type::Unknown;
+            }
+        };
+
+        let callee = format!("plz \"{}\"", call);
+        let result = match call.as_str() {
+            "if" => self.within(&callee, stage.span(), |checker| {
+                checker.branch(parameters)
+            }),
+            _ => match self.runtime.get(call) {
+                Some(registered) => runtime_call(&registered, parameters),
+                None => Err(ErrorKind::UnknownRuntimeCall(call.clone())),
+            },
+        };
+        result.unwrap_or_else(|kind| {
+            self.errors.push(
+                EvaluationError::new(kind)
+                    .in_call(&callee, Some(stage.span()))
+                    .with_span(span.clone()),
+            );
+            Type::Unknown
+        })
+    }
+
+    /// an `if` comes to whichever of its branches it picks
+    fn branch(
+        &mut self,
+        parameters: &[Type<'a>],
+    ) -> Result<Type<'a>, ErrorKind> {
+        let [condition, yes, no] = parameters else {
+            return Err(arity_mismatch("if", 3, parameters.len()));
+        };
+        let branch = |ty: &Type| matches!(ty, Type::Block(_) | Type::Unknown);
+        if !matches!(condition, Type::Boolean | Type::Unknown)
+            || !branch(yes)
+            || !branch(no)
+        {
+            return Err(type_mismatch("if requires boolean and two closures"));
+        }
+
+        let mut picked = Type::Unknown;
+        for (index, branch) in [yes, no].into_iter().enumerate() {
+            let ty = match branch {
+                Type::Block(closure) => {
+                    self.invoke(closure, Given::known(vec![]))
+                }
+                _ => Type::Unknown,
+            };
+            picked = if index == 0 { ty } else { picked.join(ty) };
+        }
+        Ok(picked)
+    }
+
+    /// checks a match. the arms whose patterns could match the value are
+    /// invoked with what they'd capture, and the match comes to what any of
+    /// them could.
+    fn matching(
+        &mut self,
+        subject: &'a ASTNode,
+        arms: &'a [Arm],
+        span: &Span,
+        locals: &mut Locals<'a>,
+    ) -> Type<'a> {
+        let ty = self.expression(subject, locals);
+
+        let mut result: Option<Type<'a>> = None;
+        for arm in arms {
+            let guard = arm
+                .guard
+                .as_ref()
+                .map(|guard| (self.expression(guard, locals), guard.span()));
+            let body = self.expression(&arm.body, locals);
+            let Some(captured) = Given::captured(&arm.pattern, &ty) else {
+                continue;
+            };
+
+            if let Some((Type::Block(guard), guard_span)) = guard {
+                let passed =
+                    self.within("match guard", guard_span.clone(), |checker| {
+                        checker.invoke(&guard, captured.clone())
+                    });
+                if !matches!(passed, Type::Boolean | Type::Unknown) {
+                    self.report(
+                        ErrorKind::TypeMismatch(format!(
+                            "Match guards must produce a Boolean, not {}",
+                            passed
+                        )),
+                        &guard_span,
+                    );
+                }
+            }
+            if let Type::Block(body) = body {
+                let ty = self.within("match arm", arm.body.span(), |checker| {
+                    checker.invoke(&body, captured)
+                });
+                result = Some(match result {
+                    Some(result) => result.join(ty),
+                    None => ty,
+                });
+            }
+        }
+
+        result.unwrap_or_else(|| {
+            if let Some(found) = ty.descriptor() {
+                self.report(ErrorKind::NoMatch(found), span);
+            }
+            Type::Unknown
+        })
+    }
+}
+
+/// the error for a value of the given type not having the type it was
+/// annotated with
+fn annotation_mismatch(annotation: &Pattern, ty: &Type) -> ErrorKind {
+    match ty.descriptor() {
+        Some(found) => ErrorKind::AnnotationMismatch {
+            expected: annotation.clone(),
+            found,
+        },
+        None => ErrorKind::TypeMismatch(format!(
+            "Expected a value of type {} but found one of type {}",
+            annotation, ty
+        )),
+    }
+}
+
+/// what a registered runtime call comes to, given parameters of the given
+/// types, or the error it's sure to raise. makes the checks
+/// [RuntimeCall::invoke] does, as well as those the builtin calls make of
+/// their parameters.
+fn runtime_call<'a>(
+    call: &RuntimeCall,
+    parameters: &[Type<'a>],
+) -> Result<Type<'a>, ErrorKind> {
+    call.accepts(parameters)?;
+
+    let output = Type::Unknown.refine(&call.output);
+    if !call.is_builtin() {
+        return Ok(output);
+    }
+    match call.name.as_str() {
+        "foo" => Ok(Type::String(Some("bar".to_string()))),
+        "binop_arith" => {
+            check_binop_arith(parameters)?;
+            Ok(match (&parameters[0], &parameters[1]) {
+                (Type::Integer, Type::Integer) => Type::Integer,
+                (Type::Float, _) | (_, Type::Float) => Type::Float,
+                _ => Type::Unknown,
+            })
+        }
+        "binop_cmp" => check_binop_cmp(parameters).map(|_| Type::Boolean),
+        "strcat" => {
+            check_strcat(parameters)?;
+            Ok(match (&parameters[0], &parameters[1]) {
+                (Type::String(Some(left)), Type::String(Some(right))) => {
+                    Type::String(Some(format!("{}{}", left, right)))
+                }
+                _ => Type::String(None),
+            })
+        }
+        "json_parse" => check_json_parse(parameters).map(|_| Type::Unknown),
+        "json_stringify" => json_stringify(parameters),
+        _ => Ok(output),
+    }
+}
+
+fn json_stringify<'a>(parameters: &[Type<'a>]) -> Result<Type<'a>, ErrorKind> {
+    check_json_stringify(parameters)?;
+    match undata(&parameters[0]) {
+        Some(found) => Err(ErrorKind::TypeMismatch(format!(
+            "A value of type {} can't be serialized, only data can",
+            found
+        ))),
+        None => Ok(Type::String(None)),
+    }
+}
+
+/// the type of the first value within one of the given type that doesn't
+/// carry data, and so can't be serialized
+fn undata(ty: &Type) -> Option<TypeDescriptor> {
+    match ty {
+        Type::Block(_) | Type::RuntimeInvocation | Type::Type => {
+            ty.descriptor()
+        }
+        Type::Tuple(elements) => elements.iter().find_map(undata),
+        Type::Record(fields) => fields.iter().find_map(|(_, ty)| undata(ty)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Type<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            }
+            Type::Record(fields) => {
+                write!(f, "(")?;
+                for (i, (label, field)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", label, field)?;
+                }
+                write!(f, ")")
+            }
+            // types that aren't known show up as the pattern that matches
+            // anything
+            other => write!(f, "{}", type_name(other).unwrap_or("_")),
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use indoc::indoc;
+
+use super::*;
+use crate::{check_from_string, parse_from_string};
+
+/// the messages of the type errors found in the code, in source order
+fn type_errors(code: &str) -> Vec<String> {
+    type_errors_with(code, &RuntimeRegistry::default())
+}
+
+fn type_errors_with(code: &str, runtime: &RuntimeRegistry) -> Vec<String> {
+    check(&parse_from_string(code).unwrap(), runtime)
+        .into_iter()
+        .map(|error| error.kind.to_string())
+        .collect()
+}
+
+#[test]
+fn test_check_runtime_calls() {
+    assert_eq!(
+        type_errors(
+            r#"{
+                (("a" 1 "+") "binop_arith") |* plz
+                ((1 2 "%") "binop_arith") |* plz
+                ((1 type (1) "<") "binop_cmp") |* plz
+                ((type (1) type (2) "<") "binop_cmp") |* plz
+                (("a" 1) "strcat") |* plz
+                ((1) "print") |* plz
+                (() "nope") |* plz
+                ((1 2) "print" 3) |* plz
+                (1 "print") |* plz
+                ((1 {} {}) "if") |* plz
+            }"#
+        ),
+        vec![
+            "binop_arith requires both operands to be numeric",
+            "Unknown operation: %",
+            "binop_cmp requires both operands to be numeric or both to be types",
+            "Unknown operation: < on types",
+            "strcat requires two strings",
+            "Unknown runtime call: nope",
+            "Runtime invocation requires 2 arguments but received 3",
+            "Runtime parameters should be tuple and runtime call should be \
+             string",
+            "if requires boolean and two closures",
+        ]
+    );
+
+    // what runtime calls come to is known too
+    assert_eq!(
+        type_errors(
+            r#"{
+                sum: ((1 2.5 "+") "binop_arith") |* plz
+                joined: (("a" "b") "strcat") |* plz
+                ((sum joined "<") "binop_cmp") |* plz
+            }"#
+        ),
+        vec!["binop_cmp requires both operands to be numeric or both to be types"]
+    );
+    assert!(type_errors(
+        r#"{
+            ((1 2 "+") "binop_arith") |* plz
+            ((1 2.0 "<=") "binop_cmp") |* plz
+            ((type (1) type ("a") "==") "binop_cmp") |* plz
+            (("a" "b") "strcat") |* plz
+            ((T { 1 } { 2 }) "if") |* plz
+        }"#
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_check_blocks() {
+    // blocks are checked with the types of what they're piped
+    assert_eq!(
+        type_errors(
+            r#"{
+                add: { (($0 $1 "+") "binop_arith") |* plz }
+                greet: { |name| (("hi " name) "strcat") |* plz }
+                (1 2) |* add
+                ("a" 2) |* add
+                (1 2) |* greet
+                3 | greet
+                (1 2) |* add | greet
+                (1) |* { ($0 $1) }
+                5 | 3
+            }"#
+        ),
+        vec![
+            "binop_arith requires both operands to be numeric",
+            "Block requires 1 argument but received 2",
+            "strcat requires two strings",
+            "Block requires at least 2 arguments but received 1",
+            "Cannot pipe into a value of type Integer",
+        ]
+    );
+
+    // a block given fewer arguments than it uses can still have captured
+    // the rest, and one that's never invoked could be given anything
+    assert!(type_errors(
+        r#"{
+            ("a" "b") |* { (1) |* { ($0 $1) } }
+            { (($0 "x") "strcat") |* plz }
+            head: { |first ...rest| rest }
+            rest: (1 2 3) |* head
+            rest |* { |a b| ((a b "+") "binop_arith") |* plz }
+        }"#
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_check_access_and_match() {
+    assert_eq!(
+        type_errors(
+            r#"{
+                person: (name: "x" age: 3)
+                person.height
+                person.5
+                person.name.0
+                person.0..3
+                5 |* { $0 }
+                match (person.age) {
+                    String { 1 }
+                }
+                match (person) {
+                    (name: $name) when { $name } { 1 }
+                }
+                match (person) {
+                    (name: $name) { (($name 1 "+") "binop_arith") |* plz }
+                }
+            }"#
+        ),
+        vec![
+            "No field 'height' in value of type (name: String age: Integer)",
+            "Index 5 is out of range for a tuple of length 2",
+            "Can only index tuples and records, not String",
+            "Slice 0..3 is out of range for a tuple of length 2",
+            "Trying to destructure non-tuple value of type Integer",
+            "No pattern matches a value of type Integer",
+            "Match guards must produce a Boolean, not String",
+            "binop_arith requires both operands to be numeric",
+        ]
+    );
+
+    // a match comes to what any of its arms could
+    assert_eq!(
+        type_errors(
+            r#"{
+                size: match ((1 2)) { ($a $b) { 2 } _ { 3 } }
+                ((size "x") "strcat") |* plz
+            }"#
+        ),
+        vec!["strcat requires two strings"]
+    );
+}
+
+#[test]
+fn test_check_unknown_types() {
+    // nothing is known about imports, recursion or what a paste brought into
+    // scope, so none of them are reported
+    assert!(type_errors(
+        r#"{
+            m: import "m.blkp"
+            ((m 1 "+") "binop_arith") |* plz
+            count: {
+                again: rec
+                done: (($0 0 "==") "binop_cmp") |* plz
+                ((done { "done" } {
+                    (($0 1 "-") "binop_arith") |* plz | again
+                }) "if") |* plz
+            }
+            counted: 5 | count
+            ((counted 1 "+") "binop_arith") |* plz
+            x: "a"
+            paste (m)
+            ((x 1 "+") "binop_arith") |* plz
+        }"#
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_check_annotations() {
+    // parameters are taken to have the types they're annotated with, so
+    // blocks that are never invoked are checked too
+    assert_eq!(
+        type_errors(
+            r#"{
+                x: String = 3
+                shout: { |s: String| -> String ((s "!") "strcat") |* plz }
+                5 | shout
+                broken: { |n: Integer| ((n "!") "strcat") |* plz }
+                name: { || -> Integer "bob" }
+                fact: { |n: Integer| -> Integer
+                    again: rec
+                    done: ((n 0 "==") "binop_cmp") |* plz
+                    ((done { 1 } { ((n 1 "-") "binop_arith") |* plz | again })
+                        "if") |* plz
+                }
+                six: 3 | fact
+                ((six "!") "strcat") |* plz
+            }"#
+        ),
+        vec![
+            "Expected a value of type String but found one of type Integer",
+            "Expected a value of type String but found one of type Integer",
+            "strcat requires two strings",
+            "Expected a value of type Integer but found one of type String",
+            "strcat requires two strings",
+        ]
+    );
+
+    assert!(type_errors(
+        r#"{
+            pair: (Integer _) = (1 "a")
+            first: { |p: (Integer _)| -> Integer p.0 }
+            pair | first
+            any: { |t| -> Tuple t }
+            named: { |r: (name: String)| r.name }
+        }"#
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_check_json() {
+    assert_eq!(
+        type_errors(
+            r#"{
+                parsed: ((r"[1]") "json_parse") |* plz
+                ((parsed ()) "json_stringify") |* plz
+                ((3) "json_parse") |* plz
+                ((("a" { 1 }) ()) "json_stringify") |* plz
+                ((1 4) "json_stringify") |* plz
+            }"#
+        ),
+        vec![
+            "json_parse requires a string",
+            "A value of type Closure can't be serialized, only data can",
+            "json_stringify options must be a record",
+        ]
+    );
+}
+
+#[test]
+fn test_check_runtime_registry() {
+    // checking never makes the call, only looks at its signature
+    let string = || Pattern::Type("String".to_string());
+    let repeat = RuntimeCall::new(
+        "repeat",
+        vec![string(), Pattern::Type("Integer".to_string())],
+        |_| unreachable!(),
+    )
+    .returning(string());
+    let mut runtime = RuntimeRegistry::default();
+    runtime.register(repeat);
+    runtime.remove("print");
+
+    assert_eq!(
+        type_errors_with(
+            r#"{
+                twice: (("ab" 2) "repeat") |* plz
+                ((twice "!") "strcat") |* plz
+                (("ab" "c") "repeat") |* plz
+                (("ab") "repeat") |* plz
+                (("x") "print") |* plz
+            }"#,
+            &runtime
+        ),
+        vec![
+            "Expected a value of type Integer but found one of type String",
+            "repeat requires 2 arguments but received 1",
+            "Unknown runtime call: print",
+        ]
+    );
+}
+
+#[test]
+fn test_check_render() {
+    let code = indoc! {r#"
+        {
+          add: { (($0 $1 "+") "binop_arith") |* plz }
+          ("a" 1) |* add
+        }"#};
+    let errors = check(
+        &parse_from_string(code).unwrap(),
+        &RuntimeRegistry::default(),
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].render(code),
+        indoc! {r#"
+            error: binop_arith requires both operands to be numeric
+             --> 2:10
+              |
+            2 |   add: { (($0 $1 "+") "binop_arith") |* plz }
+              |          ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+              = in plz "binop_arith" at 2:41
+              = in block `add` at 3:14
+        "#}
+    );
+}
+
+#[test]
+fn test_check_unbound_symbols() {
+    // check finds them without running anything, even in blocks that never
+    // run, where the interpreter would never notice them
+    let source = "{ never: { missing } (missing also_missing) }";
+    let errors: Vec<_> = check_from_string(source)
+        .into_iter()
+        .map(|error| (error.kind, error.span))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                ErrorKind::UnboundSymbol("missing".to_string()),
+                Some(11..18)
+            ),
+            (
+                ErrorKind::UnboundSymbol("also_missing".to_string()),
+                Some(30..42)
+            ),
+        ]
+    );
+}
@@ -0,0 +1,133 @@
+use super::{ErrorKind, TypeDescriptor, Value};
+use crate::parser::Pattern;
+
+// conversions between values and the rust types they correspond to, for
+// hosts that pass values into programs and read back what they come to.
+// converting a value of the wrong type fails with the same error as an
+// annotation it doesn't conform to.
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Float(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Boolean(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+}
+
+/// the empty tuple, which is what blocks that have nothing to give back
+/// usually come to
+impl From<()> for Value {
+    fn from(_: ()) -> Value {
+        Value::Tuple(vec![])
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Value {
+        Value::Tuple(values)
+    }
+}
+
+/// a record, with its fields in the given order
+impl From<Vec<(String, Value)>> for Value {
+    fn from(fields: Vec<(String, Value)>) -> Value {
+        Value::Record(fields)
+    }
+}
+
+/// the error for converting a value that isn't of the given type
+fn mismatch(expected: &str, value: &Value) -> ErrorKind {
+    ErrorKind::AnnotationMismatch {
+        expected: Pattern::Type(expected.to_string()),
+        found: TypeDescriptor::of(value),
+    }
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<i64, ErrorKind> {
+        match value {
+            Value::Integer(value) => Ok(value),
+            other => Err(mismatch("Integer", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<f64, ErrorKind> {
+        match value {
+            Value::Float(value) => Ok(value),
+            other => Err(mismatch("Float", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<bool, ErrorKind> {
+        match value {
+            Value::Boolean(value) => Ok(value),
+            other => Err(mismatch("Boolean", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<String, ErrorKind> {
+        match value {
+            Value::String(value) => Ok(value),
+            other => Err(mismatch("String", &other)),
+        }
+    }
+}
+
+/// the elements of a tuple (but not a record, whose labels would be lost)
+impl TryFrom<Value> for Vec<Value> {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<Vec<Value>, ErrorKind> {
+        match value {
+            Value::Tuple(values) => Ok(values),
+            other => Err(mismatch("Tuple", &other)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<(String, Value)> {
+    type Error = ErrorKind;
+
+    fn try_from(value: Value) -> Result<Vec<(String, Value)>, ErrorKind> {
+        match value {
+            Value::Record(fields) => Ok(fields),
+            other => Err(mismatch("Record", &other)),
+        }
+    }
+}
@@ -1,90 +1,112 @@
-use std::collections::{HashMap, HashSet};
-use super::Value;
+use super::{Arguments, Value};
+use std::collections::HashMap;
+
+/// a stack frame. every name bound in the frame gets a slot, which the
+/// resolver can point identifiers at directly.
+#[derive(Debug, Default)]
+pub struct Frame {
+    names: Vec<String>,
+    values: Vec<Option<Value>>,
+}
+
+impl Frame {
+    fn slot(&self, key: &str) -> Option<usize> {
+        self.names.iter().position(|name| name == key)
+    }
+}
 
 /// the runtime stack
 #[derive(Debug)]
 pub struct Environment {
-    pub stack_frames: Vec<HashSet<String>>,
-    pub keys: HashMap<String, Vec<Value>>,
+    pub frames: Vec<Frame>,
 }
 
 impl Environment {
     pub fn new() -> Environment {
-        Environment {
-            stack_frames: Vec::new(),
-            keys: HashMap::new(),
-        }
+        Environment { frames: Vec::new() }
     }
 
     /// invoked at the start of a block
     pub fn push_stack_frame(&mut self) {
-        self.stack_frames.push(HashSet::new());
+        self.frames.push(Frame::default());
+    }
+
+    /// pushes a frame with (empty) slots for the given names, in order
+    pub fn push_slots(&mut self, names: Vec<String>) {
+        let values = vec![None; names.len()];
+        self.frames.push(Frame { names, values });
     }
 
     pub fn pop_stack_frame(&mut self) -> Result<(), ()> {
-        let last_frame = self.stack_frames.pop().ok_or(())?;
+        self.frames.pop().map(|_| ()).ok_or(())
+    }
 
-        for key in last_frame {
-            self.keys
-                .get_mut(&key)
-                .expect("stack corruption: pop stack frame")
-                .pop();
+    pub fn bind(&mut self, key: String, value: Value) {
+        let frame = self.frames.last_mut().expect("stack corruption: bind");
 
-            if self
-                .keys
-                .get_mut(&key)
-                .expect("stack corruption: pop stack frame")
-                .is_empty()
-            {
-                self.keys.remove(&key);
+        // if the key already has a slot in the current frame, then we'll
+        // just replace its value, and otherwise give it a new one
+        match frame.slot(&key) {
+            Some(index) => frame.values[index] = Some(value),
+            None => {
+                frame.names.push(key);
+                frame.values.push(Some(value));
             }
         }
+    }
 
-        Ok(())
+    /// binds the slot at the given index of the current frame
+    pub fn assign(&mut self, index: usize, value: Value) {
+        let frame = self.frames.last_mut().expect("stack corruption: assign");
+        frame.values[index] = Some(value);
     }
 
-    pub fn bind(&mut self, key: String, value: Value) {
-        // if the current key is already in the stack frame, then we'll remove it
-        if self
-            .stack_frames
-            .last()
-            .expect("stack corruption: bind")
-            .contains(&key)
-        {
-            self.keys
-                .get_mut(&key)
-                .expect("stack corruption: bind")
-                .pop();
-        }
-        // otherwise, we'll just go ahead and insert the current key to the stack frame
-        else {
-            self.stack_frames
-                .last_mut()
-                .expect("stack corruption: bind")
-                .insert(key.clone());
-        }
+    pub fn lookup(&self, key: &str) -> Option<Value> {
+        let (depth, index) = self.locate(key)?;
+        self.lookup_at(depth, index)
+    }
 
-        // and then we'll unconditionally insert the key
-        self.keys.entry(key).or_insert(Vec::new()).push(value);
+    /// looks up the slot at `index` of the frame `depth` frames out from the
+    /// current one
+    pub fn lookup_at(&self, depth: usize, index: usize) -> Option<Value> {
+        let frame = self.frames.iter().rev().nth(depth)?;
+        frame.values.get(index)?.clone()
     }
 
-    pub fn lookup(&self, key: &str) -> Option<Value> {
-        Some(self.keys.get(key)?.last()?.clone())
+    /// finds the innermost binding of a key, as a (depth, index) pair
+    pub fn locate(&self, key: &str) -> Option<(usize, usize)> {
+        self.frames
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, frame)| {
+                let index = frame.slot(key)?;
+                frame.values[index].as_ref().map(|_| (depth, index))
+            })
+    }
+
+    /// the arguments that top level code was run with, as bound by
+    /// `bind_parameters`
+    pub fn arguments(&self) -> Arguments {
+        let count = match self.lookup("$n") {
+            Some(Value::Integer(count)) => count as usize,
+            _ => 0,
+        };
+        let values =
+            (0..count).filter_map(|index| self.lookup(&format!("${}", index)));
+        values.collect::<Vec<_>>().into()
     }
 
     /// grab the most recent set of bindings in the environment
     pub fn image(&self) -> HashMap<String, Value> {
-        self.keys
-            .iter()
-            .map(|(key, value)| {
-                let cloned_key = key.clone();
-                let cloned_value = value
-                    .last()
-                    .expect("binding without associated values")
-                    .clone();
-
-                (cloned_key, cloned_value)
-            })
-            .collect()
+        let mut image = HashMap::new();
+        for frame in &self.frames {
+            for (name, value) in frame.names.iter().zip(&frame.values) {
+                if let Some(value) = value {
+                    image.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        image
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,398 @@
+use core::fmt;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+use logos::Span;
+
+use super::{Limit, TypeDescriptor};
+use crate::parser::{Accessor, ParserError, Pattern, SyntaxError};
+
+/// the different ways in which evaluation can go wrong
+#[derive(Debug, PartialEq, Clone)]
+pub enum ErrorKind {
+    UnboundSymbol(String),
+    ArityMismatch {
+        call: String,
+        expected: usize,
+        found: usize,
+    },
+    // given to a block that takes the rest of its arguments as a tuple
+    MissingArguments {
+        expected: usize,
+        found: usize,
+    },
+    TypeMismatch(String),
+    // a value that doesn't have the type it was annotated with
+    AnnotationMismatch {
+        expected: Pattern,
+        found: TypeDescriptor,
+    },
+    DestructureNonTuple(TypeDescriptor),
+    MissingField {
+        field: String,
+        found: TypeDescriptor,
+    },
+    OutOfRange {
+        accessor: Accessor,
+        length: usize,
+    },
+    // a match on a value that none of its arms accept
+    NoMatch(TypeDescriptor),
+    DivisionByZero,
+    UnknownRuntimeCall(String),
+    UnknownOperation(String),
+    ParseError(String),
+    ImportFailed {
+        path: String,
+        reason: String,
+    },
+    CyclicImport(Vec<String>),
+    // an error raised while evaluating an imported module, already rendered
+    // against the module's own source
+    ModuleError {
+        path: String,
+        report: String,
+    },
+    LimitExceeded {
+        limit: Limit,
+        max: usize,
+    },
+}
+
+/// the code a block was written in, so that errors raised by the block can
+/// be rendered against it wherever it ends up being called from. code that
+/// goes unnamed is the code an error is rendered against.
+#[derive(Default, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Source {
+        Source {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+}
+
+// the text is left out, since every block written in it holds on to it
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Source({:?})", self.name)
+    }
+}
+
+/// a single block invocation that was active when an error was raised
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallFrame {
+    pub name: String,
+    pub span: Option<Span>,
+    /// the code the span lies in, once the error has left it
+    pub source: Option<Rc<Source>>,
+}
+
+/// an evaluation error - what went wrong, the byte span of the expression
+/// responsible for it (when known), and the block invocations it propagated
+/// through, innermost first. spans are tagged with the code they lie in as
+/// the error propagates out of the blocks written in it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvaluationError {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+    pub source: Option<Rc<Source>>,
+    pub call_stack: Vec<CallFrame>,
+}
+
+impl EvaluationError {
+    pub fn new(kind: ErrorKind) -> EvaluationError {
+        EvaluationError {
+            kind,
+            span: None,
+            source: None,
+            call_stack: vec![],
+        }
+    }
+
+    /// attaches a span to the error, unless a more precise one (from a
+    /// nested expression) has already been attached.
+    pub fn with_span(mut self, span: Span) -> EvaluationError {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    /// records that the error propagated out of a block invocation
+    pub fn in_call(mut self, name: &str, span: Option<Span>) -> Self {
+        self.call_stack.push(CallFrame {
+            name: name.to_string(),
+            span,
+            source: None,
+        });
+        self
+    }
+
+    /// records that the error propagated out of a block written in the given
+    /// source, which every span attached since it left the last one lies in
+    pub fn in_source(mut self, source: &Rc<Source>) -> Self {
+        if self.span.is_some() && self.source.is_none() {
+            self.source = Some(source.clone());
+        }
+        for frame in &mut self.call_stack {
+            if frame.span.is_some() && frame.source.is_none() {
+                frame.source = Some(source.clone());
+            }
+        }
+        self
+    }
+
+    /// render
+    ///
+    /// Produces a human readable report of the error against the source it
+    /// came from: the message, the offending source line with a caret under
+    /// the span, and the chain of block invocations that led to it. Spans
+    /// that lie in some other named source, like a block written in an
+    /// imported module, are rendered against that source instead, with its
+    /// name in front of their location.
+    pub fn render(&self, source: &str) -> String {
+        let mut report = format!("error: {}\n", self.kind);
+
+        if let Some(span) = &self.span {
+            let (text, name) = origin(source, self.source.as_deref());
+            report.push_str(&render_snippet(text, name, span));
+        }
+
+        // runaway recursion makes for a lot of identical frames, which are
+        // only listed once
+        let mut frames = self.call_stack.iter().peekable();
+        while let Some(frame) = frames.next() {
+            match &frame.span {
+                Some(span) => {
+                    let (text, name) = origin(source, frame.source.as_deref());
+                    report.push_str(&format!(
+                        "  = in {} at {}\n",
+                        frame.name,
+                        location(text, name, span.start)
+                    ));
+                }
+                None => report.push_str(&format!("  = in {}\n", frame.name)),
+            }
+
+            let mut repeats = 0;
+            while frames.next_if_eq(&frame).is_some() {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                report.push_str(&format!(
+                    "  = ... repeated {} more time{}\n",
+                    repeats,
+                    if repeats == 1 { "" } else { "s" }
+                ));
+            }
+        }
+
+        report
+    }
+}
+
+/// the text a span tagged with the given source lies in, along with the
+/// name to locate it by - which is left out when it lies in the source being
+/// rendered against
+fn origin<'s>(
+    rendered: &'s str,
+    source: Option<&'s Source>,
+) -> (&'s str, Option<&'s str>) {
+    match source {
+        Some(source) if !source.name.is_empty() && source.text != rendered => {
+            (&source.text, Some(&source.name))
+        }
+        _ => (rendered, None),
+    }
+}
+
+/// where a byte offset falls in the source, as `line:column` - preceded by
+/// the source's name if it has one
+fn location(source: &str, name: Option<&str>, offset: usize) -> String {
+    let (line, column) = line_and_column(source, offset);
+    match name {
+        Some(name) => format!("{}:{}:{}", name, line, column),
+        None => format!("{}:{}", line, column),
+    }
+}
+
+/// the offset moved back onto the source, and onto the start of the
+/// character it falls in, so that a span from some other source can't make
+/// slicing this one panic
+fn clamp(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// obtains the (1-indexed) line and column of a byte offset into the source
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..clamp(source, offset)];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// something that is likely to be a mistake, but won't stop a program from
+/// running, like a match that doesn't handle every value it could be given
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Warning {
+    /// renders the warning against the source it came from, in the same
+    /// format as [EvaluationError::render]
+    pub fn render(&self, source: &str) -> String {
+        format!(
+            "warning: {}\n{}",
+            self.message,
+            render_snippet(source, None, &self.span)
+        )
+    }
+}
+
+/// renders an error that doesn't come from evaluating the source, such as a
+/// construct the compiler can't handle, in the same format as
+/// [EvaluationError::render]
+pub fn render_error(message: &str, source: &str, span: &Span) -> String {
+    format!("error: {}\n{}", message, render_snippet(source, None, span))
+}
+
+fn render_snippet(source: &str, name: Option<&str>, span: &Span) -> String {
+    let (line, column) = line_and_column(source, span.start);
+    let (start, end) = (clamp(source, span.start), clamp(source, span.end));
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let text = source[line_start..].lines().next().unwrap_or("");
+
+    // the caret covers the span, but never runs past the end of the line
+    let width = source[start..end.max(start)]
+        .lines()
+        .next()
+        .map_or(1, |covered| covered.chars().count().max(1));
+
+    let gutter = " ".repeat(line.to_string().len());
+    format!(
+        "{gutter}--> {}\n\
+         {gutter} |\n\
+         {line} | {text}\n\
+         {gutter} | {}{}\n",
+        location(source, name, span.start),
+        " ".repeat(column - 1),
+        "^".repeat(width),
+    )
+}
+
+impl From<ErrorKind> for EvaluationError {
+    fn from(kind: ErrorKind) -> Self {
+        EvaluationError::new(kind)
+    }
+}
+
+impl From<ParserError> for EvaluationError {
+    fn from(error: ParserError) -> Self {
+        EvaluationError::new(ErrorKind::ParseError(error.to_string()))
+            .with_span(error.span)
+    }
+}
+
+impl From<SyntaxError> for EvaluationError {
+    fn from(error: SyntaxError) -> Self {
+        let span = error.span();
+        EvaluationError::new(ErrorKind::ParseError(error.to_string()))
+            .with_span(span)
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnboundSymbol(symbol) => {
+                write!(f, "Unbound symbol '{}'", symbol)
+            }
+            ErrorKind::ArityMismatch {
+                call,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} requires {} argument{} but received {}",
+                call,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            ErrorKind::TypeMismatch(message) => write!(f, "{}", message),
+            ErrorKind::AnnotationMismatch { expected, found } => write!(
+                f,
+                "Expected a value of type {} but found one of type {}",
+                expected, found
+            ),
+            ErrorKind::DestructureNonTuple(descriptor) => {
+                write!(
+                    f,
+                    "Trying to destructure non-tuple value of type {}",
+                    descriptor
+                )
+            }
+            ErrorKind::MissingField { field, found } => {
+                write!(f, "No field '{}' in value of type {}", field, found)
+            }
+            ErrorKind::MissingArguments { expected, found } => write!(
+                f,
+                "Block requires at least {} argument{} but received {}",
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            ErrorKind::NoMatch(found) => {
+                write!(f, "No pattern matches a value of type {}", found)
+            }
+            ErrorKind::OutOfRange { accessor, length } => write!(
+                f,
+                "{} {} is out of range for a tuple of length {}",
+                match accessor {
+                    Accessor::Slice(..) => "Slice",
+                    _ => "Index",
+                },
+                accessor,
+                length
+            ),
+            ErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            ErrorKind::UnknownRuntimeCall(call) => {
+                write!(f, "Unknown runtime call: {}", call)
+            }
+            ErrorKind::UnknownOperation(op) => {
+                write!(f, "Unknown operation: {}", op)
+            }
+            ErrorKind::ParseError(message) => write!(f, "{}", message),
+            ErrorKind::ImportFailed { path, reason } => {
+                write!(f, "Failed to import {}: {}", path, reason)
+            }
+            ErrorKind::CyclicImport(chain) => {
+                write!(f, "Cyclic import: {}", chain.join(" -> "))
+            }
+            ErrorKind::ModuleError { path, report } => {
+                write!(f, "In module {}:\n{}", path, report.trim_end())
+            }
+            ErrorKind::LimitExceeded { limit, max } => {
+                write!(f, "{} limit of {} exceeded", limit, max)
+            }
+        }
+    }
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
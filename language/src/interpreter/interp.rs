@@ -1,12 +1,41 @@
-use super::invoke_runtime;
+use std::{collections::HashMap, rc::Rc};
+
+use logos::Span;
+
+use super::conforms;
+use super::matches;
+use super::pipe_into_runtime;
+use super::resolve;
+use super::runtime_tail_call;
+use super::trampoline;
+use super::Address;
+use super::Arguments;
 use super::Environment;
+use super::ErrorKind;
+use super::Limits;
+use super::Meter;
+use super::ModuleLoader;
+use super::Outcome;
+use super::RuntimeRegistry;
+use super::Scope;
+use super::SharedProgram;
+use super::Source;
+use super::TailCall;
+use super::TypeDescriptor;
 use super::Value;
-use crate::interpreter::EvaluateResult;
-use crate::parser::{ASTNode, LiteralVariant, PipeType};
+use crate::interpreter::{EvaluateResult, EvaluationError};
+use crate::parser::{ASTNode, Arm, LiteralVariant, Parameters, PipeType};
+use crate::{native, vm};
 
 pub struct Interpreter {
     pub root_node: ASTNode,
     pub env: Environment,
+    pub program: SharedProgram,
+    /// what the code being evaluated was written in, which the blocks it
+    /// creates hold on to for rendering their errors against
+    pub source: Rc<Source>,
+    // what the resolver worked out about the code being evaluated
+    scope: Rc<Scope>,
 }
 
 impl Interpreter {
@@ -14,9 +43,22 @@ impl Interpreter {
         Interpreter {
             root_node,
             env: Environment::new(),
+            program: SharedProgram::default(),
+            source: Rc::default(),
+            scope: Rc::default(),
         }
     }
 
+    /// caps what the program may use from here on, starting afresh
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.program.borrow_mut().meter = Meter::new(limits);
+    }
+
+    /// replaces the runtime calls the program can make through `plz`
+    pub fn set_runtime(&mut self, runtime: RuntimeRegistry) {
+        self.program.borrow_mut().runtime = runtime;
+    }
+
     pub fn bind_parameters(&mut self, parameters: Vec<Value>) {
         for (index, parameter) in parameters.iter().enumerate() {
             let parameter_id = format!("${}", index);
@@ -25,6 +67,7 @@ impl Interpreter {
 
         self.env
             .bind("$n".to_string(), Value::Integer(parameters.len() as i64));
+        self.env.bind("$@".to_string(), Value::Tuple(parameters));
     }
 
     pub fn evaluate_from_root(
@@ -43,20 +86,88 @@ impl Interpreter {
         result
     }
 
+    /// evaluate
+    ///
+    /// Evaluates an expression at the top level, after resolving the names
+    /// it uses against the environment - so those of its own that are
+    /// unbound are reported before any of it runs.
     pub fn evaluate(&mut self, node: &ASTNode) -> EvaluateResult {
-        match node {
-            ASTNode::Literal(literal) => self.evaluate_literal(literal),
-            ASTNode::Tuple(tuple) => self.evaluate_tuple(tuple),
-            ASTNode::Identifier(id) => self.evaluate_identifier(id),
-            ASTNode::Block(expressions) => self.evaluate_block(expressions),
-            ASTNode::Binding((identifier, value)) => {
-                self.evaluate_binding(identifier, value)
+        self.scope = Rc::new(resolve(node, &self.env, &self.source)?);
+        self.evaluate_expression(node)
+    }
+
+    fn evaluate_expression(&mut self, node: &ASTNode) -> EvaluateResult {
+        self.program
+            .borrow_mut()
+            .meter
+            .step()
+            .map_err(|error| error.with_span(node.span()))?;
+
+        let result = match node {
+            ASTNode::Literal(literal, _) => self.evaluate_literal(literal),
+            ASTNode::Tuple(tuple, _) => self.evaluate_tuple(tuple),
+            ASTNode::Record(fields, _) => self.evaluate_record(fields),
+            ASTNode::Identifier(id, span) => self.evaluate_identifier(id, span),
+            ASTNode::Access(operand, accessor, _) => self
+                .evaluate_expression(operand)
+                .and_then(|value| Ok(value.access(accessor)?)),
+            ASTNode::Block(_, span) => self.evaluate_block(span),
+            ASTNode::Annotated(value, annotation, _) => {
+                self.evaluate_expression(value).and_then(|value| {
+                    conforms(annotation, &value)?;
+                    Ok(value)
+                })
             }
-            ASTNode::Pipe(expressions, pipe_types) => {
-                self.evaluate_pipe(expressions, pipe_types)
+            ASTNode::Binding((identifier, value), span) => {
+                self.evaluate_binding(identifier, value, span)
             }
-            _ => panic!("Unimplemented ASTNode variant"),
-        }
+            // blocks declare their parameters on being entered, so this is
+            // only reached by top level code, which has the arguments the
+            // program was run with
+            ASTNode::Parameters(declared, _) => {
+                let arguments = self.env.arguments();
+                self.declare(&arguments, declared)
+            }
+            ASTNode::Pipe(expressions, pipe_types, _) => self
+                .evaluate_pipe(expressions, pipe_types, false)
+                .map(|outcome| match outcome {
+                    Outcome::Done(value) => value,
+                    Outcome::TailCall(_) => {
+                        unreachable!("tail call outside of tail position")
+                    }
+                }),
+            ASTNode::Match(subject, arms, _) => self
+                .evaluate_match(subject, arms, false)
+                .map(|outcome| match outcome {
+                    Outcome::Done(value) => value,
+                    Outcome::TailCall(_) => {
+                        unreachable!("tail call outside of tail position")
+                    }
+                }),
+            ASTNode::Type(operand, _) => self.evaluate_type(operand),
+            ASTNode::Import(path, _) => {
+                ModuleLoader::import(&self.program, path)
+            }
+            // outside of a tuple, pasting behaves like a sequence of
+            // expressions: the last spliced value is the one that sticks
+            ASTNode::Paste(operand, _) => {
+                self.evaluate_paste(operand).map(|mut spliced| {
+                    spliced.pop().unwrap_or(Value::Tuple(vec![]))
+                })
+            }
+            ASTNode::Error(_) => Err(ErrorKind::ParseError(
+                "Cannot evaluate source that failed to parse".to_string(),
+            )
+            .into()),
+        };
+
+        let result = result.and_then(|value| {
+            self.program.borrow().meter.check(&value)?;
+            Ok(value)
+        });
+
+        // errors point at the innermost expression that produced them
+        result.map_err(|error| error.with_span(node.span()))
     }
 
     fn evaluate_literal(&self, literal: &LiteralVariant) -> EvaluateResult {
@@ -68,125 +179,448 @@ impl Interpreter {
         }
     }
 
-    fn evaluate_tuple(&mut self, tuple: &Vec<ASTNode>) -> EvaluateResult {
+    fn evaluate_tuple(&mut self, tuple: &[ASTNode]) -> EvaluateResult {
         let mut values = Vec::new();
         for node in tuple {
-            values.push(self.evaluate(node)?);
+            if let ASTNode::Paste(operand, _) = node {
+                values.extend(self.evaluate_paste(operand)?);
+            } else {
+                values.push(self.evaluate_expression(node)?);
+            }
         }
         Ok(Value::Tuple(values))
     }
 
-    fn evaluate_identifier(&self, identifier: &String) -> EvaluateResult {
-        self.env
-            .lookup(identifier)
-            .ok_or(format!("Unbound symbol '{}'", identifier))
+    fn evaluate_record(
+        &mut self,
+        fields: &[(String, ASTNode)],
+    ) -> EvaluateResult {
+        let mut values = Vec::with_capacity(fields.len());
+        for (label, node) in fields {
+            values.push((label.clone(), self.evaluate_expression(node)?));
+        }
+        Ok(Value::Record(values))
+    }
+
+    fn evaluate_identifier(
+        &self,
+        identifier: &str,
+        span: &Span,
+    ) -> EvaluateResult {
+        self.lookup(identifier, self.scope.address(span))
+            .ok_or_else(|| {
+                ErrorKind::UnboundSymbol(identifier.to_string()).into()
+            })
+    }
+
+    fn lookup(
+        &self,
+        identifier: &str,
+        address: Option<&Address>,
+    ) -> Option<Value> {
+        match address {
+            Some(Address::Slot(depth, index)) => {
+                self.env.lookup_at(*depth, *index)
+            }
+            Some(Address::Dynamic) | None => self.env.lookup(identifier),
+        }
     }
 
-    fn evaluate_block(&self, expressions: &Vec<ASTNode>) -> EvaluateResult {
-        let env_image = self.env.image();
-        Ok(Value::Closure(expressions.clone(), env_image))
+    /// closes the block over the values of the names it uses, and of those
+    /// bound by the code creating it. names that aren't bound yet are left
+    /// out, and stay unbound inside the block.
+    fn evaluate_block(&self, span: &Span) -> EvaluateResult {
+        let definition = self
+            .scope
+            .definition(span)
+            .expect("block missed by the resolver");
+
+        let mut captured = HashMap::new();
+        for (name, address) in &definition.captures {
+            if let Some(value) = self.lookup(name, Some(address)) {
+                captured.insert(name.clone(), value);
+            }
+        }
+        Ok(Value::Closure(definition.block.clone(), Rc::new(captured)))
     }
 
     fn evaluate_binding(
         &mut self,
-        identifier: &String,
-        value: &Box<ASTNode>,
+        identifier: &str,
+        value: &ASTNode,
+        span: &Span,
     ) -> EvaluateResult {
-        let expr_value = self.evaluate(value)?;
-        self.env.bind(identifier.clone(), expr_value);
+        let expr_value = self.evaluate_expression(value)?;
+        match self.scope.address(span) {
+            Some(Address::Slot(0, index)) => {
+                self.env.assign(*index, expr_value)
+            }
+            _ => self.env.bind(identifier.to_string(), expr_value),
+        }
         Ok(Value::Tuple(vec![]))
     }
 
-    fn evaluate_pipe(
+    /// binds the parameters declared by a block to the arguments it was given
+    fn declare(
         &mut self,
-        expressions: &Vec<ASTNode>,
-        pipe_types: &Vec<PipeType>,
+        arguments: &Arguments,
+        declared: &Parameters,
     ) -> EvaluateResult {
-        let mut curr_value = self.evaluate(&expressions[0])?;
-
-        for (expr, pipe_type) in expressions[1..].iter().zip(pipe_types) {
-            let closure = self.evaluate(expr)?;
-            let transformed_input = match pipe_type {
-                PipeType::Standard => vec![curr_value.clone()],
-                PipeType::Destructure => {
-                    if let Value::Tuple(values) = curr_value.clone() {
-                        values
-                    } else {
-                        return Err(
-                            "Trying to destructure non-tuple value".to_string()
-                        );
-                    }
+        let values = arguments.declare(declared)?;
+        for ((name, value), (_, span)) in
+            values.into_iter().zip(declared.declared())
+        {
+            match self.scope.address(span) {
+                Some(Address::Slot(0, index)) => self.env.assign(*index, value),
+                _ => self.env.bind(name, value),
+            }
+        }
+        Ok(Value::Tuple(vec![]))
+    }
+
+    fn evaluate_type(&mut self, operand: &ASTNode) -> EvaluateResult {
+        // `type (x)` describes x itself rather than the single element tuple
+        // wrapped around it, while `type (x y)` describes the tuple (x y)
+        let value = match operand {
+            ASTNode::Tuple(elements, _) if elements.len() == 1 => {
+                self.evaluate_expression(&elements[0])?
+            }
+            _ => self.evaluate_expression(operand)?,
+        };
+
+        Ok(Value::Type(TypeDescriptor::of(&value)))
+    }
+
+    /// evaluate_paste
+    ///
+    /// Evaluates every expression in the operand of `paste (...)` and returns
+    /// the values to splice in its place: tuples and records contribute their
+    /// elements,
+    /// while closures contribute their captured bindings to the current frame
+    /// instead of any values.
+    fn evaluate_paste(
+        &mut self,
+        operand: &ASTNode,
+    ) -> Result<Vec<Value>, EvaluationError> {
+        let pasted = match operand {
+            ASTNode::Tuple(elements, _) => elements.as_slice(),
+            _ => std::slice::from_ref(operand),
+        };
+
+        let mut spliced = Vec::new();
+        for node in pasted {
+            let env_image = match self.evaluate_expression(node)? {
+                Value::Tuple(values) => {
+                    spliced.extend(values);
+                    continue;
+                }
+                Value::Record(fields) => {
+                    spliced.extend(fields.into_iter().map(|(_, value)| value));
+                    continue;
                 }
+                other => other.bindings().ok_or_else(|| {
+                    ErrorKind::TypeMismatch(format!(
+                        "Can only paste tuples and closures, not {}",
+                        TypeDescriptor::of(&other)
+                    ))
+                })?,
             };
+
+            for (id, val) in env_image {
+                self.env.bind(id, val);
+            }
+        }
+
+        Ok(spliced)
+    }
+
+    /// evaluates a pipe. in tail position, the last stage isn't called but
+    /// handed back as a [TailCall] for the caller of the block to make.
+    fn evaluate_pipe(
+        &mut self,
+        expressions: &[ASTNode],
+        pipe_types: &[PipeType],
+        tail: bool,
+    ) -> Result<Outcome, EvaluationError> {
+        let mut curr_value = self.evaluate_expression(&expressions[0])?;
+        let last = pipe_types.len() - 1;
+
+        for (index, (expr, pipe_type)) in
+            expressions[1..].iter().zip(pipe_types).enumerate()
+        {
+            let closure = self.evaluate_expression(expr)?;
+            let destructure = matches!(pipe_type, PipeType::Destructure);
+            let transformed_input = Arguments::piped(curr_value, destructure)?;
+            if tail && index == last {
+                return Self::tail_call(
+                    &self.program,
+                    closure,
+                    transformed_input,
+                    Self::describe_callee(expr),
+                    &expr.span(),
+                );
+            }
             curr_value = match closure {
-                Value::RuntimeInvocation => {
-                    if transformed_input.len() != 2 {
-                        return Err(
-                            "Runtime invocation requires 2 arguments - parameters to runtime and runtime call"
-                                .to_string(),
-                        );
-                    } else {
-                        let runtime_parameters = &transformed_input[0];
-                        let runtime_call = &transformed_input[1];
-                        match (runtime_parameters, runtime_call) {
-                            (Value::Tuple(parameters), Value::String(call)) => {
-                                invoke_runtime(parameters.clone(), call.clone())?
-                            },
-                            _ => {
-                                return Err(
-                                    "Runtime parameters should be tuple and runtime call should be string"
-                                        .to_string(),
-                                )
-                            }
-                        }
-                    }
+                Value::RuntimeInvocation => pipe_into_runtime(
+                    &self.program,
+                    transformed_input.values,
+                    &expr.span(),
+                )?,
+                _ => Self::execute_closure_with(
+                    &self.program,
+                    transformed_input,
+                    &closure,
+                )
+                .map_err(|error| {
+                    error.in_call(
+                        &Self::describe_callee(expr),
+                        Some(expr.span()),
+                    )
+                })?,
+            };
+        }
+
+        Ok(Outcome::Done(curr_value))
+    }
+
+    /// evaluates a match, running the body of the first arm whose pattern
+    /// matches and whose guard (if any) gives T. in tail position, the body
+    /// is handed back as a [TailCall] instead.
+    fn evaluate_match(
+        &mut self,
+        subject: &ASTNode,
+        arms: &[Arm],
+        tail: bool,
+    ) -> Result<Outcome, EvaluationError> {
+        let value = self.evaluate_expression(subject)?;
+
+        for arm in arms {
+            let Some(captured) = matches(&arm.pattern, &value) else {
+                continue;
+            };
+            let guard = match &arm.guard {
+                Some(guard) => {
+                    Some((self.evaluate_expression(guard)?, guard.span()))
                 }
-                _ => Self::execute_closure(transformed_input, &closure)?,
+                None => None,
+            };
+            let body = self.evaluate_expression(&arm.body)?;
+            let outcome = Self::enter_arm(
+                &self.program,
+                captured,
+                guard,
+                (body, arm.body.span()),
+                tail,
+            )?;
+            if let Some(outcome) = outcome {
+                return Ok(outcome);
+            }
+        }
+
+        Err(ErrorKind::NoMatch(TypeDescriptor::of(&value)).into())
+    }
+
+    /// enter_arm
+    ///
+    /// Runs an arm of a match whose pattern matched, given the blocks of its
+    /// guard and body along with their spans. Gives `None` if the guard
+    /// turned the arm down, and otherwise what the body came to - which in
+    /// tail position is a [TailCall] for the caller to make.
+    pub(crate) fn enter_arm(
+        program: &SharedProgram,
+        captured: Arguments,
+        guard: Option<(Value, Span)>,
+        (body, span): (Value, Span),
+        tail: bool,
+    ) -> Result<Option<Outcome>, EvaluationError> {
+        if let Some((guard, guard_span)) = guard {
+            let passed =
+                Self::execute_closure_with(program, captured.clone(), &guard)
+                    .map_err(|error| {
+                    error.in_call("match guard", Some(guard_span.clone()))
+                })?;
+            match passed {
+                Value::Boolean(true) => {}
+                Value::Boolean(false) => return Ok(None),
+                other => {
+                    return Err(EvaluationError::new(ErrorKind::TypeMismatch(
+                        format!(
+                            "Match guards must produce a Boolean, not {}",
+                            TypeDescriptor::of(&other)
+                        ),
+                    ))
+                    .with_span(guard_span))
+                }
+            }
+        }
+
+        if tail {
+            return Ok(Some(Outcome::TailCall(TailCall {
+                closure: body,
+                arguments: captured,
+                callee: "match arm".to_string(),
+                span,
+            })));
+        }
+        Self::execute_closure_with(program, captured, &body)
+            .map(|value| Some(Outcome::Done(value)))
+            .map_err(|error| error.in_call("match arm", Some(span)))
+    }
+
+    /// the last stage of a pipe in tail position. runtime calls other than
+    /// `if` are made straight away, since they don't recurse.
+    pub(crate) fn tail_call(
+        program: &SharedProgram,
+        closure: Value,
+        arguments: Arguments,
+        callee: String,
+        span: &Span,
+    ) -> Result<Outcome, EvaluationError> {
+        if let Value::RuntimeInvocation = closure {
+            return match runtime_tail_call(&arguments.values, span) {
+                Some(call) => call.map(Outcome::TailCall),
+                None => pipe_into_runtime(program, arguments.values, span)
+                    .map(Outcome::Done),
             };
         }
 
-        Ok(curr_value)
+        Ok(Outcome::TailCall(TailCall {
+            closure,
+            arguments,
+            callee,
+            span: span.clone(),
+        }))
     }
 
-    pub fn execute_closure(
+    /// names a pipe stage for the call stack of an [EvaluationError]
+    pub(crate) fn describe_callee(expr: &ASTNode) -> String {
+        match expr {
+            ASTNode::Identifier(id, _) => format!("block `{}`", id),
+            _ => "anonymous block".to_string(),
+        }
+    }
+
+    /// executes a closure with fresh program state. prefer
+    /// [Interpreter::call_closure] when there is an interpreter at hand, so
+    /// that modules imported by the closure are shared with the program.
+    /// only the tests need it; hosts call closures through
+    /// [crate::BlockPipe::call_value].
+    #[cfg(test)]
+    pub(crate) fn execute_closure(
         parameters: Vec<Value>,
         closure: &Value,
     ) -> EvaluateResult {
-        if let Value::Closure(c_exps, env_image) = closure {
+        let program = SharedProgram::default();
+        Self::execute_closure_with(&program, parameters.into(), closure)
+    }
+
+    /// executes a closure, sharing this interpreter's program state
+    pub fn call_closure(
+        &self,
+        parameters: Vec<Value>,
+        closure: &Value,
+    ) -> EvaluateResult {
+        Self::execute_closure_with(&self.program, parameters.into(), closure)
+    }
+
+    /// executes a closure, along with the tail calls it leaves behind
+    pub(crate) fn execute_closure_with(
+        program: &SharedProgram,
+        parameters: Arguments,
+        closure: &Value,
+    ) -> EvaluateResult {
+        program.borrow_mut().meter.enter()?;
+        let result =
+            trampoline(closure.clone(), parameters, |closure, parameters| {
+                Self::enter_closure(program, parameters, closure)
+            });
+        program.borrow_mut().meter.exit();
+        result
+    }
+
+    /// runs the body of a closure, up to the call in its tail position
+    pub(crate) fn enter_closure(
+        program: &SharedProgram,
+        parameters: Arguments,
+        closure: &Value,
+    ) -> Result<Outcome, EvaluationError> {
+        if let Value::Closure(block, captured) = closure {
             // this is hacky, but we'll actually just create a new interpreter
             // to execute the closure in with a dummy root node
-            let mut new_interpreter = Interpreter::new(ASTNode::Block(vec![]));
+            let mut new_interpreter = Interpreter {
+                root_node: ASTNode::Block(vec![], 0..0),
+                env: Environment::new(),
+                program: program.clone(),
+                source: block.source.clone(),
+                scope: block.scope.clone(),
+            };
 
-            // the closure needs to execute in a new stack frame
-            new_interpreter.env.push_stack_frame();
+            // the closure needs to execute in a new stack frame, with a slot
+            // for every name the resolver found in it
+            new_interpreter.env.push_slots(block.scope.slots.clone());
 
-            // we'll then bind everything that it needs; conflicting identifiers
-            // from the environment get shadowed.
-            for (id, val) in env_image {
-                new_interpreter.env.bind(id.clone(), val.clone());
+            // then we'll fill in the slots: the closure needs to know how to
+            // recurse, so rec is bound to it, and the arguments are bound in
+            // the $0, $1, ... $n fashion, as well as by label. everything else
+            // starts out with the value that was captured, if any.
+            for (index, name) in block.scope.slots.iter().enumerate() {
+                let value = match name.as_str() {
+                    "rec" => Some(closure.clone()),
+                    _ => parameters
+                        .parameter(name)
+                        .or_else(|| captured.get(name).cloned()),
+                };
+                if let Some(value) = value {
+                    new_interpreter.env.assign(index, value);
+                }
             }
 
-            // the closure needs to know how to recurse, so we'll bind it to rec
-            new_interpreter.env.bind("rec".to_string(), closure.clone());
-
-            // then we'll have to bind arguments in the $0, $1, ... $n fashion.
-            new_interpreter.bind_parameters(parameters);
+            // the parameters the block declares (if any) are bound next,
+            // once they've been checked against the arguments
+            let mut statements = block.statements.as_slice();
+            if let Some((ASTNode::Parameters(declared, span), rest)) =
+                statements.split_first()
+            {
+                new_interpreter
+                    .declare(&parameters, declared)
+                    .map_err(|error| error.with_span(span.clone()))?;
+                statements = rest;
+            }
 
             // then we actually run the closure - the value that the last
             // statement evaluates to is the one that we return. note that empty
             // blocks just evaluate to the empty tuple.
-            let mut last_value = Value::Tuple(vec![]);
-            for expression in c_exps {
-                last_value = new_interpreter.evaluate(expression)?;
+            let Some((last, statements)) = statements.split_last() else {
+                return Ok(Outcome::Done(Value::Tuple(vec![])));
+            };
+            for expression in statements {
+                new_interpreter.evaluate_expression(expression)?;
             }
 
-            // lastly, we'll have to pop off the stack frame
-            new_interpreter.env.pop_stack_frame().expect("stack corruption");
-
-            // and we're done
-            Ok(last_value)
+            // if the last statement is a pipe, its last stage is left for our
+            // caller, so that loops written with rec don't grow the stack.
+            // the same goes for the arm a match in last place picks.
+            match last {
+                ASTNode::Pipe(expressions, pipe_types, span) => new_interpreter
+                    .evaluate_pipe(expressions, pipe_types, true)
+                    .map_err(|error| error.with_span(span.clone())),
+                ASTNode::Match(subject, arms, span) => new_interpreter
+                    .evaluate_match(subject, arms, true)
+                    .map_err(|error| error.with_span(span.clone())),
+                _ => {
+                    new_interpreter.evaluate_expression(last).map(Outcome::Done)
+                }
+            }
+        } else if let Value::CompiledClosure(closure) = closure {
+            vm::enter(program, closure, parameters)
+        } else if let Value::NativeClosure(closure) = closure {
+            native::enter(program, closure, parameters)
         } else {
-            Err("Passed non-closure for evaluation".to_string())
+            Err(ErrorKind::TypeMismatch(format!(
+                "Cannot pipe into a value of type {}",
+                TypeDescriptor::of(closure)
+            ))
+            .into())
         }
     }
-}
\ No newline at end of file
+}
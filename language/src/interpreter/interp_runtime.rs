@@ -1,110 +1,330 @@
-use super::{EvaluateResult, Interpreter, Value};
+use logos::Span;
+use serde::Serialize;
+use serde_json::{error::Category, ser::PrettyFormatter};
 
-pub fn invoke_runtime(parameters: Vec<Value>, call: String) -> EvaluateResult {
-    match call.as_str() {
-        "foo" => foo(parameters),
-        "binop_arith" => binop_arith(parameters),
-        "binop_cmp" => binop_cmp(parameters),
-        "strcat" => strcat(parameters),
-        "print" => print(parameters),
-        "if" => if_runtime_call(parameters),
-        _ => Err(format!("Unknown runtime call: {}", call)),
+use super::{
+    Arguments, Elements, ErrorKind, EvaluateResult, EvaluationError,
+    Interpreter, RuntimeCall, Shape, SharedProgram, TailCall, TypeDescriptor,
+    Value,
+};
+use crate::parser::Pattern;
+
+/// the calls a [RuntimeRegistry](super::RuntimeRegistry) holds by default.
+/// they check the types of their parameters themselves, to explain what
+/// they accept - with checks written against [Shape], which
+/// [check](super::check) makes ahead of time too.
+pub(super) fn builtin_calls() -> Vec<RuntimeCall> {
+    let any = |count| vec![Pattern::Wildcard; count];
+    let string = || Pattern::Type("String".to_string());
+    let boolean = Pattern::Type("Boolean".to_string());
+    let unit = Pattern::Tuple(vec![]);
+    [
+        RuntimeCall::new("foo", any(0), foo).returning(string()),
+        RuntimeCall::new("binop_arith", any(3), binop_arith),
+        RuntimeCall::new("binop_cmp", any(3), binop_cmp).returning(boolean),
+        RuntimeCall::new("strcat", any(2), strcat).returning(string()),
+        RuntimeCall::new("print", any(1), print).returning(unit),
+        RuntimeCall::new("json_parse", any(1), json_parse),
+        RuntimeCall::new("json_stringify", any(2), json_stringify)
+            .returning(string()),
+    ]
+    .into_iter()
+    .map(RuntimeCall::builtin)
+    .collect()
+}
+
+/// pipe_into_runtime
+///
+/// Handles piping into `plz`, which takes a tuple of parameters and the name
+/// of the runtime call to invoke with them, looked up in the program's
+/// registry. Errors raised by the call itself get a frame for it on their
+/// call stack. The branch picked by an `if` runs
+/// as part of the same program, sharing its modules and limits.
+pub fn pipe_into_runtime(
+    program: &SharedProgram,
+    arguments: Vec<Value>,
+    span: &Span,
+) -> EvaluateResult {
+    if arguments.len() != 2 {
+        return Err(
+            arity_mismatch("Runtime invocation", 2, arguments.len()).into()
+        );
+    }
+
+    if let Some(call) = runtime_tail_call(&arguments, span) {
+        let call = call?;
+        return Interpreter::execute_closure_with(
+            program,
+            call.arguments,
+            &call.closure,
+        )
+        .map_err(|error| error.in_call(&call.callee, Some(call.span)));
+    }
+
+    match (&arguments[0], &arguments[1]) {
+        (Value::Tuple(parameters), Value::String(call)) => {
+            // the registry isn't borrowed while the call runs
+            let registered = program.borrow().runtime.get(call);
+            let result = match registered {
+                Some(registered) => registered.invoke(parameters.clone()),
+                None => Err(ErrorKind::UnknownRuntimeCall(call.clone()).into()),
+            };
+            // what a call gives back is built all at once, so nothing inside
+            // it has been checked against the limits yet
+            let result = result.and_then(|value| {
+                program.borrow().meter.check_deep(&value)?;
+                Ok(value)
+            });
+            result.map_err(|error| {
+                error.in_call(&format!("plz \"{}\"", call), Some(span.clone()))
+            })
+        }
+        _ => Err(type_mismatch(
+            "Runtime parameters should be tuple and runtime call should be string",
+        )
+        .into()),
     }
 }
 
-fn foo(parameters: Vec<Value>) -> EvaluateResult {
-    Ok(Value::String("bar".to_string()))
+/// runtime_tail_call
+///
+/// Piping into `plz` in tail position: an `if` comes to a call to the branch
+/// it selects, which is left for the caller to make. Any other runtime call
+/// gives `None`, and is made with [pipe_into_runtime] as usual.
+pub fn runtime_tail_call(
+    arguments: &[Value],
+    span: &Span,
+) -> Option<Result<TailCall, EvaluationError>> {
+    let [Value::Tuple(parameters), Value::String(call)] = arguments else {
+        return None;
+    };
+    if call != "if" {
+        return None;
+    }
+
+    let callee = "plz \"if\"".to_string();
+    Some(match if_branch(parameters) {
+        Ok(branch) => Ok(TailCall {
+            closure: branch,
+            arguments: Arguments::default(),
+            callee,
+            span: span.clone(),
+        }),
+        Err(error) => Err(error.in_call(&callee, Some(span.clone()))),
+    })
 }
 
-fn binop_arith(parameters: Vec<Value>) -> EvaluateResult {
-    if parameters.len() != 3 {
-        return Err("binop_arith requires 2 numbers and an operation".to_string());
+pub(super) fn arity_mismatch(
+    call: &str,
+    expected: usize,
+    found: usize,
+) -> ErrorKind {
+    ErrorKind::ArityMismatch {
+        call: call.to_string(),
+        expected,
+        found,
+    }
+}
+
+pub(super) fn type_mismatch(message: &str) -> ErrorKind {
+    ErrorKind::TypeMismatch(message.to_string())
+}
+
+/// the operation a binop is given, if it's known
+fn operation<'v, T: Shape>(
+    op: &'v T,
+    message: &str,
+) -> Result<Option<&'v str>, ErrorKind> {
+    match op.type_name() {
+        Some("String") | None => Ok(op.text()),
+        _ => Err(type_mismatch(message)),
     }
+}
+
+fn numeric(name: Option<&str>) -> bool {
+    matches!(name, Some("Integer" | "Float"))
+}
+
+pub(super) fn check_binop_arith<T: Shape>(
+    parameters: &[T],
+) -> Result<(), ErrorKind> {
+    let op = operation(
+        &parameters[2],
+        "Third parameter must be an operation string",
+    )?;
 
+    let operand =
+        |value: &T| value.type_name().is_none() || numeric(value.type_name());
+    if !operand(&parameters[0]) || !operand(&parameters[1]) {
+        return Err(type_mismatch(
+            "binop_arith requires both operands to be numeric",
+        ));
+    }
+
+    match op {
+        Some(op) if !matches!(op, "+" | "-" | "*" | "/") => {
+            Err(ErrorKind::UnknownOperation(op.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn check_binop_cmp<T: Shape>(
+    parameters: &[T],
+) -> Result<(), ErrorKind> {
+    let op = operation(
+        &parameters[2],
+        "Third parameter must be a comparison operation string",
+    )?;
+
+    let left = parameters[0].type_name();
+    let right = parameters[1].type_name();
+    let comparable = match (left, right) {
+        (None, None) | (Some("Type"), Some("Type")) => true,
+        (None, other) | (other, None) => {
+            numeric(other) || other == Some("Type")
+        }
+        (left, right) => numeric(left) && numeric(right),
+    };
+    if !comparable {
+        return Err(type_mismatch(
+            "binop_cmp requires both operands to be numeric or both to be types",
+        ));
+    }
+
+    let types = left == Some("Type") || right == Some("Type");
+    match op {
+        Some(op) if types && !matches!(op, "==" | "!=") => {
+            Err(ErrorKind::UnknownOperation(format!("{} on types", op)))
+        }
+        Some(op) if !matches!(op, "<" | "<=" | ">" | ">=" | "==" | "!=") => {
+            Err(ErrorKind::UnknownOperation(op.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn check_strcat<T: Shape>(
+    parameters: &[T],
+) -> Result<(), ErrorKind> {
+    let string = |value: &T| matches!(value.type_name(), Some("String") | None);
+    if string(&parameters[0]) && string(&parameters[1]) {
+        Ok(())
+    } else {
+        Err(type_mismatch("strcat requires two strings"))
+    }
+}
+
+pub(super) fn check_json_parse<T: Shape>(
+    parameters: &[T],
+) -> Result<(), ErrorKind> {
+    match parameters[0].type_name() {
+        Some("String") | None => Ok(()),
+        _ => Err(type_mismatch("json_parse requires a string")),
+    }
+}
+
+/// json_stringify's options are either `()` or a record, as [json_indent]
+/// reads them
+pub(super) fn check_json_stringify<T: Shape>(
+    parameters: &[T],
+) -> Result<(), ErrorKind> {
+    let options = &parameters[1];
+    match (options.type_name(), options.elements()) {
+        (Some("Record") | None, _) => Ok(()),
+        (_, Some(Elements::Tuple([]))) => Ok(()),
+        _ => Err(type_mismatch("json_stringify options must be a record")),
+    }
+}
+
+fn foo(_parameters: Vec<Value>) -> EvaluateResult {
+    Ok(Value::String("bar".to_string()))
+}
+
+fn binop_arith(parameters: Vec<Value>) -> EvaluateResult {
+    check_binop_arith(&parameters)?;
     let left = &parameters[0];
     let right = &parameters[1];
-    let op = match &parameters[2] {
-        Value::String(op) => op.as_str(),
-        _ => return Err("Third parameter must be an operation string".to_string()),
+    let Value::String(op) = &parameters[2] else {
+        unreachable!("checked to be a string")
     };
 
     match (left, right) {
         (Value::Integer(left), Value::Integer(right)) => {
-            perform_arith_int(*left, *right, op).map(Value::Integer)
+            Ok(Value::Integer(perform_arith_int(*left, *right, op)?))
         }
         (Value::Float(left), Value::Float(right)) => {
-            perform_arith_float(*left, *right, op).map(Value::Float)
+            Ok(Value::Float(perform_arith_float(*left, *right, op)?))
         }
         (Value::Integer(left), Value::Float(right)) => {
-            perform_arith_float(*left as f64, *right, op).map(Value::Float)
+            Ok(Value::Float(perform_arith_float(*left as f64, *right, op)?))
         }
         (Value::Float(left), Value::Integer(right)) => {
-            perform_arith_float(*left, *right as f64, op).map(Value::Float)
+            Ok(Value::Float(perform_arith_float(*left, *right as f64, op)?))
         }
-        _ => Err("binop_arith requires both operands to be numeric".to_string()),
+        _ => unreachable!("checked to be numeric"),
     }
 }
 
-fn perform_arith_int(left: i64, right: i64, op: &str) -> Result<i64, String> {
+fn perform_arith_int(left: i64, right: i64, op: &str) -> Result<i64, ErrorKind> {
     match op {
         "+" => Ok(left + right),
         "-" => Ok(left - right),
         "*" => Ok(left * right),
         "/" => {
             if right == 0 {
-                Err("Division by zero".to_string())
+                Err(ErrorKind::DivisionByZero)
             } else {
                 Ok(left / right)
             }
         }
-        _ => Err(format!("Unknown arithmetic operation: {}", op)),
+        _ => Err(ErrorKind::UnknownOperation(op.to_string())),
     }
 }
 
-fn perform_arith_float(left: f64, right: f64, op: &str) -> Result<f64, String> {
+fn perform_arith_float(left: f64, right: f64, op: &str) -> Result<f64, ErrorKind> {
     match op {
         "+" => Ok(left + right),
         "-" => Ok(left - right),
         "*" => Ok(left * right),
         "/" => {
             if right == 0.0 {
-                Err("Division by zero".to_string())
+                Err(ErrorKind::DivisionByZero)
             } else {
                 Ok(left / right)
             }
         }
-        _ => Err(format!("Unknown arithmetic operation: {}", op)),
+        _ => Err(ErrorKind::UnknownOperation(op.to_string())),
     }
 }
 
 
 
 fn binop_cmp(parameters: Vec<Value>) -> EvaluateResult {
-    if parameters.len() != 3 {
-        return Err("binop_cmp requires 2 numbers and a comparison operation".to_string());
-    }
-
+    check_binop_cmp(&parameters)?;
     let left = &parameters[0];
     let right = &parameters[1];
-    let op = match &parameters[2] {
-        Value::String(op) => op.as_str(),
-        _ => return Err("Third parameter must be a comparison operation string".to_string()),
+    let Value::String(op) = &parameters[2] else {
+        unreachable!("checked to be a string")
     };
 
     match (left, right) {
         (Value::Integer(left), Value::Integer(right)) => 
-            perform_cmp_int(*left, *right, op).map(Value::Boolean),
+            Ok(Value::Boolean(perform_cmp_int(*left, *right, op)?)),
         (Value::Float(left), Value::Float(right)) => 
-            perform_cmp_float(*left, *right, op).map(Value::Boolean),
+            Ok(Value::Boolean(perform_cmp_float(*left, *right, op)?)),
         (Value::Integer(left), Value::Float(right)) => 
-            perform_cmp_float(*left as f64, *right, op).map(Value::Boolean),
+            Ok(Value::Boolean(perform_cmp_float(*left as f64, *right, op)?)),
         (Value::Float(left), Value::Integer(right)) => 
-            perform_cmp_float(*left, *right as f64, op).map(Value::Boolean),
-        _ => Err("binop_cmp requires both operands to be numeric".to_string()),
+            Ok(Value::Boolean(perform_cmp_float(*left, *right as f64, op)?)),
+        (Value::Type(left), Value::Type(right)) => {
+            Ok(Value::Boolean(perform_cmp_type(left, right, op)?))
+        }
+        _ => unreachable!("checked to be comparable"),
     }
 }
 
-fn perform_cmp_int(left: i64, right: i64, op: &str) -> Result<bool, String> {
+fn perform_cmp_int(left: i64, right: i64, op: &str) -> Result<bool, ErrorKind> {
     match op {
         "<" => Ok(left < right),
         "<=" => Ok(left <= right),
@@ -112,11 +332,11 @@ fn perform_cmp_int(left: i64, right: i64, op: &str) -> Result<bool, String> {
         ">=" => Ok(left >= right),
         "==" => Ok(left == right),
         "!=" => Ok(left != right),
-        _ => Err(format!("Unknown comparison operation: {}", op)),
+        _ => Err(ErrorKind::UnknownOperation(op.to_string())),
     }
 }
 
-fn perform_cmp_float(left: f64, right: f64, op: &str) -> Result<bool, String> {
+fn perform_cmp_float(left: f64, right: f64, op: &str) -> Result<bool, ErrorKind> {
     match op {
         "<" => Ok(left < right),
         "<=" => Ok(left <= right),
@@ -124,61 +344,160 @@ fn perform_cmp_float(left: f64, right: f64, op: &str) -> Result<bool, String> {
         ">=" => Ok(left >= right),
         "==" => Ok(left == right),
         "!=" => Ok(left != right),
-        _ => Err(format!("Unknown comparison operation: {}", op)),
+        _ => Err(ErrorKind::UnknownOperation(op.to_string())),
     }
 }
 
+fn perform_cmp_type(
+    left: &TypeDescriptor,
+    right: &TypeDescriptor,
+    op: &str,
+) -> Result<bool, ErrorKind> {
+    match op {
+        "==" => Ok(left == right),
+        "!=" => Ok(left != right),
+        _ => Err(ErrorKind::UnknownOperation(format!("{} on types", op))),
+    }
+}
 
 fn strcat(parameters: Vec<Value>) -> EvaluateResult {
-    if parameters.len() != 2 {
-        return Err("strcat requires 2 arguments".to_string());
-    } else {
-        let left = &parameters[0];
-        let right = &parameters[1];
-        match (left, right) {
-            (Value::String(left), Value::String(right)) => {
-                Ok(Value::String(format!("{}{}", left, right)))
-            }
-            _ => Err("strcat requires two strings".to_string()),
+    check_strcat(&parameters)?;
+    match (&parameters[0], &parameters[1]) {
+        (Value::String(left), Value::String(right)) => {
+            Ok(Value::String(format!("{}{}", left, right)))
         }
+        _ => unreachable!("checked to be strings"),
     }
 }
 
 fn print(parameters: Vec<Value>) -> EvaluateResult {
-    if parameters.len() != 1 {
-        return Err("print requires 1 argument".to_string());
-    } else {
-        let value = &parameters[0];
+    let value = &parameters[0];
+
+    println!("{}", value);
+    Ok(Value::Tuple(vec![]))
+}
+
+/// parses json, with objects becoming records and arrays tuples, and comes
+/// to `(ok: value)`. malformed json isn't an error, but comes to a record
+/// describing what's wrong with it and where instead, like
+/// `(error: (message: "invalid JSON syntax" line: 1 column: 2))`.
+fn json_parse(parameters: Vec<Value>) -> EvaluateResult {
+    check_json_parse(&parameters)?;
+    let Value::String(json) = &parameters[0] else {
+        unreachable!("checked to be a string")
+    };
+
+    Ok(match serde_json::from_str(json) {
+        Ok(value) => Value::Record(vec![("ok".to_string(), value)]),
+        Err(error) => {
+            let message = match error.classify() {
+                Category::Syntax => "invalid JSON syntax",
+                Category::Eof => "unexpected end of JSON",
+                Category::Data => "JSON value BlockPipe can't represent",
+                Category::Io => "JSON couldn't be read",
+            };
+            let error = Value::Record(vec![
+                ("message".to_string(), Value::String(message.to_string())),
+                ("line".to_string(), Value::Integer(error.line() as i64)),
+                ("column".to_string(), Value::Integer(error.column() as i64)),
+            ]);
+            Value::Record(vec![("error".to_string(), error)])
+        }
+    })
+}
+
+/// writes a value as json, compactly or pretty-printed as its options (the
+/// second parameter) ask
+fn json_stringify(parameters: Vec<Value>) -> EvaluateResult {
+    check_json_stringify(&parameters)?;
+    let indent = json_indent(&parameters[1])?;
+    let value = &parameters[0];
+
+    let json = match indent {
+        None => serde_json::to_string(value),
+        Some(indent) => {
+            let indent = " ".repeat(indent);
+            let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+            let mut json = vec![];
+            let mut serializer =
+                serde_json::Serializer::with_formatter(&mut json, formatter);
+            value
+                .serialize(&mut serializer)
+                .map(|_| String::from_utf8(json).expect("json is always utf-8"))
+        }
+    };
+    json.map(Value::String)
+        .map_err(|error| type_mismatch(&error.to_string()).into())
+}
 
-        println!("{}", value);
-        Ok(Value::Tuple(vec![]))
+/// how many spaces json_stringify indents by, if it pretty-prints at all.
+/// its options are either `()`, for compact json, or a record with any of
+/// `pretty: Boolean` and `indent: Integer` - pretty-printing with two spaces
+/// unless told otherwise.
+fn json_indent(options: &Value) -> Result<Option<usize>, ErrorKind> {
+    let fields = match options {
+        Value::Record(fields) => fields,
+        _ => return Ok(None),
+    };
+
+    let mut pretty = None;
+    let mut indent = None;
+    for (label, value) in fields {
+        match (label.as_str(), value) {
+            ("pretty", Value::Boolean(value)) => pretty = Some(*value),
+            ("indent", Value::Integer(value)) => {
+                indent = Some(usize::try_from(*value).map_err(|_| {
+                    type_mismatch("json_stringify indent can't be negative")
+                })?)
+            }
+            ("pretty", _) => {
+                return Err(type_mismatch(
+                    "json_stringify pretty must be a boolean",
+                ))
+            }
+            ("indent", _) => {
+                return Err(type_mismatch(
+                    "json_stringify indent must be an integer",
+                ))
+            }
+            (label, _) => {
+                return Err(type_mismatch(&format!(
+                    "Unknown json_stringify option: {}",
+                    label
+                )))
+            }
+        }
     }
+
+    Ok(match pretty.unwrap_or(indent.is_some()) {
+        true => Some(indent.unwrap_or(2)),
+        false => None,
+    })
 }
 
-fn if_runtime_call(parameters: Vec<Value>) -> EvaluateResult {
+/// the closure an `if` runtime call selects with its condition
+fn if_branch(parameters: &[Value]) -> EvaluateResult {
     if parameters.len() != 3 {
-        return Err("if requires 3 arguments".to_string());
-    } else {
-        let condition = &parameters[0];
-        let true_branch = &parameters[1];
-        let false_branch = &parameters[2];
-
-        // let's do a quick sanity check
-        if let (Value::Boolean(_), Value::Closure(_, _), Value::Closure(_, _)) =
-            (condition, true_branch, false_branch)
-        {
-            // then we can conditionally execute one of the closures
-            match condition {
-                Value::Boolean(true) => {
-                    Interpreter::execute_closure(vec![], true_branch)
-                }
-                Value::Boolean(false) => {
-                    Interpreter::execute_closure(vec![], false_branch)
-                }
-                _ => unreachable!(),
-            }
+        return Err(arity_mismatch("if", 3, parameters.len()).into());
+    }
+
+    let condition = &parameters[0];
+    let true_branch = &parameters[1];
+    let false_branch = &parameters[2];
+
+    // let's do a quick sanity check
+    if let (Value::Boolean(condition), true, true) = (
+        condition,
+        true_branch.is_closure(),
+        false_branch.is_closure(),
+    ) {
+        // then we can pick one of the closures
+        if *condition {
+            Ok(true_branch.clone())
         } else {
-            return Err("if requires boolean and two closures".to_string());
+            Ok(false_branch.clone())
         }
+    } else {
+        Err(type_mismatch("if requires boolean and two closures").into())
     }
 }
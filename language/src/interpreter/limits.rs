@@ -0,0 +1,144 @@
+use core::fmt;
+use std::fmt::{Display, Formatter};
+
+use super::{ErrorKind, EvaluationError, Value};
+
+/// how deep calls may nest unless told otherwise. a release build stays well
+/// clear of overflowing a 1MB stack (the size wasm gets) at this depth.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// caps on what a program may use while it runs. anything left as `None` is
+/// unlimited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// how many block invocations may be in progress at once. calls in tail
+    /// position replace their caller, so they don't count.
+    pub max_call_depth: Option<usize>,
+    /// how many steps the program may take: expressions evaluated by the
+    /// tree walker, or instructions executed by the virtual machine
+    pub max_steps: Option<usize>,
+    pub max_tuple_size: Option<usize>,
+    /// in bytes
+    pub max_string_length: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            max_steps: None,
+            max_tuple_size: None,
+            max_string_length: None,
+        }
+    }
+}
+
+/// the limit an [ErrorKind::LimitExceeded] is about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    CallDepth,
+    Steps,
+    TupleSize,
+    StringLength,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::CallDepth => write!(f, "Call depth"),
+            Limit::Steps => write!(f, "Step"),
+            Limit::TupleSize => write!(f, "Tuple size"),
+            Limit::StringLength => write!(f, "String length"),
+        }
+    }
+}
+
+/// keeps track of what a program has used so far, against its [Limits]
+#[derive(Debug, Default)]
+pub struct Meter {
+    pub limits: Limits,
+    depth: usize,
+    steps: usize,
+}
+
+fn exceeded(limit: Limit, max: usize) -> EvaluationError {
+    ErrorKind::LimitExceeded { limit, max }.into()
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Meter {
+        Meter {
+            limits,
+            ..Meter::default()
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), EvaluationError> {
+        self.steps += 1;
+        match self.limits.max_steps {
+            Some(max) if self.steps > max => Err(exceeded(Limit::Steps, max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// records that a block is being invoked. every successful call must be
+    /// matched by one to [Meter::exit] once the block is done.
+    pub fn enter(&mut self) -> Result<(), EvaluationError> {
+        match self.limits.max_call_depth {
+            Some(max) if self.depth >= max => {
+                Err(exceeded(Limit::CallDepth, max))
+            }
+            _ => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn exit(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// makes sure a value the program has just produced is within limits.
+    /// values the program builds are only ever built up from smaller ones
+    /// that were checked already, so there's no need to look inside them -
+    /// runtime calls can build whole values at once, and are checked with
+    /// [Meter::check_deep] instead.
+    pub fn check(&self, value: &Value) -> Result<(), EvaluationError> {
+        match value {
+            Value::Tuple(values) => match self.limits.max_tuple_size {
+                Some(max) if values.len() > max => {
+                    Err(exceeded(Limit::TupleSize, max))
+                }
+                _ => Ok(()),
+            },
+            Value::Record(fields) => match self.limits.max_tuple_size {
+                Some(max) if fields.len() > max => {
+                    Err(exceeded(Limit::TupleSize, max))
+                }
+                _ => Ok(()),
+            },
+            Value::String(string) => match self.limits.max_string_length {
+                Some(max) if string.len() > max => {
+                    Err(exceeded(Limit::StringLength, max))
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// makes sure a value and every value inside it is within limits
+    pub fn check_deep(&self, value: &Value) -> Result<(), EvaluationError> {
+        self.check(value)?;
+        match value {
+            Value::Tuple(values) => {
+                values.iter().try_for_each(|value| self.check_deep(value))
+            }
+            Value::Record(fields) => fields
+                .iter()
+                .try_for_each(|(_, value)| self.check_deep(value)),
+            _ => Ok(()),
+        }
+    }
+}
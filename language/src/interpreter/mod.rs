@@ -1,15 +1,36 @@
+mod check;
+mod convert;
 mod environment;
+mod error;
 mod value;
 mod interp;
 #[cfg(test)]
 mod tests;
 mod interp_runtime;
+mod limits;
+mod module;
+mod pattern;
+mod program;
+mod registry;
+mod resolver;
+mod serialize;
+mod shape;
+mod tail;
 
+pub use check::*;
 pub use environment::*;
+pub use error::*;
 pub use value::*;
 pub use interp::*;
 pub use interp_runtime::*;
+pub use limits::*;
+pub use module::*;
+pub use pattern::*;
+pub use program::*;
+pub use registry::*;
+pub use resolver::*;
+pub use shape::*;
+pub use tail::*;
 
-/// results and errors of evaluation operations. these types do need to be refined.
-pub type EvaluateResult = Result<Value, EvaluationError>;
-pub type EvaluationError = String;
\ No newline at end of file
+/// results of evaluation operations. see [EvaluationError] for the errors.
+pub type EvaluateResult = Result<Value, EvaluationError>;
\ No newline at end of file
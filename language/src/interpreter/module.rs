@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{
+    Block, ErrorKind, EvaluateResult, EvaluationError, SharedProgram, Source,
+    Value,
+};
+use crate::parser::ASTNode;
+use crate::Engine;
+
+/// loads the modules brought in by `import`, evaluating each file at most
+/// once and refusing to follow cycles. it's part of the state a program
+/// shares, so every part of the program sees the same modules.
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    // evaluated modules, by canonical path
+    cache: HashMap<PathBuf, Value>,
+    // the modules currently being evaluated, outermost first
+    loading: Vec<PathBuf>,
+    // what modules are evaluated with
+    pub engine: Engine,
+}
+
+impl ModuleLoader {
+    pub fn new() -> ModuleLoader {
+        ModuleLoader::default()
+    }
+
+    pub fn with_engine(engine: Engine) -> ModuleLoader {
+        ModuleLoader {
+            engine,
+            ..ModuleLoader::default()
+        }
+    }
+
+    /// import
+    ///
+    /// Evaluates the root block of the module at the given path, or fetches
+    /// it from the cache if it has been imported before. The module is a
+    /// closure over every binding made by its root block, so it can be
+    /// pasted to bring them into scope, have them read off it with `.name`,
+    /// or be piped into like any other block. Its blocks hold on to the
+    /// module's path and source, so the errors they raise once the importer
+    /// calls them are still rendered against the module.
+    pub fn import(program: &SharedProgram, path: &str) -> EvaluateResult {
+        let import_failed = |reason: String| ErrorKind::ImportFailed {
+            path: path.to_string(),
+            reason,
+        };
+
+        let canonical = fs::canonicalize(path)
+            .map_err(|error| import_failed(error.to_string()))?;
+
+        if let Some(module) = program.borrow().modules.cache.get(&canonical) {
+            return Ok(module.clone());
+        }
+
+        if let Some(start) = program
+            .borrow()
+            .modules
+            .loading
+            .iter()
+            .position(|loading| *loading == canonical)
+        {
+            let chain = program.borrow().modules.loading[start..]
+                .iter()
+                .chain([&canonical])
+                .map(|module| module.display().to_string())
+                .collect();
+            return Err(ErrorKind::CyclicImport(chain).into());
+        }
+
+        let source = fs::read_to_string(&canonical)
+            .map_err(|error| import_failed(error.to_string()))?;
+
+        program.borrow_mut().modules.loading.push(canonical.clone());
+        let result = evaluate_module(program, &canonical, &source);
+        program.borrow_mut().modules.loading.pop();
+
+        // the module's errors can only be rendered against its own source
+        let module = result.map_err(|error| ErrorKind::ModuleError {
+            path: canonical.display().to_string(),
+            report: error.render(&source),
+        })?;
+
+        program
+            .borrow_mut()
+            .modules
+            .cache
+            .insert(canonical, module.clone());
+        Ok(module)
+    }
+}
+
+fn evaluate_module(
+    program: &SharedProgram,
+    path: &Path,
+    source: &str,
+) -> EvaluateResult {
+    let mut root = crate::parse_from_string(source)?;
+    if let Some(directory) = path.parent() {
+        resolve_imports(&mut root, directory);
+    }
+
+    let statements = match &root {
+        ASTNode::Block(statements, _) => statements.clone(),
+        other => {
+            return Err(EvaluationError::new(ErrorKind::TypeMismatch(
+                "The root of a module must be a block".to_string(),
+            ))
+            .with_span(other.span()))
+        }
+    };
+
+    let source = Rc::new(Source::new(path.display().to_string(), source));
+    let image = run_root_block(program, root, &statements, &[], &source)?;
+    Ok(Value::Closure(
+        Rc::new(Block::new(&statements, &source)),
+        Rc::new(image),
+    ))
+}
+
+/// runs the statements of a root block at the top level of a fresh program,
+/// with only the builtins and the given globals around, and grabs every
+/// binding they made. the engine is the one the program state was set up
+/// with, and the blocks they create are taken to be written in the given
+/// source.
+pub(crate) fn run_root_block(
+    program: &SharedProgram,
+    root: ASTNode,
+    statements: &[ASTNode],
+    globals: &[(String, Value)],
+    source: &Rc<Source>,
+) -> Result<HashMap<String, Value>, EvaluationError> {
+    let engine = program.borrow().modules.engine;
+    match engine {
+        Engine::TreeWalking => {
+            let mut interpreter = crate::toplevel_interpreter(root);
+            interpreter.program = program.clone();
+            interpreter.source = source.clone();
+            for (name, value) in globals {
+                interpreter.env.bind(name.clone(), value.clone());
+            }
+            for statement in statements {
+                interpreter.evaluate(statement)?;
+            }
+            Ok(interpreter.env.image())
+        }
+        Engine::Bytecode => {
+            let mut machine = crate::toplevel_machine(root);
+            machine.program = program.clone();
+            machine.source = source.clone();
+            for (name, value) in globals {
+                machine.env.bind(name.clone(), value.clone());
+            }
+            for statement in statements {
+                machine.evaluate(statement)?;
+            }
+            Ok(machine.env.image())
+        }
+    }
+}
+
+/// resolve_imports
+///
+/// Rewrites the path of every import in the tree to be relative to the given
+/// directory, since a file's imports are resolved against the file itself
+/// rather than wherever the program happens to be run from.
+pub fn resolve_imports(node: &mut ASTNode, directory: &Path) {
+    match node {
+        ASTNode::Import(path, _) => {
+            *path = directory.join(&*path).to_string_lossy().into_owned();
+        }
+        ASTNode::Block(nodes, _)
+        | ASTNode::Tuple(nodes, _)
+        | ASTNode::Pipe(nodes, _, _) => {
+            for node in nodes {
+                resolve_imports(node, directory);
+            }
+        }
+        ASTNode::Paste(operand, _)
+        | ASTNode::Type(operand, _)
+        | ASTNode::Binding((_, operand), _)
+        | ASTNode::Annotated(operand, _, _)
+        | ASTNode::Access(operand, _, _) => resolve_imports(operand, directory),
+        ASTNode::Record(fields, _) => {
+            for (_, node) in fields {
+                resolve_imports(node, directory);
+            }
+        }
+        ASTNode::Match(subject, arms, _) => {
+            resolve_imports(subject, directory);
+            for arm in arms {
+                if let Some(guard) = &mut arm.guard {
+                    resolve_imports(guard, directory);
+                }
+                resolve_imports(&mut arm.body, directory);
+            }
+        }
+        ASTNode::Identifier(..)
+        | ASTNode::Parameters(..)
+        | ASTNode::Literal(..)
+        | ASTNode::Error(_) => {}
+    }
+}
@@ -0,0 +1,203 @@
+use logos::Span;
+
+use super::{Arguments, ErrorKind, TypeDescriptor, Value, Warning};
+use crate::parser::{ASTNode, Arm, LiteralVariant, Pattern};
+
+/// matches
+///
+/// Tests a value against a pattern, giving back what the pattern captured if
+/// it matches. The captures are labelled, so the blocks of the arm can refer
+/// to them as `$name`, as well as by position.
+pub fn matches(pattern: &Pattern, value: &Value) -> Option<Arguments> {
+    let mut captured = Arguments::default();
+    capture(pattern, value, &mut captured).then_some(captured)
+}
+
+fn capture(pattern: &Pattern, value: &Value, captured: &mut Arguments) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Capture(name), value) => {
+            captured.values.push(value.clone());
+            captured.labels.push(name.clone());
+            true
+        }
+        (Pattern::Literal(literal), value) => literal_value(literal) == *value,
+        (Pattern::Type(name), value) => {
+            type_name(&TypeDescriptor::of(value)) == name
+        }
+        (Pattern::Tuple(patterns), Value::Tuple(values)) => {
+            patterns.len() == values.len()
+                && patterns
+                    .iter()
+                    .zip(values)
+                    .all(|(pattern, value)| capture(pattern, value, captured))
+        }
+        // records only need the fields the pattern mentions
+        (Pattern::Record(patterns), Value::Record(fields)) => {
+            patterns.iter().all(|(label, pattern)| {
+                fields
+                    .iter()
+                    .find(|(field, _)| field == label)
+                    .is_some_and(|(_, value)| capture(pattern, value, captured))
+            })
+        }
+        (Pattern::Tuple(_) | Pattern::Record(_), _) => false,
+    }
+}
+
+fn literal_value(literal: &LiteralVariant) -> Value {
+    match literal {
+        LiteralVariant::IntegerLiteral(i) => Value::Integer(*i),
+        LiteralVariant::FloatLiteral(f) => Value::Float(*f),
+        LiteralVariant::StringLiteral(s) => Value::String(s.clone()),
+        LiteralVariant::BooleanLiteral(b) => Value::Boolean(*b),
+    }
+}
+
+/// conforms
+///
+/// Checks a value against the type it was annotated with, which is a pattern
+/// that captures nothing.
+pub fn conforms(annotation: &Pattern, value: &Value) -> Result<(), ErrorKind> {
+    match matches(annotation, value) {
+        Some(_) => Ok(()),
+        None => Err(ErrorKind::AnnotationMismatch {
+            expected: annotation.clone(),
+            found: TypeDescriptor::of(value),
+        }),
+    }
+}
+
+/// the name a type pattern uses for values of the given type
+pub(super) fn type_name(ty: &TypeDescriptor) -> &'static str {
+    match ty {
+        TypeDescriptor::Integer => "Integer",
+        TypeDescriptor::Float => "Float",
+        TypeDescriptor::String => "String",
+        TypeDescriptor::Boolean => "Boolean",
+        TypeDescriptor::Tuple(_) => "Tuple",
+        TypeDescriptor::Record(_) => "Record",
+        TypeDescriptor::Closure => "Closure",
+        TypeDescriptor::RuntimeInvocation => "RuntimeInvocation",
+        TypeDescriptor::Type => "Type",
+    }
+}
+
+/// whether a pattern matches every value of the given type
+fn covers(pattern: &Pattern, ty: &TypeDescriptor) -> bool {
+    match (pattern, ty) {
+        (Pattern::Wildcard | Pattern::Capture(_), _) => true,
+        (Pattern::Literal(_), _) => false,
+        (Pattern::Type(name), ty) => type_name(ty) == name,
+        (Pattern::Tuple(patterns), TypeDescriptor::Tuple(types)) => {
+            patterns.len() == types.len()
+                && patterns.iter().zip(types).all(|(p, ty)| covers(p, ty))
+        }
+        (Pattern::Record(patterns), TypeDescriptor::Record(fields)) => {
+            patterns.iter().all(|(label, pattern)| {
+                fields
+                    .iter()
+                    .find(|(field, _)| field == label)
+                    .is_some_and(|(_, ty)| covers(pattern, ty))
+            })
+        }
+        (Pattern::Tuple(_) | Pattern::Record(_), _) => false,
+    }
+}
+
+/// whether the arms of a match handle every value of the given type. guarded
+/// arms might not run, so only the unguarded ones count.
+pub fn exhaustive(arms: &[Arm], ty: &TypeDescriptor) -> bool {
+    let unguarded = || arms.iter().filter(|arm| arm.guard.is_none());
+    if unguarded().any(|arm| covers(&arm.pattern, ty)) {
+        return true;
+    }
+
+    // T and F between them are every Boolean
+    let literal = |b| Pattern::Literal(LiteralVariant::BooleanLiteral(b));
+    *ty == TypeDescriptor::Boolean
+        && [true, false]
+            .into_iter()
+            .all(|b| unguarded().any(|arm| arm.pattern == literal(b)))
+}
+
+/// the type an expression is known to have before it is evaluated, if any
+fn known_type(node: &ASTNode) -> Option<TypeDescriptor> {
+    match node {
+        ASTNode::Literal(literal, _) => {
+            Some(TypeDescriptor::of(&literal_value(literal)))
+        }
+        ASTNode::Tuple(elements, _) => elements
+            .iter()
+            .map(known_type)
+            .collect::<Option<_>>()
+            .map(TypeDescriptor::Tuple),
+        ASTNode::Record(fields, _) => fields
+            .iter()
+            .map(|(label, value)| Some((label.clone(), known_type(value)?)))
+            .collect::<Option<_>>()
+            .map(TypeDescriptor::Record),
+        ASTNode::Block(..) => Some(TypeDescriptor::Closure),
+        ASTNode::Type(..) => Some(TypeDescriptor::Type),
+        _ => None,
+    }
+}
+
+/// match_warnings
+///
+/// Looks through an expression for matches on values whose type is known
+/// ahead of time, warning about each one that doesn't handle every value of
+/// that type. Matches on values of unknown type are left alone.
+pub fn match_warnings(node: &ASTNode) -> Vec<Warning> {
+    let mut warnings = vec![];
+    walk(node, &mut warnings);
+    warnings
+}
+
+fn walk(node: &ASTNode, warnings: &mut Vec<Warning>) {
+    match node {
+        ASTNode::Match(subject, arms, span) => {
+            walk(subject, warnings);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    walk(guard, warnings);
+                }
+                walk(&arm.body, warnings);
+            }
+            if let Some(ty) = known_type(subject) {
+                if !exhaustive(arms, &ty) {
+                    warnings.push(non_exhaustive(&ty, span));
+                }
+            }
+        }
+        ASTNode::Tuple(nodes, _)
+        | ASTNode::Block(nodes, _)
+        | ASTNode::Pipe(nodes, _, _) => {
+            for node in nodes {
+                walk(node, warnings);
+            }
+        }
+        ASTNode::Record(fields, _) => {
+            for (_, value) in fields {
+                walk(value, warnings);
+            }
+        }
+        ASTNode::Binding((_, operand), _)
+        | ASTNode::Type(operand, _)
+        | ASTNode::Paste(operand, _)
+        | ASTNode::Annotated(operand, _, _)
+        | ASTNode::Access(operand, _, _) => walk(operand, warnings),
+        ASTNode::Identifier(..)
+        | ASTNode::Parameters(..)
+        | ASTNode::Literal(..)
+        | ASTNode::Import(..)
+        | ASTNode::Error(_) => {}
+    }
+}
+
+fn non_exhaustive(ty: &TypeDescriptor, span: &Span) -> Warning {
+    Warning {
+        message: format!("Match on a value of type {} is not exhaustive", ty),
+        span: span.clone(),
+    }
+}
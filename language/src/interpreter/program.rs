@@ -0,0 +1,28 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{Meter, ModuleLoader, RuntimeRegistry};
+use crate::Engine;
+
+/// the state of a program, shared by every interpreter (or machine) that
+/// ends up evaluating a part of it
+pub type SharedProgram = Rc<RefCell<ProgramState>>;
+
+/// what everything that runs as part of a program shares, imported modules
+/// included: the modules it has loaded, what it has used of its limits, and
+/// the runtime calls it can make.
+#[derive(Debug, Default)]
+pub struct ProgramState {
+    pub modules: ModuleLoader,
+    pub meter: Meter,
+    pub runtime: RuntimeRegistry,
+}
+
+impl ProgramState {
+    /// a program whose modules are evaluated with the given engine
+    pub fn with_engine(engine: Engine) -> ProgramState {
+        ProgramState {
+            modules: ModuleLoader::with_engine(engine),
+            ..ProgramState::default()
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use super::{builtin_calls, conforms, ErrorKind, EvaluateResult, Shape, Value};
+use crate::parser::Pattern;
+
+/// the signature of the native functions behind runtime calls. they're only
+/// ever given as many parameters as they declare, of the declared types.
+pub type NativeFunction = dyn Fn(Vec<Value>) -> EvaluateResult;
+
+/// a native function that programs can invoke by piping into `plz`, along
+/// with the types of the parameters it takes and of what it gives back -
+/// written like type annotations, so `_` accepts anything.
+#[derive(Clone)]
+pub struct RuntimeCall {
+    pub name: String,
+    pub parameters: Vec<Pattern>,
+    pub output: Pattern,
+    function: Rc<NativeFunction>,
+    // whether this is one of the calls every program gets by default, which
+    // the checker knows more about than their signatures let on
+    builtin: bool,
+}
+
+impl RuntimeCall {
+    pub fn new(
+        name: &str,
+        parameters: Vec<Pattern>,
+        function: impl Fn(Vec<Value>) -> EvaluateResult + 'static,
+    ) -> RuntimeCall {
+        RuntimeCall {
+            name: name.to_string(),
+            parameters,
+            output: Pattern::Wildcard,
+            function: Rc::new(function),
+            builtin: false,
+        }
+    }
+
+    /// declares the type of what the call gives back
+    pub fn returning(mut self, output: Pattern) -> RuntimeCall {
+        self.output = output;
+        self
+    }
+
+    pub(super) fn builtin(mut self) -> RuntimeCall {
+        self.builtin = true;
+        self
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// checks that the call is given as many parameters as it declares, of
+    /// the declared types
+    pub(super) fn accepts<T: Shape>(
+        &self,
+        parameters: &[T],
+    ) -> Result<(), ErrorKind> {
+        if parameters.len() != self.parameters.len() {
+            return Err(ErrorKind::ArityMismatch {
+                call: self.name.clone(),
+                expected: self.parameters.len(),
+                found: parameters.len(),
+            });
+        }
+        for (declared, parameter) in self.parameters.iter().zip(parameters) {
+            parameter.conform(declared)?;
+        }
+        Ok(())
+    }
+
+    /// invoke
+    ///
+    /// Calls the native function, after checking that it was given as many
+    /// parameters as it declares, of the declared types - and checks that
+    /// what it gives back has the type it declares too.
+    pub fn invoke(&self, parameters: Vec<Value>) -> EvaluateResult {
+        self.accepts(&parameters)?;
+        let result = (self.function)(parameters)?;
+        conforms(&self.output, &result)?;
+        Ok(result)
+    }
+}
+
+impl fmt::Debug for RuntimeCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parameters: Vec<_> =
+            self.parameters.iter().map(Pattern::to_string).collect();
+        write!(
+            f,
+            "{}({}) -> {}",
+            self.name,
+            parameters.join(" "),
+            self.output
+        )
+    }
+}
+
+/// the runtime calls a program can make through `plz`, by name. a default
+/// registry holds the builtin calls, like `binop_arith` and `print`; hosts
+/// can add calls of their own to it, or sandbox programs by leaving calls
+/// out. `if` is part of the language rather than a call, so it's always
+/// there.
+#[derive(Clone)]
+pub struct RuntimeRegistry {
+    calls: HashMap<String, Rc<RuntimeCall>>,
+}
+
+impl RuntimeRegistry {
+    /// a registry without any calls at all
+    pub fn empty() -> RuntimeRegistry {
+        RuntimeRegistry {
+            calls: HashMap::new(),
+        }
+    }
+
+    /// adds a call, replacing any of the same name
+    pub fn register(&mut self, call: RuntimeCall) {
+        self.calls.insert(call.name.clone(), Rc::new(call));
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Rc<RuntimeCall>> {
+        self.calls.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Rc<RuntimeCall>> {
+        self.calls.get(name).cloned()
+    }
+
+    /// the names of every call, in alphabetical order
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.calls.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+impl Default for RuntimeRegistry {
+    fn default() -> Self {
+        let mut registry = RuntimeRegistry::empty();
+        for call in builtin_calls() {
+            registry.register(call);
+        }
+        registry
+    }
+}
+
+impl fmt::Debug for RuntimeRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut calls: Vec<_> = self.calls.values().collect();
+        calls.sort_by(|a, b| a.name.cmp(&b.name));
+        f.debug_list().entries(calls).finish()
+    }
+}
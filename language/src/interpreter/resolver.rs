@@ -0,0 +1,384 @@
+use std::{collections::HashMap, rc::Rc};
+
+use logos::Span;
+
+use super::{Environment, ErrorKind, EvaluationError, Source};
+use crate::parser::{ASTNode, Parameters};
+
+/// where the value of a name lives, as worked out before evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    /// the slot at `index` of the frame `depth` frames out from the innermost
+    Slot(usize, usize),
+    /// looked up by name - for names bound by the top level code itself,
+    /// which may or may not have happened by the time they are used
+    Dynamic,
+}
+
+/// a block, along with what the resolver worked out about its body and the
+/// source it was written in
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub statements: Vec<ASTNode>,
+    pub scope: Rc<Scope>,
+    pub source: Rc<Source>,
+}
+
+impl Block {
+    /// resolves a block on its own, as if it were written at the top level
+    /// of the given source
+    pub fn new(statements: &[ASTNode], source: &Rc<Source>) -> Block {
+        resolve_block(statements, source).block
+    }
+
+    /// the parameters the block declares, if it declares any
+    pub fn parameters(&self) -> Option<&Parameters> {
+        match self.statements.first() {
+            Some(ASTNode::Parameters(parameters, _)) => Some(parameters),
+            _ => None,
+        }
+    }
+}
+
+/// the names a block captures when it is created: those used by the block,
+/// or by the blocks within it
+pub fn captures(statements: &[ASTNode]) -> Vec<String> {
+    resolve_block(statements, &Rc::default()).captures
+}
+
+/// a block that some code creates, and what it captures from that code
+#[derive(Debug, PartialEq)]
+pub struct Definition {
+    pub block: Rc<Block>,
+    pub captures: Vec<(String, Address)>,
+}
+
+/// the resolver's findings for a piece of code, with identifiers, bindings
+/// and blocks looked up by where their span starts.
+#[derive(Debug, Default, PartialEq)]
+pub struct Scope {
+    /// the names given slots in a block's frame, in slot order
+    pub slots: Vec<String>,
+    addresses: HashMap<usize, Address>,
+    blocks: HashMap<usize, Definition>,
+}
+
+impl Scope {
+    pub fn address(&self, span: &Span) -> Option<&Address> {
+        self.addresses.get(&span.start)
+    }
+
+    pub fn definition(&self, span: &Span) -> Option<&Definition> {
+        self.blocks.get(&span.start)
+    }
+}
+
+/// whether a name may be given a value by an invocation of a block
+fn is_parameter(name: &str) -> bool {
+    name == "rec" || name.starts_with('$')
+}
+
+/// whether a name is given a value by every invocation of a block, however
+/// many arguments it's given - so blocks never need to capture it
+pub fn always_given(name: &str) -> bool {
+    matches!(name, "rec" | "$n" | "$@")
+        || name.starts_with('$') && name.ends_with("..")
+}
+
+fn insert(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|existing| existing == name) {
+        names.push(name.to_string());
+    }
+}
+
+/// what a block needs from the code that creates it
+struct Resolved {
+    block: Block,
+    // every name the block (or a block within it) uses, which it captures
+    // when it is created
+    captures: Vec<String>,
+    // the names that must be bound outside the block, with where they are
+    // first used
+    free: Vec<(String, Span)>,
+}
+
+/// everything found in a block's body (or top level expression), not
+/// counting the blocks within it
+#[derive(Default)]
+struct Findings {
+    bound: Vec<String>,
+    identifiers: Vec<(String, Span)>,
+    bindings: Vec<(String, Span)>,
+    blocks: Vec<(Span, Resolved)>,
+    pastes: bool,
+    // what the blocks found were written in
+    source: Rc<Source>,
+}
+
+impl Findings {
+    fn collect(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Identifier(id, span) => {
+                self.identifiers.push((id.clone(), span.clone()))
+            }
+            ASTNode::Binding((id, value), span) => {
+                self.collect(value);
+                insert(&mut self.bound, id);
+                self.bindings.push((id.clone(), span.clone()));
+            }
+            ASTNode::Parameters(parameters, _) => {
+                for (id, span) in parameters.declared() {
+                    insert(&mut self.bound, id);
+                    self.bindings.push((id.clone(), span.clone()));
+                }
+            }
+            ASTNode::Block(statements, span) => {
+                let resolved = resolve_block(statements, &self.source);
+                self.blocks.push((span.clone(), resolved))
+            }
+            ASTNode::Paste(operand, _) => {
+                self.pastes = true;
+                self.collect(operand);
+            }
+            ASTNode::Tuple(nodes, _) | ASTNode::Pipe(nodes, _, _) => {
+                for node in nodes {
+                    self.collect(node);
+                }
+            }
+            // labels name fields, not values in scope
+            ASTNode::Record(fields, _) => {
+                for (_, value) in fields {
+                    self.collect(value);
+                }
+            }
+            ASTNode::Type(operand, _)
+            | ASTNode::Annotated(operand, _, _)
+            | ASTNode::Access(operand, _, _) => self.collect(operand),
+            // the captures of a pattern are parameters of the arm's blocks
+            ASTNode::Match(subject, arms, _) => {
+                self.collect(subject);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        self.collect(guard);
+                    }
+                    self.collect(&arm.body);
+                }
+            }
+            ASTNode::Literal(..) | ASTNode::Import(..) | ASTNode::Error(_) => {}
+        }
+    }
+
+    /// the names used here or by the blocks within, with where they're
+    /// used, in the order they appear
+    fn uses(&self) -> Vec<(String, Span)> {
+        let nested = self
+            .blocks
+            .iter()
+            .flat_map(|(_, resolved)| resolved.free.iter().cloned());
+        let mut uses: Vec<_> =
+            self.identifiers.iter().cloned().chain(nested).collect();
+        uses.sort_by_key(|(_, span)| span.start);
+        uses
+    }
+}
+
+/// the definition of a block, created by code that binds the given names.
+/// besides the names the block uses, closures over it capture everything
+/// bound by the code that creates them, so pasting one brings those
+/// bindings into scope.
+fn define(
+    resolved: Resolved,
+    bound: &[String],
+    address: impl Fn(&str) -> Address,
+) -> Definition {
+    let mut names = resolved.captures;
+    for name in bound {
+        insert(&mut names, name);
+    }
+    let captures = names
+        .into_iter()
+        .map(|name| {
+            let address = address(&name);
+            (name, address)
+        })
+        .collect();
+    Definition {
+        block: Rc::new(resolved.block),
+        captures,
+    }
+}
+
+/// the names a block (or top level expression) binds itself, not counting
+/// the blocks within it
+pub fn bound_names(statements: &[ASTNode]) -> Vec<String> {
+    let mut findings = Findings::default();
+    for statement in statements {
+        findings.collect(statement);
+    }
+    findings.bound
+}
+
+fn resolve_block(statements: &[ASTNode], source: &Rc<Source>) -> Resolved {
+    let mut findings = Findings {
+        source: source.clone(),
+        ..Findings::default()
+    };
+    for statement in statements {
+        findings.collect(statement);
+    }
+
+    // rec, $n and the like are always given a value when the block is
+    // invoked, but $0, $1, ... are captured too for when it is given fewer
+    // arguments
+    let mut captures = vec![];
+    for (name, _) in &findings.identifiers {
+        if !always_given(name) {
+            insert(&mut captures, name);
+        }
+    }
+    for (_, resolved) in &findings.blocks {
+        for name in &resolved.captures {
+            insert(&mut captures, name);
+        }
+    }
+
+    let mut slots = findings.bound.clone();
+    for (name, _) in &findings.identifiers {
+        insert(&mut slots, name);
+    }
+    for name in &captures {
+        insert(&mut slots, name);
+    }
+
+    let slot = |name: &str| {
+        let index = slots.iter().position(|slot| slot == name);
+        Address::Slot(0, index.expect("name without a slot"))
+    };
+
+    let mut scope = Scope::default();
+    for (name, span) in findings.identifiers.iter().chain(&findings.bindings) {
+        scope.addresses.insert(span.start, slot(name));
+    }
+
+    // a paste could bind any name, so there's no telling what's missing
+    let free = if findings.pastes {
+        vec![]
+    } else {
+        let mut free: Vec<(String, Span)> = vec![];
+        for (name, span) in findings.uses() {
+            if is_parameter(&name)
+                || findings.bound.contains(&name)
+                || free.iter().any(|(existing, _)| *existing == name)
+            {
+                continue;
+            }
+            free.push((name, span));
+        }
+        free
+    };
+
+    for (span, resolved) in findings.blocks {
+        let definition = define(resolved, &findings.bound, slot);
+        scope.blocks.insert(span.start, definition);
+    }
+
+    scope.slots = slots;
+    Resolved {
+        block: Block {
+            statements: statements.to_vec(),
+            scope: Rc::new(scope),
+            source: source.clone(),
+        },
+        captures,
+        free,
+    }
+}
+
+/// where a name used by an expression evaluated at the top level lives, if
+/// it's bound anywhere
+fn locate(
+    name: &str,
+    findings: &Findings,
+    env: &Environment,
+) -> Option<Address> {
+    if findings.pastes || findings.bound.iter().any(|bound| bound == name) {
+        Some(Address::Dynamic)
+    } else {
+        env.locate(name)
+            .map(|(depth, index)| Address::Slot(depth, index))
+    }
+}
+
+/// resolve
+///
+/// Works out where every name used by an expression evaluated at the top
+/// level lives, given the environment it will be evaluated in. Names the
+/// expression binds itself are left to be looked up by name, while every
+/// block in it gets a frame with a slot for each name it uses. Names that
+/// the expression uses but that are bound nowhere are reported before
+/// anything is evaluated. Those used by the blocks within it are left to be
+/// reported when the blocks run - see [unbound] for finding them up front.
+/// The blocks are taken to be written in the given source.
+pub fn resolve(
+    node: &ASTNode,
+    env: &Environment,
+    source: &Rc<Source>,
+) -> Result<Scope, EvaluationError> {
+    let mut findings = Findings {
+        source: source.clone(),
+        ..Findings::default()
+    };
+    findings.collect(node);
+    let blocks = std::mem::take(&mut findings.blocks);
+    let address = |name: &str| locate(name, &findings, env);
+
+    let unbound = findings
+        .identifiers
+        .iter()
+        .filter(|(name, _)| address(name).is_none())
+        .min_by_key(|(_, span)| span.start);
+    if let Some((name, span)) = unbound {
+        return Err(EvaluationError::new(ErrorKind::UnboundSymbol(
+            name.clone(),
+        ))
+        .with_span(span.clone()));
+    }
+
+    let mut scope = Scope::default();
+    for (name, span) in &findings.identifiers {
+        let address = address(name).expect("unbound identifier");
+        scope.addresses.insert(span.start, address);
+    }
+    for (_, span) in &findings.bindings {
+        scope.addresses.insert(span.start, Address::Dynamic);
+    }
+    for (span, resolved) in blocks {
+        let definition = define(resolved, &findings.bound, |name| {
+            address(name).unwrap_or(Address::Dynamic)
+        });
+        scope.blocks.insert(span.start, definition);
+    }
+
+    Ok(scope)
+}
+
+/// unbound
+///
+/// Finds the names used by an expression evaluated at the top level, or by
+/// the blocks within it, that are bound nowhere - given the environment it
+/// will be evaluated in. Each is reported where it's first used.
+pub fn unbound(node: &ASTNode, env: &Environment) -> Vec<EvaluationError> {
+    let mut findings = Findings::default();
+    findings.collect(node);
+    let mut reported: Vec<String> = vec![];
+    let mut errors = vec![];
+    for (name, span) in findings.uses() {
+        if locate(&name, &findings, env).is_some() || reported.contains(&name) {
+            continue;
+        }
+        reported.push(name.clone());
+        let error = EvaluationError::new(ErrorKind::UnboundSymbol(name));
+        errors.push(error.with_span(span));
+    }
+    errors
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{TypeDescriptor, Value};
+
+#[cfg(test)]
+mod tests;
+
+// values carrying data map onto the serde data model the way they would onto
+// JSON: integers, floats, strings and booleans onto the primitives of the
+// same name, tuples onto sequences (arrays), and records onto maps (objects),
+// with their fields in order. floats that aren't finite have no counterpart
+// in JSON, so they can't be serialized, and neither can closures, runtime
+// invocations and types. deserializing maps anything unit-like, such as JSON's
+// `null`, onto the empty tuple.
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Integer(value) => serializer.serialize_i64(*value),
+            Value::Float(value) if value.is_finite() => {
+                serializer.serialize_f64(*value)
+            }
+            Value::Float(value) => Err(ser::Error::custom(format!(
+                "The Float {} can't be serialized, only finite ones can",
+                value
+            ))),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Boolean(value) => serializer.serialize_bool(*value),
+            Value::Tuple(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            Value::Record(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (label, value) in fields {
+                    map.serialize_entry(label, value)?;
+                }
+                map.end()
+            }
+            Value::Closure(..)
+            | Value::CompiledClosure(_)
+            | Value::NativeClosure(_)
+            | Value::RuntimeInvocation
+            | Value::Type(_) => Err(ser::Error::custom(format!(
+                "A value of type {} can't be serialized, only data can",
+                TypeDescriptor::of(self)
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an integer, float, string, boolean, tuple or record")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Integer(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        i64::try_from(value).map(Value::Integer).map_err(|_| {
+            E::custom(format!("{} is too large to be an Integer", value))
+        })
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Tuple(vec![]))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Tuple(vec![]))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> Result<Value, A::Error> {
+        let mut values = vec![];
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Tuple(values))
+    }
+
+    /// a record. a label that appears more than once keeps its last value,
+    /// in the position it first appeared at.
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> Result<Value, A::Error> {
+        let mut fields: Vec<(String, Value)> = vec![];
+        while let Some((label, value)) = map.next_entry::<String, Value>()? {
+            match fields.iter_mut().find(|(field, _)| *field == label) {
+                Some((_, field)) => *field = value,
+                None => fields.push((label, value)),
+            }
+        }
+        Ok(Value::Record(fields))
+    }
+}
@@ -0,0 +1,82 @@
+use super::*;
+use crate::BlockPipe;
+
+#[test]
+fn test_serialize_json() {
+    let value = Value::Record(vec![
+        ("name".to_string(), Value::from("x")),
+        (
+            "sizes".to_string(),
+            Value::from(vec![Value::from(1), Value::from(2.5)]),
+        ),
+        ("ok".to_string(), Value::from(true)),
+        ("none".to_string(), Value::from(())),
+    ]);
+    assert_eq!(
+        serde_json::to_string(&value).unwrap(),
+        r#"{"name":"x","sizes":[1,2.5],"ok":true,"none":[]}"#
+    );
+
+    let closure = BlockPipe::new().evaluate("{ 1 }").unwrap();
+    assert_eq!(
+        serde_json::to_string(&Value::from(vec![closure]))
+            .unwrap_err()
+            .to_string(),
+        "A value of type Closure can't be serialized, only data can"
+    );
+    assert!(serde_json::to_string(&Value::RuntimeInvocation).is_err());
+
+    for float in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        assert_eq!(
+            serde_json::to_string(&Value::from(vec![Value::Float(float)]))
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "The Float {} can't be serialized, only finite ones can",
+                float
+            )
+        );
+    }
+}
+
+#[test]
+fn test_deserialize_json() {
+    assert_eq!(
+        serde_json::from_str::<Value>(
+            r#"{"name": "x", "sizes": [1, 2.5], "ok": false, "none": null}"#
+        )
+        .unwrap(),
+        Value::Record(vec![
+            ("name".to_string(), Value::from("x")),
+            (
+                "sizes".to_string(),
+                Value::from(vec![Value::from(1), Value::from(2.5)])
+            ),
+            ("ok".to_string(), Value::from(false)),
+            ("none".to_string(), Value::from(())),
+        ])
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(r#"{"a": 1, "b": 2, "a": 3}"#).unwrap(),
+        Value::Record(vec![
+            ("a".to_string(), Value::from(3)),
+            ("b".to_string(), Value::from(2)),
+        ])
+    );
+    assert!(serde_json::from_str::<Value>("18446744073709551615").is_err());
+
+    // json config can be fed into blocks, and what they give back emitted
+    let config = serde_json::from_str(r#"{"width": 3, "height": 4}"#).unwrap();
+    let program = BlockPipe::new()
+        .load(
+            r#"{
+                area: { |size: (width: Integer height: Integer)|
+                    area: ((size.width size.height "*") "binop_arith") |* plz
+                    (area: area)
+                }
+            }"#,
+        )
+        .unwrap();
+    let area = program.call("area", vec![config]).unwrap();
+    assert_eq!(serde_json::to_string(&area).unwrap(), r#"{"area":12}"#);
+}
@@ -0,0 +1,104 @@
+use super::{Arguments, ErrorKind, TypeDescriptor};
+use crate::parser::{Accessor, Pattern};
+
+/// what's known about a value: the value itself while a program runs, or
+/// what [check](super::check) works out about it ahead of time. accesses,
+/// pipes and runtime calls are written once against it, so that the checker
+/// reports the same errors the engines raise.
+pub trait Shape: Clone + Sized {
+    fn tuple(elements: Vec<Self>) -> Self;
+    fn record(fields: Vec<(String, Self)>) -> Self;
+    /// what `$n` is for a block given the given number of arguments
+    fn count(count: usize) -> Self;
+
+    /// the name a type pattern uses for it, if that's known
+    fn type_name(&self) -> Option<&'static str>;
+    /// the contents of a string, if they're known
+    fn text(&self) -> Option<&str>;
+    /// describes it as `type (...)` would, if every part of it is known
+    fn descriptor(&self) -> Option<TypeDescriptor>;
+    /// fails unless it has the type it's annotated with (or might have, when
+    /// that isn't known)
+    fn conform(&self, annotation: &Pattern) -> Result<(), ErrorKind>;
+
+    /// the elements of a tuple, or the fields of a record
+    fn elements(&self) -> Option<Elements<'_, Self>>;
+    /// what piping it with `|*` passes on, or itself back if it's neither a
+    /// tuple nor a record
+    fn destructure(self) -> Result<Arguments<Self>, Self>;
+}
+
+/// the elements of a tuple, or the fields of a record - which are tuples
+/// too, so their fields can be indexed and sliced as well
+pub enum Elements<'e, T> {
+    Tuple(&'e [T]),
+    Record(&'e [(String, T)]),
+}
+
+impl<T: Shape> Elements<'_, T> {
+    fn length(&self) -> usize {
+        match self {
+            Elements::Tuple(elements) => elements.len(),
+            Elements::Record(fields) => fields.len(),
+        }
+    }
+
+    /// the element an index picks out, or the tuple (or record) of those a
+    /// slice does. fields are looked up by whoever has them.
+    pub fn access(&self, accessor: &Accessor) -> Result<T, ErrorKind> {
+        let length = self.length();
+        let out_of_range = || ErrorKind::OutOfRange {
+            accessor: accessor.clone(),
+            length,
+        };
+
+        match accessor {
+            Accessor::Field(_) => unreachable!("fields are accessed by label"),
+            Accessor::Index(index) => {
+                let index = position(*index, length)
+                    .filter(|index| *index < length)
+                    .ok_or_else(out_of_range)?;
+                Ok(match self {
+                    Elements::Tuple(elements) => elements[index].clone(),
+                    Elements::Record(fields) => fields[index].1.clone(),
+                })
+            }
+            Accessor::Slice(start, end) => {
+                let first = position(*start, length);
+                let last = end
+                    .map_or(Some(length), |end| position(end, length))
+                    .filter(|last| *last <= length);
+                let range = match (first, last) {
+                    (Some(first), Some(last)) if first <= last => first..last,
+                    _ => return Err(out_of_range()),
+                };
+                Ok(match self {
+                    Elements::Tuple(elements) => {
+                        T::tuple(elements[range].to_vec())
+                    }
+                    Elements::Record(fields) => {
+                        T::record(fields[range].to_vec())
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// the error for indexing something that's neither a tuple nor a record
+pub(super) fn unindexable(found: impl std::fmt::Display) -> ErrorKind {
+    ErrorKind::TypeMismatch(format!(
+        "Can only index tuples and records, not {}",
+        found
+    ))
+}
+
+/// where an index falls in a tuple of the given length, counting from the end
+/// if it's negative. may be past the end, but never before the start.
+fn position(index: i64, length: usize) -> Option<usize> {
+    if index < 0 {
+        length.checked_sub(index.unsigned_abs() as usize)
+    } else {
+        Some(index as usize)
+    }
+}
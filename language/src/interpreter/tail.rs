@@ -0,0 +1,117 @@
+use std::rc::Rc;
+
+use logos::Span;
+
+use super::{
+    conforms, Arguments, EvaluateResult, EvaluationError, Source, Value,
+};
+use crate::parser::Pattern;
+
+/// a call in tail position: the last pipe stage of a block, or the branch an
+/// `if` selects. rather than making it, the block hands it back to whoever
+/// invoked the block, so that recursive loops don't grow the stack.
+#[derive(Debug)]
+pub struct TailCall {
+    pub closure: Value,
+    pub arguments: Arguments,
+    /// how the call shows up in the call stack of an error raised by it
+    pub callee: String,
+    pub span: Span,
+}
+
+/// what running a block comes to: either its value, or a call that it left
+/// for its caller to make
+#[derive(Debug)]
+pub enum Outcome {
+    Done(Value),
+    TailCall(TailCall),
+}
+
+/// the type annotation on the output of a closure's block, if it has one
+fn output(closure: &Value) -> Option<&(Pattern, Span)> {
+    let parameters = match closure {
+        Value::Closure(block, _) => block.parameters(),
+        Value::CompiledClosure(closure) => closure.parameters(),
+        Value::NativeClosure(closure) => closure.parameters(),
+        _ => None,
+    };
+    parameters?.output.as_ref()
+}
+
+/// what the closure's block was written in. compiled programs are made of a
+/// single source, which their errors are rendered against anyway.
+fn source(closure: &Value) -> Rc<Source> {
+    match closure {
+        Value::Closure(block, _) => block.source.clone(),
+        Value::CompiledClosure(closure) => closure.source.clone(),
+        _ => Rc::default(),
+    }
+}
+
+/// trampoline
+///
+/// Invokes a closure with `step`, then keeps making the tail calls it leaves
+/// behind until one of them finishes with a value. Errors get a call stack
+/// frame for each tail call made on the way, except that a loop of tail calls
+/// only shows up once, however many times it went round. Errors are tagged
+/// with the source of the block they leave, as are the frames of the tail
+/// calls it made.
+///
+/// The value a tail call finishes with is also the output of every block
+/// that made one on the way, so it is checked against all of their output
+/// annotations - the last block's first.
+pub fn trampoline(
+    mut closure: Value,
+    mut arguments: Arguments,
+    mut step: impl FnMut(&Value, Arguments) -> Result<Outcome, EvaluationError>,
+) -> EvaluateResult {
+    let mut calls: Vec<(String, Span, Rc<Source>)> = vec![];
+    let mut outputs: Vec<(Pattern, Span, Rc<Source>)> = vec![];
+    loop {
+        let source = source(&closure);
+        if let Some((annotation, span)) = output(&closure) {
+            let output = (annotation.clone(), span.clone(), source.clone());
+            if !outputs.contains(&output) {
+                outputs.push(output);
+            }
+        }
+
+        let result = match step(&closure, arguments) {
+            Ok(Outcome::Done(value)) => outputs
+                .iter()
+                .rev()
+                .try_for_each(|(annotation, span, source)| {
+                    conforms(annotation, &value).map_err(|kind| {
+                        EvaluationError::from(kind)
+                            .with_span(span.clone())
+                            .in_source(source)
+                    })
+                })
+                .map(|_| value),
+            Ok(Outcome::TailCall(call)) => {
+                let seen = calls.iter().position(|(callee, span, made_in)| {
+                    *callee == call.callee
+                        && *span == call.span
+                        && *made_in == source
+                });
+                match seen {
+                    Some(index) => calls.truncate(index + 1),
+                    None => calls.push((call.callee, call.span, source)),
+                }
+                closure = call.closure;
+                arguments = call.arguments;
+                continue;
+            }
+            Err(error) => Err(error.in_source(&source)),
+        };
+
+        return result.map_err(|error| {
+            calls.into_iter().rev().fold(
+                error,
+                |error, (callee, span, source)| {
+                    error.in_call(&callee, Some(span)).in_source(&source)
+                },
+            )
+        });
+    }
+}
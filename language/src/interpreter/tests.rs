@@ -1,3 +1,4 @@
+use indoc::indoc;
 use logos::Span;
 
 use super::*;
@@ -20,6 +21,10 @@ fn lex_parse_evaluate(input: &str) -> EvaluateResult {
     interpreter.evaluate(&interpreter.root_node.clone())
 }
 
+fn integers(values: &[i64]) -> Value {
+    Value::Tuple(values.iter().map(|i| Value::Integer(*i)).collect())
+}
+
 #[test]
 fn test_evaluate_integer_literal() {
     assert_eq!(lex_parse_evaluate("1"), Ok(Value::Integer(1)));
@@ -140,6 +145,7 @@ fn test_evaluate_identifiers() {
 }
 
 #[test]
+#[allow(unused_must_use)]
 fn test_evaluate_binding() {
     let mut interpreter =
         Interpreter::new(lex_and_parse("a: \"bruh\"").unwrap());
@@ -177,6 +183,67 @@ fn test_evaluate_closure() {
     );
 }
 
+/// the values a closure captured, whichever engine created it
+fn captured(closure: &Value) -> std::collections::HashMap<String, Value> {
+    match closure {
+        Value::Closure(_, captured) => (**captured).clone(),
+        Value::CompiledClosure(closure) => closure.image(),
+        other => panic!("expected a closure, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_closures_capture_free_variables() {
+    // besides the names they use, closures capture what the block creating
+    // them binds - but nothing else from further out
+    let code = r#"
+        () | {
+            unused: 1
+            () | {
+                a: 2
+                b: 3
+                { (a { b }) }
+            }
+        }
+    "#;
+
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    let closure = interpreter.evaluate_from_root(None).unwrap();
+    let mut names: Vec<String> = captured(&closure).into_keys().collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b"]);
+}
+
+#[test]
+fn test_resolve_addresses() {
+    let mut env = Environment::new();
+    env.push_stack_frame();
+    env.bind("a".to_string(), Value::Integer(1));
+    env.bind("b".to_string(), Value::Integer(2));
+    env.push_stack_frame();
+    env.bind("c".to_string(), Value::Integer(3));
+
+    let node = lex_and_parse("d: (a b c d)").unwrap();
+    let source = std::rc::Rc::default();
+    let scope = resolve(&node, &env, &source).unwrap();
+    let address = |start: usize| scope.address(&(start..start + 1)).cloned();
+
+    assert_eq!(address(4), Some(Address::Slot(1, 0)));
+    assert_eq!(address(6), Some(Address::Slot(1, 1)));
+    assert_eq!(address(8), Some(Address::Slot(0, 0)));
+    // names bound at the top level are looked up once they have been bound
+    assert_eq!(address(10), Some(Address::Dynamic));
+
+    let error = resolve(&lex_and_parse("(a e)").unwrap(), &env, &source);
+    assert_eq!(
+        error.map_err(|error| (error.kind, error.span)),
+        Err((ErrorKind::UnboundSymbol("e".to_string()), Some(3..4)))
+    );
+    // those used by blocks are reported when the blocks run
+    let block = lex_and_parse("{ (a e) }").unwrap();
+    assert!(resolve(&block, &env, &source).is_ok());
+}
+
 #[test]
 fn test_evaluate_pipe() {
     let code = r#"
@@ -198,7 +265,7 @@ fn test_evaluate_pipe() {
 #[test]
 fn test_basic_blockpipe() {
     let code = r#"
-        (
+        () | {
             main: {
                 swap: {
                     ($1 $0)
@@ -217,223 +284,512 @@ fn test_basic_blockpipe() {
             }
 
             () | main
-        )
+        }
     "#;
 
     let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
     assert_eq!(
         interpreter.evaluate_from_root(None),
         Ok(Value::Tuple(vec![
-            Value::Tuple(vec![]),
-            Value::Tuple(vec![
-                Value::Integer(2),
-                Value::String("bruh".to_string())
-            ])
+            Value::Integer(2),
+            Value::String("bruh".to_string())
         ]))
     );
 }
 
-fn interpreter_with_runtime(code: &str) -> Interpreter {
-    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
-    interpreter.env.push_stack_frame();
-    interpreter
-        .env
-        .bind("plz".to_string(), Value::RuntimeInvocation);
-    interpreter
+#[test]
+fn test_evaluate_type() {
+    assert_eq!(
+        lex_parse_evaluate("type (1)"),
+        Ok(Value::Type(TypeDescriptor::Integer))
+    );
+    assert_eq!(
+        lex_parse_evaluate("type ({ $0 })"),
+        Ok(Value::Type(TypeDescriptor::Closure))
+    );
+    assert_eq!(
+        lex_parse_evaluate("type (1 (\"a\" T) 2.0)"),
+        Ok(Value::Type(TypeDescriptor::Tuple(vec![
+            TypeDescriptor::Integer,
+            TypeDescriptor::Tuple(vec![
+                TypeDescriptor::String,
+                TypeDescriptor::Boolean
+            ]),
+            TypeDescriptor::Float
+        ])))
+    );
+    assert_eq!(
+        lex_parse_evaluate("type (type (()))"),
+        Ok(Value::Type(TypeDescriptor::Type))
+    );
 }
 
 #[test]
-fn test_runtime_foo() {
+fn test_display_type() {
+    let descriptor = lex_parse_evaluate("type (1 (\"a\" T) 2.0 ())").unwrap();
+    assert_eq!(descriptor.to_string(), "(Integer (String Boolean) Float ())");
+}
+
+#[test]
+fn test_pipe_type() {
     let code = r#"
-        (() "foo") |* plz
+        (1 2) | { type ($0) } | { $0 }
     "#;
 
-    let mut interpreter = interpreter_with_runtime(code);
-
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
     assert_eq!(
         interpreter.evaluate_from_root(None),
-        Ok(Value::String("bar".to_string()))
+        Ok(Value::Type(TypeDescriptor::Tuple(vec![
+            TypeDescriptor::Integer,
+            TypeDescriptor::Integer
+        ])))
     );
 }
 
 #[test]
-fn test_binop_arith() {
-    let mut interpreter = interpreter_with_runtime(
-        r#"
+fn test_paste_tuple() {
+    let code = r#"
         () | {
-            add: {
-                (($0 $1 "+") "binop_arith") |* plz
-            }
-
-            sub: {
-                (($0 $1 "-") "binop_arith") |* plz
-            }
-
-            mul: {
-                (($0 $1 "*") "binop_arith") |* plz
-            }
-
-            div: {
-                (($0 $1 "/") "binop_arith") |* plz
-            }
-
-            data: (1.0 2.0)
-
-            (
-                data |* add
-                data |* sub
-                data |* mul
-                data |* div
-            )
+            xs: (2 3)
+            (1 paste (xs) 4 paste (() (5) xs))
         }
-    "#,
-    );
+    "#;
 
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
     assert_eq!(
         interpreter.evaluate_from_root(None),
         Ok(Value::Tuple(vec![
-            Value::Float(3.0),
-            Value::Float(-1.0),
-            Value::Float(2.0),
-            Value::Float(0.5)
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(3),
+            Value::Integer(4),
+            Value::Integer(5),
+            Value::Integer(2),
+            Value::Integer(3)
         ]))
     );
 }
 
 #[test]
-fn test_binop_cmp() {
-    let mut interpreter = interpreter_with_runtime(
-        r#"
-        () | {
-            less_than: {
-                (($0 $1 "<") "binop_cmp") |* plz
-            }
+fn test_paste_in_block() {
+    assert_eq!(
+        lex_parse_evaluate("() | { paste ((1 2)) }"),
+        Ok(Value::Integer(2))
+    );
+    assert_eq!(
+        lex_parse_evaluate("() | { paste (()) }"),
+        Ok(Value::Tuple(vec![]))
+    );
+}
 
-            less_than_equal: {
-                (($0 $1 "<=") "binop_cmp") |* plz
+#[test]
+fn test_paste_closure_bindings() {
+    let code = r#"
+        () | {
+            point: () | {
+                x: 1
+                y: 2
+                {}
             }
 
-            greater_than: {
-                (($0 $1 ">") "binop_cmp") |* plz
+            () | {
+                x: 5
+                paste (point)
+                (x y $n)
             }
+        }
+    "#;
 
-            greater_than_equal: {
-                (($0 $1 ">=") "binop_cmp") |* plz
-            }
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Integer(1),
+            Value::Integer(2),
+            Value::Integer(1)
+        ]))
+    );
+}
 
-            equal: {
-                (($0 $1 "==") "binop_cmp") |* plz
-            }
+#[test]
+fn test_paste_non_tuple() {
+    assert!(lex_parse_evaluate("(1 paste (2))").is_err());
+}
 
-            not_equal: {
-                (($0 $1 "!=") "binop_cmp") |* plz
-            }
+#[test]
+fn test_evaluate_record() {
+    let record = lex_parse_evaluate(r#"(name: "x" age: 3)"#).unwrap();
+    assert_eq!(
+        record,
+        Value::Record(vec![
+            ("name".to_string(), Value::String("x".to_string())),
+            ("age".to_string(), Value::Integer(3)),
+        ])
+    );
+    assert_eq!(record.to_string(), "(name: x age: 3)");
 
-            data_int: (2 3)
-            data_float: (2.0 3.0)
-            data_mixed: (2 3.0)
+    let descriptor = lex_parse_evaluate(r#"type ((name: "x" age: 3))"#);
+    assert_eq!(descriptor.unwrap().to_string(), "(name: String age: Integer)");
+}
 
-            (
-                data_int |* less_than
-                data_int |* less_than_equal
-                data_int |* greater_than
-                data_int |* greater_than_equal
-                data_int |* equal
-                data_int |* not_equal
-                data_float |* less_than
-                data_float |* less_than_equal
-                data_float |* greater_than
-                data_float |* greater_than_equal
-                data_float |* equal
-                data_float |* not_equal
-                data_mixed |* less_than
-                data_mixed |* less_than_equal
-                data_mixed |* greater_than
-                data_mixed |* greater_than_equal
-                data_mixed |* equal
-                data_mixed |* not_equal
-            )
+#[test]
+fn test_field_access() {
+    let code = r#"
+        () | {
+            person: (name: "x" address: (city: "y" code: 1))
+            (person.name person.address.city)
         }
-    "#,
-    );
+    "#;
 
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
     assert_eq!(
         interpreter.evaluate_from_root(None),
         Ok(Value::Tuple(vec![
-            // Comparisons with integers (2, 3)
-            Value::Boolean(true),   // 2 < 3
-            Value::Boolean(true),   // 2 <= 3
-            Value::Boolean(false),  // 2 > 3
-            Value::Boolean(false),  // 2 >= 3
-            Value::Boolean(false),  // 2 == 3
-            Value::Boolean(true),   // 2 != 3
-
-            // Comparisons with floats (2.0, 3.0)
-            Value::Boolean(true),   // 2.0 < 3.0
-            Value::Boolean(true),   // 2.0 <= 3.0
-            Value::Boolean(false),  // 2.0 > 3.0
-            Value::Boolean(false),  // 2.0 >= 3.0
-            Value::Boolean(false),  // 2.0 == 3.0
-            Value::Boolean(true),   // 2.0 != 3.0
-
-            // Heterogeneous comparisons (2, 3.0)
-            Value::Boolean(true),   // 2 < 3.0
-            Value::Boolean(true),   // 2 <= 3.0
-            Value::Boolean(false),  // 2 > 3.0
-            Value::Boolean(false),  // 2 >= 3.0
-            Value::Boolean(false),  // 2 == 3.0
-            Value::Boolean(true)    // 2 != 3.0
+            Value::String("x".to_string()),
+            Value::String("y".to_string())
         ]))
     );
-}
 
+    let error = lex_parse_evaluate("(a: 1).b").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::MissingField {
+            field: "b".to_string(),
+            found: TypeDescriptor::Record(vec![(
+                "a".to_string(),
+                TypeDescriptor::Integer
+            )]),
+        }
+    );
+    assert_eq!(error.span, Some(0..8));
+    assert_eq!(
+        lex_parse_evaluate("(1 2).a").map_err(|error| error.kind.to_string()),
+        Err("No field 'a' in value of type (Integer Integer)".to_string())
+    );
+}
 
 #[test]
-fn test_strcat() {
-    let mut interpreter = interpreter_with_runtime(
-        r#"
-        () | {
-            data: ("hello " "world")
+fn test_index_access() {
+    assert_eq!(lex_parse_evaluate("(1 2 3).0"), Ok(Value::Integer(1)));
+    assert_eq!(lex_parse_evaluate("(1 2 3).-1"), Ok(Value::Integer(3)));
+    assert_eq!(
+        lex_parse_evaluate("((1 2) (3 4)).1.0"),
+        Ok(Value::Integer(3))
+    );
+    assert_eq!(
+        lex_parse_evaluate("(name: \"x\" age: 3).1"),
+        Ok(Value::Integer(3))
+    );
+    assert_eq!(
+        lex_parse_evaluate("((1 2) (3 4)) | { $0.-1 }"),
+        Ok(integers(&[3, 4]))
+    );
 
-            data |* { (($0 $1) "strcat") } |* plz
+    let error = lex_parse_evaluate("(1 2 3).3").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::OutOfRange {
+            accessor: Accessor::Index(3),
+            length: 3
         }
-    "#,
     );
+    assert_eq!(error.span, Some(0..9));
+    assert_eq!(
+        lex_parse_evaluate("(1 2).-3").map_err(|error| error.kind.to_string()),
+        Err("Index -3 is out of range for a tuple of length 2".to_string())
+    );
+    assert_eq!(
+        lex_parse_evaluate("\"ab\".0").map_err(|error| error.kind),
+        Err(ErrorKind::TypeMismatch(
+            "Can only index tuples and records, not String".to_string()
+        ))
+    );
+}
 
+#[test]
+fn test_slice_access() {
+    assert_eq!(lex_parse_evaluate("(1 2 3 4).1..3"), Ok(integers(&[2, 3])));
+    assert_eq!(lex_parse_evaluate("(1 2 3 4).1.."), Ok(integers(&[2, 3, 4])));
     assert_eq!(
-        interpreter.evaluate_from_root(None),
-        Ok(Value::String("hello world".to_string()))
+        lex_parse_evaluate("(1 2 3 4).0..-1"),
+        Ok(integers(&[1, 2, 3]))
+    );
+    assert_eq!(lex_parse_evaluate("(1 2 3 4).-2.."), Ok(integers(&[3, 4])));
+    assert_eq!(lex_parse_evaluate("(1 2 3 4).2..2"), Ok(integers(&[])));
+    assert_eq!(
+        lex_parse_evaluate("(a: 1 b: 2 c: 3).1.."),
+        Ok(Value::Record(vec![
+            ("b".to_string(), Value::Integer(2)),
+            ("c".to_string(), Value::Integer(3)),
+        ]))
+    );
+
+    assert_eq!(
+        lex_parse_evaluate("(1 2 3).2..1").map_err(|error| error.kind),
+        Err(ErrorKind::OutOfRange {
+            accessor: Accessor::Slice(2, Some(1)),
+            length: 3
+        })
+    );
+    assert_eq!(
+        lex_parse_evaluate("(1 2 3).0..4")
+            .map_err(|error| error.kind.to_string()),
+        Err("Slice 0..4 is out of range for a tuple of length 3".to_string())
     );
 }
 
 #[test]
-fn test_if() {
-    let mut interpreter = interpreter_with_runtime(
-        r#"
-        () | {
-            if: {
-                (($0 $1 $2) "if") |* plz
-            }
+fn test_destructure_record() {
+    let code = r#"
+        (name: "x" age: 3) |* { ($age $name $0 $n () | { $age }) }
+    "#;
 
-            (T { "all good!" } { "oof something ain't right" }) |* if
-        }
-    "#,
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Integer(3),
+            Value::String("x".to_string()),
+            Value::String("x".to_string()),
+            Value::Integer(2),
+            Value::Integer(3)
+        ]))
     );
 
-    assert_eq!(interpreter.evaluate_from_root(None), Ok(Value::String("all good!".to_string())));
+    // records are passed on as they are by a standard pipe
+    assert_eq!(
+        lex_parse_evaluate("(a: 1) | { $0.a }"),
+        Ok(Value::Integer(1))
+    );
+    assert_eq!(
+        lex_parse_evaluate("(a: 1) | { $a }").map_err(|error| error.kind),
+        Err(ErrorKind::UnboundSymbol("$a".to_string()))
+    );
 }
 
 #[test]
-fn test_recursive_if() {
-    let mut interpreter = interpreter_with_runtime(
-        r#"
+fn test_paste_record() {
+    assert_eq!(
+        lex_parse_evaluate("(0 paste ((a: 1 b: 2)))"),
+        Ok(Value::Tuple(vec![
+            Value::Integer(0),
+            Value::Integer(1),
+            Value::Integer(2)
+        ]))
+    );
+}
+
+#[test]
+fn test_variadic_parameters() {
+    assert_eq!(
+        lex_parse_evaluate("(1 2 3) |* { ($@ $1.. $3.. $0..) }"),
+        Ok(Value::Tuple(vec![
+            integers(&[1, 2, 3]),
+            integers(&[2, 3]),
+            integers(&[]),
+            integers(&[1, 2, 3]),
+        ]))
+    );
+    assert_eq!(lex_parse_evaluate("4 | { $@ }"), Ok(integers(&[4])));
+    // labels are kept, just like when slicing a record
+    assert_eq!(
+        lex_parse_evaluate("(a: 1 b: 2) |* { $1.. }"),
+        Ok(Value::Record(vec![("b".to_string(), Value::Integer(2))]))
+    );
+    // nested blocks get arguments of their own
+    assert_eq!(
+        lex_parse_evaluate("(1 2) |* { () |* { $@ } }"),
+        Ok(integers(&[]))
+    );
+}
+
+#[test]
+fn test_declared_parameters() {
+    let code = r#"
         () | {
-            if: {
-                (($0 $1 $2) "if") |* plz
+            pair: { |a b| (b a) }
+            head: { |first ...rest| (first rest $n) }
+            ((1 2) |* pair (1 2 3) |* head 1 | head)
+        }
+    "#;
+
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Tuple(vec![Value::Integer(2), Value::Integer(1)]),
+            Value::Tuple(vec![
+                Value::Integer(1),
+                Value::Tuple(vec![Value::Integer(2), Value::Integer(3)]),
+                Value::Integer(3),
+            ]),
+            Value::Tuple(vec![
+                Value::Integer(1),
+                Value::Tuple(vec![]),
+                Value::Integer(1),
+            ]),
+        ]))
+    );
+}
+
+#[test]
+fn test_declared_parameter_arity() {
+    let error = lex_parse_evaluate("(1 2 3) |* { |a b| a }").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::ArityMismatch {
+            call: "Block".to_string(),
+            expected: 2,
+            found: 3
+        }
+    );
+    assert_eq!(error.span, Some(13..18));
+    assert_eq!(error.call_stack[0].name, "anonymous block");
+
+    let error = lex_parse_evaluate("() |* { |a ...rest| a }").unwrap_err();
+    assert_eq!(
+        error.kind.to_string(),
+        "Block requires at least 1 argument but received 0"
+    );
+}
+
+#[test]
+fn test_annotations() {
+    // blocks with an annotated output still make tail calls
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            total: Integer = 0
+            pair: (Integer (name: String)) = (1 (name: "a" age: 2))
+            countdown: { |n: Integer| -> String
+                done: ((n 0 "==") "binop_cmp") |* plz
+                again: rec
+                (
+                    (
+                        done
+                        { "liftoff" }
+                        { ((n 1 "-") "binop_arith") |* plz | again }
+                    )
+                    "if"
+                ) |* plz
             }
+            (total pair.1.name 100000 | countdown)
+        }
+    "#,
+    );
 
-            leq: {
-                (($0 $1 "<=") "binop_cmp") |* plz
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Integer(0),
+            Value::String("a".to_string()),
+            Value::String("liftoff".to_string()),
+        ]))
+    );
+}
+
+#[test]
+fn test_annotation_mismatch() {
+    let error = lex_parse_evaluate("x: String = 3").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::AnnotationMismatch {
+            expected: Pattern::Type("String".to_string()),
+            found: TypeDescriptor::Integer,
+        }
+    );
+    assert_eq!(
+        error.kind.to_string(),
+        "Expected a value of type String but found one of type Integer"
+    );
+    assert_eq!(error.span, Some(3..13));
+
+    // parameters are checked on the way into a block
+    let error =
+        lex_parse_evaluate(r#"("a" 1) |* { |a: String b: (String _)| a }"#)
+            .unwrap_err();
+    assert_eq!(
+        error.kind.to_string(),
+        "Expected a value of type (String _) but found one of type Integer"
+    );
+    assert_eq!(error.span, Some(24..25));
+    assert_eq!(error.call_stack[0].name, "anonymous block");
+
+    // and outputs on the way out, even of the blocks a tail call left
+    let code = r#"() | { f: { |x| -> Integer g: { "a" } x | g } () | f }"#;
+    let error = lex_parse_evaluate(code).unwrap_err();
+    assert_eq!(
+        error.kind.to_string(),
+        "Expected a value of type Integer but found one of type String"
+    );
+    let start = code.find("Integer").unwrap();
+    assert_eq!(error.span, Some(start..start + 7));
+    assert_eq!(
+        error
+            .call_stack
+            .iter()
+            .map(|frame| frame.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["block `g`", "block `f`", "anonymous block"]
+    );
+}
+
+#[test]
+fn test_top_level_arguments() {
+    let mut interpreter = Interpreter::new(lex_and_parse("($@ $n)").unwrap());
+    assert_eq!(
+        interpreter.evaluate_from_root(Some(vec![Value::Integer(1)])),
+        Ok(Value::Tuple(vec![
+            Value::Tuple(vec![Value::Integer(1)]),
+            Value::Integer(1)
+        ]))
+    );
+}
+
+#[test]
+fn test_comments_are_ignored() {
+    let code = r#"
+        # the answer, eventually
+        () | {
+            /* pick the /* second */ element */
+            (1 2) |* { $1 } // and nothing else
+        }
+    "#;
+
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    assert_eq!(interpreter.evaluate_from_root(None), Ok(Value::Integer(2)));
+}
+
+fn interpreter_with_runtime(code: &str) -> Interpreter {
+    let mut interpreter = Interpreter::new(lex_and_parse(code).unwrap());
+    interpreter.env.push_stack_frame();
+    interpreter
+        .env
+        .bind("plz".to_string(), Value::RuntimeInvocation);
+    interpreter
+}
+
+#[test]
+fn test_runtime_foo() {
+    let code = r#"
+        (() "foo") |* plz
+    "#;
+
+    let mut interpreter = interpreter_with_runtime(code);
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::String("bar".to_string()))
+    );
+}
+
+#[test]
+fn test_binop_arith() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            add: {
+                (($0 $1 "+") "binop_arith") |* plz
             }
 
             sub: {
@@ -444,22 +800,1203 @@ fn test_recursive_if() {
                 (($0 $1 "*") "binop_arith") |* plz
             }
 
-            factorial: {
-                x: $0
-                fact_rec: rec
-                (
-                    (x 1) |* leq
-                    {1}
-                    {   
-                        (x 1)  |* sub | fact_rec | {(x $0)} |* mul
-                    }
-                ) |* if
+            div: {
+                (($0 $1 "/") "binop_arith") |* plz
             }
 
-            5 | factorial
+            data: (1.0 2.0)
+
+            (
+                data |* add
+                data |* sub
+                data |* mul
+                data |* div
+            )
         }
     "#,
     );
 
-    println!("{:?}", interpreter.evaluate_from_root(None));
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Float(3.0),
+            Value::Float(-1.0),
+            Value::Float(2.0),
+            Value::Float(0.5)
+        ]))
+    );
+}
+
+#[test]
+fn test_binop_cmp() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            less_than: {
+                (($0 $1 "<") "binop_cmp") |* plz
+            }
+
+            less_than_equal: {
+                (($0 $1 "<=") "binop_cmp") |* plz
+            }
+
+            greater_than: {
+                (($0 $1 ">") "binop_cmp") |* plz
+            }
+
+            greater_than_equal: {
+                (($0 $1 ">=") "binop_cmp") |* plz
+            }
+
+            equal: {
+                (($0 $1 "==") "binop_cmp") |* plz
+            }
+
+            not_equal: {
+                (($0 $1 "!=") "binop_cmp") |* plz
+            }
+
+            data_int: (2 3)
+            data_float: (2.0 3.0)
+            data_mixed: (2 3.0)
+
+            (
+                data_int |* less_than
+                data_int |* less_than_equal
+                data_int |* greater_than
+                data_int |* greater_than_equal
+                data_int |* equal
+                data_int |* not_equal
+                data_float |* less_than
+                data_float |* less_than_equal
+                data_float |* greater_than
+                data_float |* greater_than_equal
+                data_float |* equal
+                data_float |* not_equal
+                data_mixed |* less_than
+                data_mixed |* less_than_equal
+                data_mixed |* greater_than
+                data_mixed |* greater_than_equal
+                data_mixed |* equal
+                data_mixed |* not_equal
+            )
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            // Comparisons with integers (2, 3)
+            Value::Boolean(true),   // 2 < 3
+            Value::Boolean(true),   // 2 <= 3
+            Value::Boolean(false),  // 2 > 3
+            Value::Boolean(false),  // 2 >= 3
+            Value::Boolean(false),  // 2 == 3
+            Value::Boolean(true),   // 2 != 3
+
+            // Comparisons with floats (2.0, 3.0)
+            Value::Boolean(true),   // 2.0 < 3.0
+            Value::Boolean(true),   // 2.0 <= 3.0
+            Value::Boolean(false),  // 2.0 > 3.0
+            Value::Boolean(false),  // 2.0 >= 3.0
+            Value::Boolean(false),  // 2.0 == 3.0
+            Value::Boolean(true),   // 2.0 != 3.0
+
+            // Heterogeneous comparisons (2, 3.0)
+            Value::Boolean(true),   // 2 < 3.0
+            Value::Boolean(true),   // 2 <= 3.0
+            Value::Boolean(false),  // 2 > 3.0
+            Value::Boolean(false),  // 2 >= 3.0
+            Value::Boolean(false),  // 2 == 3.0
+            Value::Boolean(true)    // 2 != 3.0
+        ]))
+    );
+}
+
+
+#[test]
+fn test_strcat() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            data: ("hello " "world")
+
+            data |* { (($0 $1) "strcat") } |* plz
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::String("hello world".to_string()))
+    );
+}
+
+#[test]
+fn test_json_parse() {
+    let mut interpreter = interpreter_with_runtime(
+        r##"
+        () | {
+            json: r#"{"name": "x", "sizes": [1, 2.5], "debug": false}"#
+            config: ((json) "json_parse") |* plz
+            (config.ok.name config.ok.sizes.1 config.ok.debug)
+        }
+    "##,
+    );
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::String("x".to_string()),
+            Value::Float(2.5),
+            Value::Boolean(false),
+        ]))
+    );
+
+    // malformed json comes to a record describing where it went wrong
+    let parse_error = |json: &str, message: &str, line, column| {
+        let mut interpreter = interpreter_with_runtime(&format!(
+            r#"(({:?}) "json_parse") |* plz"#,
+            json
+        ));
+        assert_eq!(
+            interpreter.evaluate_from_root(None),
+            Ok(Value::Record(vec![(
+                "error".to_string(),
+                Value::Record(vec![
+                    ("message".to_string(), Value::from(message)),
+                    ("line".to_string(), Value::Integer(line)),
+                    ("column".to_string(), Value::Integer(column)),
+                ])
+            )]))
+        );
+    };
+    parse_error("{\"a\": [1,\n  }", "invalid JSON syntax", 2, 3);
+    parse_error("[1,", "unexpected end of JSON", 1, 3);
+    parse_error(
+        "18446744073709551615",
+        "JSON value BlockPipe can't represent",
+        1,
+        20,
+    );
+
+    let mut interpreter =
+        interpreter_with_runtime(r#"((1) "json_parse") |* plz"#);
+    assert_eq!(
+        interpreter.evaluate_from_root(None).unwrap_err().kind,
+        ErrorKind::TypeMismatch("json_parse requires a string".to_string())
+    );
+}
+
+#[test]
+fn test_json_stringify() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            data: (name: "x" sizes: (1 2.5) nothing: ())
+            compact: ((data ()) "json_stringify") |* plz
+            pretty: ((data (indent: 4)) "json_stringify") |* plz
+            short: ((("a") (pretty: T)) "json_stringify") |* plz
+            (compact pretty short)
+        }
+    "#,
+    );
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::String(
+                r#"{"name":"x","sizes":[1,2.5],"nothing":[]}"#.to_string()
+            ),
+            Value::String(
+                indoc! {r#"
+                    {
+                        "name": "x",
+                        "sizes": [
+                            1,
+                            2.5
+                        ],
+                        "nothing": []
+                    }"#}
+                .to_string()
+            ),
+            Value::String("[\n  \"a\"\n]".to_string()),
+        ]))
+    );
+
+    let errors = [
+        (
+            r#"((({ 1 }) ()) "json_stringify") |* plz"#,
+            "A value of type Closure can't be serialized, only data can",
+        ),
+        (
+            r#"((1 (indent: "4")) "json_stringify") |* plz"#,
+            "json_stringify indent must be an integer",
+        ),
+        (
+            r#"((1 (width: 4)) "json_stringify") |* plz"#,
+            "Unknown json_stringify option: width",
+        ),
+        (
+            r#"((1 4) "json_stringify") |* plz"#,
+            "json_stringify options must be a record",
+        ),
+    ];
+    for (code, message) in errors {
+        let mut interpreter = interpreter_with_runtime(code);
+        assert_eq!(
+            interpreter.evaluate_from_root(None).unwrap_err().kind,
+            ErrorKind::TypeMismatch(message.to_string())
+        );
+    }
+}
+
+#[test]
+fn test_if() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            if: {
+                (($0 $1 $2) "if") |* plz
+            }
+
+            (T { "all good!" } { "oof something ain't right" }) |* if
+        }
+    "#,
+    );
+
+    assert_eq!(interpreter.evaluate_from_root(None), Ok(Value::String("all good!".to_string())));
+}
+
+#[test]
+fn test_recursive_if() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            if: {
+                (($0 $1 $2) "if") |* plz
+            }
+
+            leq: {
+                (($0 $1 "<=") "binop_cmp") |* plz
+            }
+
+            sub: {
+                (($0 $1 "-") "binop_arith") |* plz
+            }
+
+            mul: {
+                (($0 $1 "*") "binop_arith") |* plz
+            }
+
+            factorial: {
+                x: $0
+                fact_rec: rec
+                (
+                    (x 1) |* leq
+                    {1}
+                    {   
+                        (x 1)  |* sub | fact_rec | {(x $0)} |* mul
+                    }
+                ) |* if
+            }
+
+            5 | factorial
+        }
+    "#,
+    );
+
+    println!("{:?}", interpreter.evaluate_from_root(None));
+}
+
+#[test]
+fn test_tail_calls_do_not_grow_the_stack() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            countdown: {
+                n: $0
+                again: rec
+                done: ((n 0 "==") "binop_cmp") |* plz
+                (
+                    (
+                        done
+                        { "liftoff" }
+                        { ((n 1 "-") "binop_arith") |* plz | again }
+                    )
+                    "if"
+                ) |* plz
+            }
+
+            1000000 | countdown
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::String("liftoff".to_string()))
+    );
+}
+
+#[test]
+fn test_tail_call_loop_appears_once_in_call_stack() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            if: { (($0 $1 $2) "if") |* plz }
+            eq: { (($0 $1 "==") "binop_cmp") |* plz }
+            sub: { (($0 $1 "-") "binop_arith") |* plz }
+            div: { (($0 $1 "/") "binop_arith") |* plz }
+
+            countdown: {
+                n: $0
+                again: rec
+                ((n 0) |* eq { (1 n) |* div } { (n 1) |* sub | again }) |* if
+            }
+
+            3 | countdown
+        }
+    "#,
+    );
+
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::DivisionByZero);
+    assert_eq!(
+        error
+            .call_stack
+            .iter()
+            .map(|frame| frame.name.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "plz \"binop_arith\"",
+            "block `div`",
+            "plz \"if\"",
+            "block `if`",
+            "block `countdown`",
+            "anonymous block",
+        ]
+    );
+}
+
+#[test]
+fn test_match_literals_and_types() {
+    let code = r#"
+        () | {
+            describe: {
+                match ($0) {
+                    0 { "zero" }
+                    "hi" { "greeting" }
+                    T { "yes" }
+                    Integer { "integer" }
+                    Float { "float" }
+                    Tuple { "tuple" }
+                    _ { "something else" }
+                }
+            }
+            (0 | describe "hi" | describe T | describe 7 | describe
+             1.5 | describe (1 2) | describe F | describe)
+        }
+    "#;
+
+    let strings = |values: &[&str]| {
+        Value::Tuple(
+            values
+                .iter()
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        )
+    };
+    assert_eq!(
+        lex_parse_evaluate(code),
+        Ok(strings(&[
+            "zero",
+            "greeting",
+            "yes",
+            "integer",
+            "float",
+            "tuple",
+            "something else",
+        ]))
+    );
+}
+
+#[test]
+fn test_match_captures() {
+    let code = r#"
+        () | {
+            swap: {
+                match ($0) {
+                    ($a ($b _)) { ($b $a) }
+                    (name: $name) { $name }
+                    $other { ($0 $n) }
+                }
+            }
+            ((1 (2 3)) | swap
+             (age: 3 name: "x") | swap
+             "y" | swap)
+        }
+    "#;
+
+    assert_eq!(
+        lex_parse_evaluate(code),
+        Ok(Value::Tuple(vec![
+            Value::Tuple(vec![Value::Integer(2), Value::Integer(1)]),
+            Value::String("x".to_string()),
+            Value::Tuple(vec![
+                Value::String("y".to_string()),
+                Value::Integer(1)
+            ]),
+        ]))
+    );
+}
+
+#[test]
+fn test_match_guards() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            lt: { (($0 $1 "<") "binop_cmp") |* plz }
+            order: {
+                match ($0) {
+                    ($a $b) when { ($a $b) |* lt } { "ascending" }
+                    ($a $a2) when { ($a2 $a) |* lt } { "descending" }
+                    _ { "equal" }
+                }
+            }
+            ((1 2) | order (2 1) | order (1 1) | order)
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::String("ascending".to_string()),
+            Value::String("descending".to_string()),
+            Value::String("equal".to_string()),
+        ]))
+    );
+}
+
+#[test]
+fn test_match_errors() {
+    let error = lex_parse_evaluate("match ((1 2)) { ($a) { $a } 1 { 2 } }")
+        .unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::NoMatch(TypeDescriptor::Tuple(vec![
+            TypeDescriptor::Integer,
+            TypeDescriptor::Integer
+        ]))
+    );
+    assert_eq!(error.span, Some(0..37));
+    assert_eq!(
+        error.kind.to_string(),
+        "No pattern matches a value of type (Integer Integer)"
+    );
+
+    let error =
+        lex_parse_evaluate("match (1) { _ when { 1 } { 2 } }").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::TypeMismatch(
+            "Match guards must produce a Boolean, not Integer".to_string()
+        )
+    );
+    assert_eq!(error.span, Some(19..24));
+
+    let error = lex_parse_evaluate("match (1) { $x { $x.0 } }").unwrap_err();
+    assert_eq!(error.span, Some(17..21));
+    assert_eq!(error.call_stack[0].name, "match arm");
+}
+
+#[test]
+fn test_match_tail_calls_do_not_grow_the_stack() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            countdown: {
+                again: rec
+                match ($0) {
+                    0 { "liftoff" }
+                    $k { (($k 1 "-") "binop_arith") |* plz | again }
+                }
+            }
+
+            1000000 | countdown
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::String("liftoff".to_string()))
+    );
+}
+
+#[test]
+fn test_match_warnings() {
+    let warnings = |code: &str| {
+        match_warnings(&lex_and_parse(code).unwrap())
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        warnings("{ match ((1 T)) { (0 _) { 1 } (Integer T) { 2 } } }"),
+        vec!["Match on a value of type (Integer Boolean) is not exhaustive"]
+    );
+    assert_eq!(
+        warnings("match (T) { T { 1 } _ when { T } { 2 } }"),
+        vec!["Match on a value of type Boolean is not exhaustive"]
+    );
+    assert!(warnings("match (T) { T { 1 } F { 2 } }").is_empty());
+    assert!(
+        warnings("match ((a: 1 b: \"x\")) { (b: String) { 1 } }").is_empty()
+    );
+    assert!(warnings("match ((1 T)) { ($a Boolean) { 1 } }").is_empty());
+    // nothing is known about the type of a name, or of a pipe's result
+    assert!(warnings("match (x) { 1 { 1 } }").is_empty());
+    assert!(warnings("match (x | f) { 1 { 1 } }").is_empty());
+
+    let code = "match (1) { 0 { 1 } }";
+    let warning = &match_warnings(&lex_and_parse(code).unwrap())[0];
+    assert_eq!(
+        warning.render(code),
+        indoc! {"
+            warning: Match on a value of type Integer is not exhaustive
+             --> 1:1
+              |
+            1 | match (1) { 0 { 1 } }
+              | ^^^^^^^^^^^^^^^^^^^^^
+        "}
+    );
+}
+
+#[test]
+fn test_compare_types() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            same_type: {
+                ((type ($0) type ($1) "==") "binop_cmp") |* plz
+            }
+
+            (
+                (1 2) |* same_type
+                (1 "one") |* same_type
+                ((1 "a") (2 "b")) |* same_type
+            )
+        }
+    "#,
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Boolean(true),
+            Value::Boolean(false),
+            Value::Boolean(true)
+        ]))
+    );
+}
+
+#[test]
+fn test_unbound_symbol_error() {
+    assert_eq!(
+        lex_parse_evaluate("(a)").map_err(|error| error.kind),
+        Err(ErrorKind::UnboundSymbol("a".to_string()))
+    );
+}
+
+#[test]
+fn test_destructure_error() {
+    assert_eq!(
+        lex_parse_evaluate("1 |* { $0 }").map_err(|error| error.kind),
+        Err(ErrorKind::DestructureNonTuple(TypeDescriptor::Integer))
+    );
+}
+
+#[test]
+fn test_runtime_error_call_stack() {
+    let mut interpreter = interpreter_with_runtime(
+        r#"
+        () | {
+            div: {
+                (($0 $1 "/") "binop_arith") |* plz
+            }
+
+            (1 0) |* div
+        }
+    "#,
+    );
+
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::DivisionByZero);
+    assert_eq!(
+        error
+            .call_stack
+            .iter()
+            .map(|frame| frame.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["plz \"binop_arith\"", "block `div`", "anonymous block"]
+    );
+}
+
+fn repeat_call() -> RuntimeCall {
+    RuntimeCall::new(
+        "repeat",
+        vec![
+            Pattern::Type("String".to_string()),
+            Pattern::Type("Integer".to_string()),
+        ],
+        |parameters| match &parameters[..] {
+            [Value::String(s), Value::Integer(n)] => {
+                Ok(Value::String(s.repeat(*n as usize)))
+            }
+            _ => unreachable!(),
+        },
+    )
+    .returning(Pattern::Type("String".to_string()))
+}
+
+fn interpreter_with_registry(
+    code: &str,
+    runtime: RuntimeRegistry,
+) -> Interpreter {
+    let mut interpreter = interpreter_with_runtime(code);
+    interpreter.set_runtime(runtime);
+    interpreter
+}
+
+#[test]
+fn test_runtime_registry() {
+    let mut runtime = RuntimeRegistry::default();
+    runtime.register(repeat_call());
+
+    let mut interpreter = interpreter_with_registry(
+        r#"(("ab" 3) "repeat") |* plz"#,
+        runtime.clone(),
+    );
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::String("ababab".to_string()))
+    );
+
+    // parameters are checked against the types the call declares
+    let mut interpreter = interpreter_with_registry(
+        r#"(("ab" "c") "repeat") |* plz"#,
+        runtime.clone(),
+    );
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::AnnotationMismatch {
+            expected: Pattern::Type("Integer".to_string()),
+            found: TypeDescriptor::String,
+        }
+    );
+    assert_eq!(error.call_stack[0].name, "plz \"repeat\"");
+
+    let mut interpreter = interpreter_with_registry(
+        r#"(("ab") "repeat") |* plz"#,
+        runtime.clone(),
+    );
+    assert_eq!(
+        interpreter.evaluate_from_root(None).unwrap_err().kind,
+        ErrorKind::ArityMismatch {
+            call: "repeat".to_string(),
+            expected: 2,
+            found: 1,
+        }
+    );
+
+    // as is what the call gives back
+    runtime.register(
+        RuntimeCall::new("lie", vec![], |_| Ok(Value::Integer(1)))
+            .returning(Pattern::Type("String".to_string())),
+    );
+    let mut interpreter =
+        interpreter_with_registry(r#"(() "lie") |* plz"#, runtime.clone());
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::AnnotationMismatch {
+            expected: Pattern::Type("String".to_string()),
+            found: TypeDescriptor::Integer,
+        }
+    );
+    assert_eq!(error.call_stack[0].name, "plz \"lie\"");
+
+    // calls left out of the registry can't be made, though `if` always can
+    runtime.remove("print");
+    let mut interpreter = interpreter_with_registry(
+        r#"((T { (("x") "print") |* plz } { () }) "if") |* plz"#,
+        runtime,
+    );
+    assert_eq!(
+        interpreter.evaluate_from_root(None).unwrap_err().kind,
+        ErrorKind::UnknownRuntimeCall("print".to_string())
+    );
+}
+
+fn interpreter_with_limits(code: &str, limits: Limits) -> Interpreter {
+    let mut interpreter = interpreter_with_runtime(code);
+    interpreter.set_limits(limits);
+    interpreter
+}
+
+#[test]
+fn test_call_depth_limit() {
+    let source = indoc! {"
+        () | {
+            deeper: { (() | rec) }
+            () | deeper
+        }
+    "};
+    let mut interpreter = interpreter_with_limits(
+        source,
+        Limits {
+            max_call_depth: Some(20),
+            ..Limits::default()
+        },
+    );
+
+    // piping into `deeper` is a tail call, which doesn't count towards the
+    // depth, so it's `rec` that runs out
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::CallDepth,
+            max: 20
+        }
+    );
+    assert_eq!(
+        error.render(source),
+        indoc! {"
+            error: Call depth limit of 20 exceeded
+             --> 2:16
+              |
+            2 |     deeper: { (() | rec) }
+              |                ^^^^^^^^
+              = in block `rec` at 2:21
+              = ... repeated 19 more times
+              = in block `deeper` at 3:10
+              = in anonymous block at 1:6
+        "}
+    );
+}
+
+#[test]
+fn test_step_limit_stops_endless_loops() {
+    let mut interpreter = interpreter_with_limits(
+        "() | { () | { () | rec } }",
+        Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        },
+    );
+
+    assert_eq!(
+        interpreter.evaluate_from_root(None).unwrap_err().kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::Steps,
+            max: 1000
+        }
+    );
+}
+
+#[test]
+fn test_size_limits() {
+    let limits = Limits {
+        max_tuple_size: Some(3),
+        max_string_length: Some(5),
+        ..Limits::default()
+    };
+
+    let mut interpreter = interpreter_with_limits("(1 (2 3 4) 5)", limits);
+    assert_eq!(
+        interpreter.evaluate_from_root(None),
+        Ok(Value::Tuple(vec![
+            Value::Integer(1),
+            Value::Tuple(vec![
+                Value::Integer(2),
+                Value::Integer(3),
+                Value::Integer(4)
+            ]),
+            Value::Integer(5),
+        ]))
+    );
+
+    let mut interpreter =
+        interpreter_with_limits("(1 paste ((2 3)) 4)", limits);
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::TupleSize,
+            max: 3
+        }
+    );
+    assert_eq!(error.span, Some(0..19));
+
+    let mut interpreter =
+        interpreter_with_limits(r#"(("abc" "def") "strcat") |* plz"#, limits);
+    assert_eq!(
+        interpreter.evaluate_from_root(None).unwrap_err().kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::StringLength,
+            max: 5
+        }
+    );
+
+    // values a runtime call builds are checked all the way down
+    let mut interpreter = interpreter_with_limits(
+        r#"(("[[1,2,3,4]]") "json_parse") |* plz"#,
+        Limits {
+            max_tuple_size: Some(3),
+            ..Limits::default()
+        },
+    );
+    let error = interpreter.evaluate_from_root(None).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::LimitExceeded {
+            limit: Limit::TupleSize,
+            max: 3
+        }
+    );
+    assert_eq!(error.call_stack[0].name, "plz \"json_parse\"");
+}
+
+#[test]
+fn test_parse_error_is_reported() {
+    let error = crate::interpret_from_string("(1 2", None, false).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::ParseError(_)));
+    assert_eq!(error.span, Some(3..4));
+}
+
+#[test]
+fn test_lex_error_is_reported() {
+    let source = "(1 99999999999999999999 3)";
+    let error = crate::interpret_from_string(source, None, false).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::ParseError(
+            "Integer literal 99999999999999999999 does not fit in 64 bits"
+                .to_string()
+        )
+    );
+    assert_eq!(error.span, Some(3..23));
+}
+
+#[test]
+fn test_render_error() {
+    let source = "{\n  (a\n    bad)\n}";
+    let error = EvaluationError::new(ErrorKind::UnboundSymbol(
+        "bad".to_string(),
+    ))
+    .with_span(11..14)
+    .in_call("block `main`", None);
+
+    assert_eq!(
+        error.render(source),
+        indoc! {"
+            error: Unbound symbol 'bad'
+             --> 3:5
+              |
+            3 |     bad)
+              |     ^^^
+              = in block `main`
+        "}
+    );
+}
+
+#[test]
+fn test_error_spans() {
+    assert_eq!(lex_parse_evaluate("(1 a)").unwrap_err().span, Some(3..4));
+
+    let source = indoc! {"
+        {
+            check: {
+                $0 |* { missing }
+            }
+
+            (1 2) | check
+        }
+    "};
+    let error = crate::interpret_from_string(source, None, true).unwrap_err();
+
+    assert_eq!(
+        error.render(source),
+        indoc! {"
+            error: Unbound symbol 'missing'
+             --> 3:17
+              |
+            3 |         $0 |* { missing }
+              |                 ^^^^^^^
+              = in anonymous block at 3:15
+              = in block `check` at 6:13
+        "}
+    );
+}
+
+#[test]
+fn test_render_mismatched_span() {
+    // a span from some other source is kept off the middle of a character,
+    // rather than making rendering panic
+    let error = EvaluationError::new(ErrorKind::DivisionByZero)
+        .with_span(3..5)
+        .in_call("block `f`", Some(5..40));
+    assert_eq!(
+        error.render("éé x"),
+        indoc! {"
+            error: Division by zero
+             --> 1:2
+              |
+            1 | éé x
+              |  ^^
+              = in block `f` at 1:4
+        "}
+    );
+}
+
+#[test]
+fn test_runtime_call_error_spans() {
+    let source = indoc! {r#"
+        {
+            check: {
+                $0 |* { (($0 $1 "/") "binop_arith") |* plz }
+            }
+
+            (1 0) | check
+        }
+    "#};
+    let error = crate::interpret_from_string(source, None, true).unwrap_err();
+
+    assert_eq!(
+        error.render(source),
+        indoc! {r#"
+            error: Division by zero
+             --> 3:17
+              |
+            3 |         $0 |* { (($0 $1 "/") "binop_arith") |* plz }
+              |                 ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+              = in plz "binop_arith" at 3:48
+              = in anonymous block at 3:15
+              = in block `check` at 6:13
+        "#}
+    );
+}
+
+#[test]
+fn test_unbound_symbol_in_pasting_block() {
+    // a paste might have bound the name, so it can only be missed at runtime
+    let error =
+        crate::interpret_from_string("{ paste (()) missing }", None, true)
+            .unwrap_err();
+    assert_eq!(error.kind, ErrorKind::UnboundSymbol("missing".to_string()));
+    assert_eq!(error.call_stack, vec![]);
+    assert_eq!(error.span, Some(13..20));
+}
+
+/// writes the given files into a fresh directory, returning its path. these
+/// tests run once per engine, so each copy gets directories of its own.
+fn module_directory(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "blockpipe-{}-{}-{}",
+        module_path!().replace("::", "-"),
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+
+    for (path, contents) in files {
+        let path = directory.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    directory
+}
+
+#[test]
+fn test_import_module() {
+    let directory = module_directory(
+        "import",
+        &[
+            (
+                "lib/math.blkp",
+                r#"{
+                    consts: import "consts.blkp"
+                    paste (consts)
+                    add: { (($0 $1 "+") "binop_arith") |* plz }
+                    double: { ($0 $0) |* add }
+                }"#,
+            ),
+            ("lib/consts.blkp", "{ ten: 10 }"),
+        ],
+    );
+
+    let source = r#"{
+        math: import "lib/math.blkp"
+        paste (math)
+        (ten | double  (ten 1) |* add)
+    }"#;
+    let result =
+        crate::interpret_from_string_at(source, Some(&directory), None, true);
+
+    assert_eq!(
+        result,
+        Ok(Value::Tuple(vec![Value::Integer(20), Value::Integer(11)]))
+    );
+}
+
+#[test]
+fn test_import_namespaced() {
+    let directory = module_directory(
+        "namespaced",
+        &[(
+            "lib.blkp",
+            r#"{
+                ten: 10
+                double: { (($0 $0 "+") "binop_arith") |* plz }
+            }"#,
+        )],
+    );
+
+    let source = r#"{
+        m: import "lib.blkp"
+        (3 | m.double  m.ten)
+    }"#;
+    let result =
+        crate::interpret_from_string_at(source, Some(&directory), None, true);
+    assert_eq!(
+        result,
+        Ok(Value::Tuple(vec![Value::Integer(6), Value::Integer(10)]))
+    );
+
+    let source = r#"{
+        m: import "lib.blkp"
+        m.triple
+    }"#;
+    let result =
+        crate::interpret_from_string_at(source, Some(&directory), None, true);
+    assert_eq!(
+        result.unwrap_err().kind,
+        ErrorKind::MissingField {
+            field: "triple".to_string(),
+            found: TypeDescriptor::Closure,
+        }
+    );
+}
+
+#[test]
+fn test_module_error_spans() {
+    let directory = module_directory(
+        "spans",
+        &[(
+            "lib.blkp",
+            indoc! {r#"
+                {
+                    halve: { (($0 0 "/") "binop_arith") |* plz }
+                }
+            "#},
+        )],
+    );
+
+    let source = indoc! {r#"
+        {
+            m: import "lib.blkp"
+            4 | m.halve
+        }
+    "#};
+    let error =
+        crate::interpret_from_string_at(source, Some(&directory), None, true)
+            .unwrap_err();
+
+    // the error lies in the module, while the call that led to it doesn't
+    let path = std::fs::canonicalize(directory.join("lib.blkp")).unwrap();
+    assert_eq!(
+        error.render(source),
+        format!(
+            indoc! {r#"
+                error: Division by zero
+                 --> {}:2:14
+                  |
+                2 |     halve: {{ (($0 0 "/") "binop_arith") |* plz }}
+                  |              ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+                  = in plz "binop_arith" at {}:2:44
+                  = in anonymous block at 3:9
+            "#},
+            path.display(),
+            path.display()
+        )
+    );
+}
+
+#[test]
+fn test_import_is_cached() {
+    let directory = module_directory("cached", &[("one.blkp", "{ x: 1 }")]);
+    let mut import = crate::parse_from_string(r#"import "one.blkp""#).unwrap();
+    resolve_imports(&mut import, &directory);
+
+    let mut interpreter = Interpreter::new(import.clone());
+    interpreter.env.push_stack_frame();
+    let first = interpreter.evaluate(&import).unwrap();
+
+    // changes to the file after it has been imported go unnoticed
+    std::fs::write(directory.join("one.blkp"), "{ x: 2 }").unwrap();
+    let second = interpreter.evaluate(&import).unwrap();
+
+    assert_eq!(first, second);
+    let Value::Closure(_, bindings) = second else {
+        panic!("expected a module, found {:?}", second);
+    };
+    assert_eq!(bindings.get("x"), Some(&Value::Integer(1)));
+}
+
+#[test]
+fn test_cyclic_import() {
+    let directory = module_directory(
+        "cyclic",
+        &[
+            ("a.blkp", r#"{ b: import "b.blkp" }"#),
+            ("b.blkp", r#"{ a: import "a.blkp" }"#),
+        ],
+    );
+
+    let error = crate::interpret_from_string_at(
+        r#"import "a.blkp""#,
+        Some(&directory),
+        None,
+        false,
+    )
+    .unwrap_err();
+
+    let ErrorKind::ModuleError { report, .. } = error.kind else {
+        panic!("expected a module error, found {:?}", error.kind);
+    };
+    assert!(report.contains("Cyclic import: "));
+    assert!(report.contains("a.blkp -> "));
+    assert_eq!(error.span, Some(0..15));
+}
+
+#[test]
+fn test_import_missing_module() {
+    let directory = module_directory("missing", &[]);
+    let error = crate::interpret_from_string_at(
+        r#"import "nowhere.blkp""#,
+        Some(&directory),
+        None,
+        false,
+    )
+    .unwrap_err();
+
+    assert!(matches!(
+        error.kind,
+        ErrorKind::ImportFailed { path, .. } if path.ends_with("nowhere.blkp")
+    ));
 }
@@ -1,6 +1,10 @@
 use core::fmt;
-use std::{collections::{HashMap, HashSet}, fmt::Formatter, fmt::Display};
-use crate::parser::ASTNode;
+use std::{collections::HashMap, fmt::Formatter, fmt::Display, rc::Rc};
+use super::pattern::type_name;
+use super::shape::unindexable;
+use super::{conforms, Block, Elements, ErrorKind, EvaluationError, Shape};
+use crate::parser::{Accessor, Parameters, Pattern};
+use crate::{native, vm};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
@@ -12,10 +16,304 @@ pub enum Value {
 
     // tuples
     Tuple(Vec<Value>),
+    // tuples whose elements are labelled, in the order they were written
+    Record(Vec<(String, Value)>),
 
-    // closure
-    Closure(Vec<ASTNode>, HashMap<String, Value>),
+    // closure, over the values of the names its block uses
+    Closure(Rc<Block>, Rc<HashMap<String, Value>>),
+    // a closure created by the bytecode machine
+    CompiledClosure(vm::Closure),
+    // a closure created by a program compiled to native code
+    NativeClosure(native::NativeClosure),
     RuntimeInvocation, // special type of closure to invoke runtime calls
+
+    // the result of a `type (...)` expression
+    Type(TypeDescriptor),
+}
+
+/// describes the shape of a value. produced by `type (...)`, so that programs
+/// can inspect, compare and branch on the kind of data flowing through them.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeDescriptor {
+    Integer,
+    Float,
+    String,
+    Boolean,
+    Tuple(Vec<TypeDescriptor>),
+    Record(Vec<(String, TypeDescriptor)>),
+    Closure,
+    RuntimeInvocation,
+    Type,
+}
+
+impl TypeDescriptor {
+    pub fn of(value: &Value) -> TypeDescriptor {
+        match value {
+            Value::Integer(_) => TypeDescriptor::Integer,
+            Value::Boolean(_) => TypeDescriptor::Boolean,
+            Value::String(_) => TypeDescriptor::String,
+            Value::Float(_) => TypeDescriptor::Float,
+            Value::Tuple(values) => {
+                TypeDescriptor::Tuple(values.iter().map(Self::of).collect())
+            }
+            Value::Record(fields) => TypeDescriptor::Record(
+                fields
+                    .iter()
+                    .map(|(label, value)| (label.clone(), Self::of(value)))
+                    .collect(),
+            ),
+            Value::Closure(_, _)
+            | Value::CompiledClosure(_)
+            | Value::NativeClosure(_) => TypeDescriptor::Closure,
+            Value::RuntimeInvocation => TypeDescriptor::RuntimeInvocation,
+            Value::Type(_) => TypeDescriptor::Type,
+        }
+    }
+}
+
+impl Value {
+    /// whether the value can be piped into like a block
+    pub fn is_closure(&self) -> bool {
+        matches!(
+            self,
+            Value::Closure(_, _)
+                | Value::CompiledClosure(_)
+                | Value::NativeClosure(_)
+        )
+    }
+
+    /// the bindings a closure carries, which pasting it brings into scope:
+    /// what it captured, less the parameters and rec of the invocation that
+    /// created it. `None` for values that aren't closures.
+    pub fn bindings(&self) -> Option<HashMap<String, Value>> {
+        let mut image = match self {
+            Value::Closure(_, image) => image.as_ref().clone(),
+            Value::CompiledClosure(closure) => closure.image(),
+            Value::NativeClosure(closure) => closure.image(),
+            _ => return None,
+        };
+        image.retain(|id, _| !id.starts_with('$') && id != "rec");
+        Some(image)
+    }
+
+    /// a field, element or slice of a tuple or record. records are tuples
+    /// too, so their fields can be indexed and sliced as well.
+    pub fn access(&self, accessor: &Accessor) -> Result<Value, ErrorKind> {
+        if let Accessor::Field(label) = accessor {
+            return self.field(label);
+        }
+
+        match self.elements() {
+            Some(elements) => elements.access(accessor),
+            None => Err(unindexable(TypeDescriptor::of(self))),
+        }
+    }
+
+    /// the value of a record's field, or of a binding a closure captured -
+    /// which is how the bindings of an imported module are reached
+    fn field(&self, label: &str) -> Result<Value, ErrorKind> {
+        let found = match self {
+            Value::Record(fields) => fields
+                .iter()
+                .find(|(field, _)| field == label)
+                .map(|(_, value)| value.clone()),
+            _ => self.bindings().and_then(|mut bindings| bindings.remove(label)),
+        };
+
+        found.ok_or_else(|| ErrorKind::MissingField {
+            field: label.to_string(),
+            found: TypeDescriptor::of(self),
+        })
+    }
+}
+
+impl Shape for Value {
+    fn tuple(elements: Vec<Value>) -> Value {
+        Value::Tuple(elements)
+    }
+
+    fn record(fields: Vec<(String, Value)>) -> Value {
+        Value::Record(fields)
+    }
+
+    fn count(count: usize) -> Value {
+        Value::Integer(count as i64)
+    }
+
+    fn type_name(&self) -> Option<&'static str> {
+        Some(type_name(&TypeDescriptor::of(self)))
+    }
+
+    fn text(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn descriptor(&self) -> Option<TypeDescriptor> {
+        Some(TypeDescriptor::of(self))
+    }
+
+    fn conform(&self, annotation: &Pattern) -> Result<(), ErrorKind> {
+        conforms(annotation, self)
+    }
+
+    fn elements(&self) -> Option<Elements<'_, Value>> {
+        match self {
+            Value::Tuple(values) => Some(Elements::Tuple(values)),
+            Value::Record(fields) => Some(Elements::Record(fields)),
+            _ => None,
+        }
+    }
+
+    fn destructure(self) -> Result<Arguments, Value> {
+        match self {
+            Value::Tuple(values) => Ok(values.into()),
+            Value::Record(fields) => {
+                let (labels, values) = fields.into_iter().unzip();
+                Ok(Arguments { values, labels })
+            }
+            other => Err(other),
+        }
+    }
+}
+
+/// what a block is invoked with: the values of `$0`, `$1`, ... along with
+/// their labels, if they were destructured from a record. the checker works
+/// out the types of arguments the same way, as `Arguments<Type>`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arguments<T = Value> {
+    pub values: Vec<T>,
+    pub labels: Vec<String>,
+}
+
+impl<T: Shape> Arguments<T> {
+    /// the arguments a pipe passes to the block it pipes into
+    pub fn piped(input: T, destructure: bool) -> Result<Self, ErrorKind> {
+        if !destructure {
+            return Ok(vec![input].into());
+        }
+        input.destructure().map_err(|other| {
+            ErrorKind::DestructureNonTuple(
+                other.descriptor().expect("only tuples have unknown parts"),
+            )
+        })
+    }
+
+    /// the value of a parameter - `$n`, `$0`, `$1`, ... or `$` followed by a
+    /// label, as well as `$@` for every argument and `$1..`, `$2..`, ... for
+    /// the arguments from some point on. `$n` is always the number of
+    /// arguments, even when one of them is labelled n.
+    pub fn parameter(&self, name: &str) -> Option<T> {
+        let name = name.strip_prefix('$')?;
+        if name == "n" {
+            return Some(T::count(self.values.len()));
+        }
+        if name == "@" {
+            return Some(self.rest(0));
+        }
+        if let Some(start) = name.strip_suffix("..") {
+            return Some(self.rest(start.parse().ok()?));
+        }
+
+        let index = match name.parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => self.labels.iter().position(|label| label == name)?,
+        };
+        self.values.get(index).cloned()
+    }
+
+    /// the arguments from the given position on, as a tuple - or as a
+    /// record, if they are labelled. empty if there are fewer arguments.
+    pub fn rest(&self, start: usize) -> T {
+        let start = start.min(self.values.len());
+        let values = self.values[start..].to_vec();
+        if self.labels.is_empty() {
+            T::tuple(values)
+        } else {
+            let labels = self.labels[start..].iter().cloned();
+            T::record(labels.zip(values).collect())
+        }
+    }
+
+    /// pairs each parameter a block declares with the argument it's given,
+    /// after checking that there are the right number of arguments for them
+    pub fn assign(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Vec<(String, T)>, ErrorKind> {
+        let expected = parameters.names.len();
+        let found = self.values.len();
+        match &parameters.rest {
+            None if found != expected => {
+                return Err(ErrorKind::ArityMismatch {
+                    call: "Block".to_string(),
+                    expected,
+                    found,
+                })
+            }
+            Some(_) if found < expected => {
+                return Err(ErrorKind::MissingArguments { expected, found })
+            }
+            _ => {}
+        }
+
+        let named = parameters
+            .names
+            .iter()
+            .zip(&self.values)
+            .map(|((name, _), value)| (name.clone(), value.clone()));
+        let rest = parameters
+            .rest
+            .iter()
+            .map(|(name, _)| (name.clone(), self.rest(expected)));
+        Ok(named.chain(rest).collect())
+    }
+}
+
+impl Arguments {
+    /// declare
+    ///
+    /// Gives the value of each parameter a block declares, after checking
+    /// that the block was given the right number of arguments for them, and
+    /// that they have the types the parameters are annotated with.
+    pub fn declare(
+        &self,
+        parameters: &Parameters,
+    ) -> Result<Vec<(String, Value)>, EvaluationError> {
+        let declared = self.assign(parameters)?;
+
+        // mismatched types are pointed out on the parameter itself
+        for ((name, value), (_, span)) in
+            declared.iter().zip(parameters.declared())
+        {
+            if let Some(annotation) = parameters.annotation(name) {
+                conforms(annotation, value).map_err(|kind| {
+                    EvaluationError::from(kind).with_span(span.clone())
+                })?;
+            }
+        }
+        Ok(declared)
+    }
+}
+
+impl<T> Default for Arguments<T> {
+    fn default() -> Self {
+        Arguments {
+            values: vec![],
+            labels: vec![],
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Arguments<T> {
+    fn from(values: Vec<T>) -> Self {
+        Arguments {
+            values,
+            labels: vec![],
+        }
+    }
 }
 
 impl Display for Value {
@@ -30,14 +328,61 @@ impl Display for Value {
                 for (i, v) in t.iter().enumerate() {
                     s.push_str(&format!("{}", v));
                     if i != t.len() - 1 {
-                        s.push_str(" ");
+                        s.push(' ');
                     }
                 }
-                s.push_str(")");
+                s.push(')');
                 write!(f, "{}", s)
             },
-            Value::Closure(_, _) => write!(f, "<closure>"),
+            Value::Record(fields) => {
+                write!(f, "(")?;
+                for (i, (label, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", label, value)?;
+                }
+                write!(f, ")")
+            }
+            Value::Closure(_, _)
+            | Value::CompiledClosure(_)
+            | Value::NativeClosure(_) => write!(f, "<closure>"),
             Value::RuntimeInvocation => write!(f, "<runtime invocation>"),
+            Value::Type(t) => write!(f, "{}", t),
+        }
+    }
+}
+
+impl Display for TypeDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeDescriptor::Integer => write!(f, "Integer"),
+            TypeDescriptor::Float => write!(f, "Float"),
+            TypeDescriptor::String => write!(f, "String"),
+            TypeDescriptor::Boolean => write!(f, "Boolean"),
+            TypeDescriptor::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            }
+            TypeDescriptor::Record(fields) => {
+                write!(f, "(")?;
+                for (i, (label, field)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}: {}", label, field)?;
+                }
+                write!(f, ")")
+            }
+            TypeDescriptor::Closure => write!(f, "Closure"),
+            TypeDescriptor::RuntimeInvocation => write!(f, "RuntimeInvocation"),
+            TypeDescriptor::Type => write!(f, "Type"),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+use core::fmt;
+use std::fmt::{Display, Formatter};
+
+use logos::Span;
+
+/// the different ways in which lexing can fail. this is the error type that
+/// logos produces for each token, so it has to know how to default to
+/// something when no pattern matches at all.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum LexErrorKind {
+    #[default]
+    UnexpectedCharacter,
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidEscape(String),
+    IntegerOverflow,
+}
+
+/// a lexer error, along with the byte span and text of the offending input
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+    pub text: String,
+}
+
+impl LexError {
+    pub fn new(kind: LexErrorKind, span: Span, input: &str) -> LexError {
+        LexError {
+            kind,
+            text: input[span.clone()].to_string(),
+            span,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnexpectedCharacter => {
+                write!(f, "Unexpected character {:?}", self.text)
+            }
+            LexErrorKind::UnterminatedString => {
+                write!(f, "Unterminated string literal")
+            }
+            LexErrorKind::UnterminatedComment => {
+                write!(f, "Unterminated block comment")
+            }
+            LexErrorKind::InvalidEscape(sequence) => write!(
+                f,
+                "Invalid escape sequence {} in string literal",
+                sequence
+            ),
+            LexErrorKind::IntegerOverflow => write!(
+                f,
+                "Integer literal {} does not fit in 64 bits",
+                self.text
+            ),
+        }
+    }
+}
@@ -1,4 +1,6 @@
+mod error;
 #[cfg(test)]
 mod tests;
 mod token;
+pub use error::*;
 pub use token::*;
\ No newline at end of file
@@ -1,9 +1,17 @@
 use super::*;
 use logos::{Logos, Span};
 
+/// lexes the input, keeping only the kind of each error
+fn lex_kinds(input: &str) -> Vec<(Result<Token, LexErrorKind>, Span)> {
+    lex(input)
+        .into_iter()
+        .map(|(tok, span)| (tok.map_err(|error| error.kind), span))
+        .collect()
+}
+
 #[test]
 fn test_integer() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("1 12 -1 -12").spanned().collect();
 
     assert_eq!(
@@ -19,7 +27,7 @@ fn test_integer() {
 
 #[test]
 fn test_float() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("1.0 12.0 -1.0 -12.0").spanned().collect();
 
     assert_eq!(
@@ -35,7 +43,7 @@ fn test_float() {
 
 #[test]
 fn test_string() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("\"hi\" \"bye\"").spanned().collect();
 
     assert_eq!(
@@ -49,7 +57,7 @@ fn test_string() {
 
 #[test]
 fn test_boolean() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("T F").spanned().collect();
 
     assert_eq!(
@@ -63,7 +71,7 @@ fn test_boolean() {
 
 #[test]
 fn test_parentheses() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer(") ( )( () ( )").spanned().collect();
     assert_eq!(
         lexed,
@@ -82,7 +90,7 @@ fn test_parentheses() {
 
 #[test]
 fn test_braces() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("} { }{ {} { }").spanned().collect();
     assert_eq!(
         lexed,
@@ -101,7 +109,7 @@ fn test_braces() {
 
 #[test]
 fn test_pipe() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("| || |").spanned().collect();
 
     assert_eq!(
@@ -117,7 +125,7 @@ fn test_pipe() {
 
 #[test]
 fn test_type_keyword() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("type").spanned().collect();
 
     assert_eq!(lexed, vec![(Ok(Token::Type), 0..4),]);
@@ -125,23 +133,46 @@ fn test_type_keyword() {
 
 #[test]
 fn test_paste_keyword() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("paste").spanned().collect();
 
     assert_eq!(lexed, vec![(Ok(Token::Paste), 0..5),]);
 }
 
+#[test]
+fn test_import_keyword() {
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
+        Token::lexer("import").spanned().collect();
+
+    assert_eq!(lexed, vec![(Ok(Token::Import), 0..6),]);
+}
+
 #[test]
 fn test_colon() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer(":").spanned().collect();
 
     assert_eq!(lexed, vec![(Ok(Token::Colon), 0..1)]);
 }
 
+#[test]
+fn test_annotation_marks() {
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
+        Token::lexer("= -> -1").spanned().collect();
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Equals), 0..1),
+            (Ok(Token::Arrow), 2..4),
+            (Ok(Token::IntegerLiteral(-1)), 5..7),
+        ]
+    );
+}
+
 #[test]
 fn test_identifier() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("hello $0 world").spanned().collect();
 
     assert_eq!(
@@ -154,9 +185,25 @@ fn test_identifier() {
     );
 }
 
+#[test]
+fn test_field_access() {
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
+        Token::lexer("person.name $age").spanned().collect();
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Identifier("person".to_string())), 0..6),
+            (Ok(Token::Dot), 6..7),
+            (Ok(Token::Identifier("name".to_string())), 7..11),
+            (Ok(Token::Identifier("$age".to_string())), 12..16),
+        ]
+    );
+}
+
 #[test]
 fn test_whitespace() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("hello\tworld\n").spanned().collect();
 
     assert_eq!(
@@ -170,7 +217,7 @@ fn test_whitespace() {
 
 #[test]
 fn test_pipestar() {
-    let lexed: Vec<(Result<Token, ()>, Span)> =
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
         Token::lexer("|*").spanned().collect();
 
     assert_eq!(lexed, vec![(Ok(Token::PipeStar), 0..2)]);
@@ -178,7 +225,7 @@ fn test_pipestar() {
 
 #[test]
 fn test_mixed_tokens() {
-    let lexed: Vec<(Result<Token, ()>, Span)> = Token::lexer(
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> = Token::lexer(
         "{type}(T)|-12.34 |* \"hello\" bruh type moment paste",
     )
     .spanned()
@@ -203,4 +250,277 @@ fn test_mixed_tokens() {
             (Ok(Token::Paste), 45..50)
         ]
     );
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_line_comments() {
+    let lexed = lex("a # one\nb // two\n\"# three\"");
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Identifier("a".to_string())), 0..1),
+            (Ok(Token::Identifier("b".to_string())), 8..9),
+            (Ok(Token::StringLiteral("# three".to_string())), 17..26),
+        ]
+    );
+}
+
+#[test]
+fn test_nested_block_comments() {
+    let lexed = lex("a /* one /* two */ still\none */ b");
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Identifier("a".to_string())), 0..1),
+            (Ok(Token::Identifier("b".to_string())), 32..33),
+        ]
+    );
+}
+
+#[test]
+fn test_unterminated_block_comment() {
+    let lexed = lex_kinds("a /* one /* two */");
+
+    assert_eq!(
+        lexed,
+        vec![(Ok(Token::Identifier("a".to_string())), 0..1), (Err(LexErrorKind::UnterminatedComment), 2..18)]
+    );
+}
+
+#[test]
+fn test_lex_with_comments() {
+    let lexed = lex_with_comments("/* a */ b # c");
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::BlockComment("/* a */".to_string())), 0..7),
+            (Ok(Token::Identifier("b".to_string())), 8..9),
+            (Ok(Token::LineComment("# c".to_string())), 10..13),
+        ]
+    );
+}
+
+#[test]
+fn test_string_escapes() {
+    let lexed = lex(r#""a\nb\t\"c\"\\ \x41\u{1F600}\0""#);
+
+    assert_eq!(
+        lexed,
+        vec![(
+            Ok(Token::StringLiteral(
+                "a\nb\t\"c\"\\ A\u{1F600}\0".to_string()
+            )),
+            0..31
+        )]
+    );
+}
+
+#[test]
+fn test_invalid_string_escapes() {
+    for (invalid, sequence) in [
+        (r#""\q""#, r"\q"),
+        (r#""\x80""#, r"\x80"),
+        (r#""\x4""#, r"\x4"),
+        (r#""\u{}""#, r"\u{}"),
+        (r#""\u{110000}""#, r"\u{110000}"),
+        (r#""\u{1234567}""#, r"\u{1234567"),
+    ] {
+        assert_eq!(
+            lex_kinds(invalid),
+            vec![(
+                Err(LexErrorKind::InvalidEscape(sequence.to_string())),
+                0..invalid.len()
+            )],
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_raw_strings() {
+    let lexed = lex("r\"C:\\path\" r#\"say \"hi\"\nthere\"# a");
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::StringLiteral("C:\\path".to_string())), 0..10),
+            (
+                Ok(Token::StringLiteral("say \"hi\"\nthere".to_string())),
+                11..30
+            ),
+            (Ok(Token::Identifier("a".to_string())), 31..32),
+        ]
+    );
+}
+
+#[test]
+fn test_unterminated_raw_string() {
+    assert_eq!(
+        lex_kinds("r#\"abc\" def"),
+        vec![(Err(LexErrorKind::UnterminatedString), 0..11)]
+    );
+}
+
+#[test]
+fn test_unexpected_character() {
+    let lexed = lex("a ; b");
+
+    assert_eq!(lexed[0], (Ok(Token::Identifier("a".to_string())), 0..1));
+    assert_eq!(
+        lexed[1],
+        (
+            Err(LexError {
+                kind: LexErrorKind::UnexpectedCharacter,
+                span: 2..3,
+                text: ";".to_string()
+            }),
+            2..3
+        )
+    );
+    assert_eq!(lexed[2], (Ok(Token::Identifier("b".to_string())), 4..5));
+}
+
+#[test]
+fn test_unterminated_string() {
+    assert_eq!(
+        lex_kinds("a \"abc\\\" def"),
+        vec![
+            (Ok(Token::Identifier("a".to_string())), 0..1),
+            (Err(LexErrorKind::UnterminatedString), 2..12),
+        ]
+    );
+}
+
+#[test]
+fn test_unterminated_string_ending_in_backslash() {
+    assert_eq!(
+        lex_kinds("a \"abc\\"),
+        vec![
+            (Ok(Token::Identifier("a".to_string())), 0..1),
+            (Err(LexErrorKind::UnterminatedString), 2..7),
+        ]
+    );
+}
+
+#[test]
+fn test_integer_overflow() {
+    let lexed = lex("99999999999999999999 1");
+
+    assert_eq!(
+        lexed,
+        vec![
+            (
+                Err(LexError {
+                    kind: LexErrorKind::IntegerOverflow,
+                    span: 0..20,
+                    text: "99999999999999999999".to_string()
+                }),
+                0..20
+            ),
+            (Ok(Token::IntegerLiteral(1)), 21..22),
+        ]
+    );
+}
+
+#[test]
+fn test_lex_error_display() {
+    let (error, _) = &lex("\"a\\qb\"")[0];
+
+    assert_eq!(
+        error.as_ref().unwrap_err().to_string(),
+        "Invalid escape sequence \\q in string literal"
+    );
+}
+
+#[test]
+fn test_is_incomplete() {
+    assert!(!is_incomplete("x: (1 2)"));
+    assert!(is_incomplete("x: { (1"));
+    assert!(is_incomplete("x: { \"abc"));
+    assert!(is_incomplete("/* still going"));
+    assert!(!is_incomplete("x: (1 2))"));
+    assert!(!is_incomplete(""));
+}
+
+#[test]
+fn test_index_and_slice_access() {
+    assert_eq!(
+        lex_kinds("t.1..-3 t.0.1"),
+        vec![
+            (Ok(Token::Identifier("t".to_string())), 0..1),
+            (Ok(Token::Dot), 1..2),
+            (Ok(Token::IntegerLiteral(1)), 2..3),
+            (Ok(Token::DotDot), 3..5),
+            (Ok(Token::IntegerLiteral(-3)), 5..7),
+            (Ok(Token::Identifier("t".to_string())), 8..9),
+            (Ok(Token::Dot), 9..10),
+            // nested indices look like a float, but lex as indices
+            (Ok(Token::IntegerLiteral(0)), 10..11),
+            (Ok(Token::Dot), 11..12),
+            (Ok(Token::IntegerLiteral(1)), 12..13),
+        ]
+    );
+
+    // which keeps every digit, however many there are
+    assert_eq!(
+        lex_kinds("t.0.1234567890123456789 t.0.99999999999999999999")[4..],
+        [
+            (Ok(Token::IntegerLiteral(1234567890123456789)), 4..23),
+            (Ok(Token::Identifier("t".to_string())), 24..25),
+            (Ok(Token::Dot), 25..26),
+            (Ok(Token::IntegerLiteral(0)), 26..27),
+            (Ok(Token::Dot), 27..28),
+            (Err(LexErrorKind::IntegerOverflow), 28..48),
+        ]
+    );
+    // while floats anywhere else stay floats
+    assert_eq!(lex_kinds("0.1")[0].0, Ok(Token::FloatLiteral(0.1)));
+}
+
+#[test]
+fn test_match_tokens() {
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
+        Token::lexer("match when matches Integer T F True")
+            .spanned()
+            .collect();
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Match), 0..5),
+            (Ok(Token::When), 6..10),
+            (Ok(Token::Identifier("matches".to_string())), 11..18),
+            (Ok(Token::TypeName("Integer".to_string())), 19..26),
+            (Ok(Token::BooleanLiteral(true)), 27..28),
+            (Ok(Token::BooleanLiteral(false)), 29..30),
+            (Ok(Token::TypeName("True".to_string())), 31..35),
+        ]
+    );
+}
+
+#[test]
+fn test_variadic_parameters() {
+    let lexed: Vec<(Result<Token, LexErrorKind>, Span)> =
+        Token::lexer("|a ...rest| $@ $2.. x|f").spanned().collect();
+
+    assert_eq!(
+        lexed,
+        vec![
+            (Ok(Token::Pipe), 0..1),
+            (Ok(Token::Identifier("a".to_string())), 1..2),
+            (Ok(Token::Ellipsis), 3..6),
+            (Ok(Token::Identifier("rest".to_string())), 6..10),
+            (Ok(Token::Pipe), 10..11),
+            (Ok(Token::Identifier("$@".to_string())), 12..14),
+            (Ok(Token::Identifier("$2..".to_string())), 15..19),
+            // pipes don't need spaces around them
+            (Ok(Token::Identifier("x".to_string())), 20..21),
+            (Ok(Token::Pipe), 21..22),
+            (Ok(Token::Identifier("f".to_string())), 22..23),
+        ]
+    );
+}
@@ -1,10 +1,110 @@
 use core::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
 use logos::{Lexer, Logos, Span};
 
-// TODO: unescape string literals
-fn load_string(lex: &mut Lexer<Token>) -> String {
-    lex.slice()[1..lex.slice().len() - 1].to_string()
+use super::{LexError, LexErrorKind};
+
+fn load_string(lex: &mut Lexer<Token>) -> Result<String, LexErrorKind> {
+    unescape(&lex.slice()[1..lex.slice().len() - 1])
+}
+
+fn unterminated_string(
+    _lex: &mut Lexer<Token>,
+) -> Result<String, LexErrorKind> {
+    Err(LexErrorKind::UnterminatedString)
+}
+
+/// resolves the C-style escape sequences in the body of a string literal,
+/// failing on the first invalid one.
+fn unescape(body: &str) -> Result<String, LexErrorKind> {
+    let mut unescaped = String::with_capacity(body.len());
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match unescape_sequence(&mut chars) {
+            Some(escaped) => unescaped.push(escaped),
+            None => {
+                let end = chars.peek().map_or(body.len(), |(index, _)| *index);
+                return Err(LexErrorKind::InvalidEscape(
+                    body[start..end].to_string(),
+                ));
+            }
+        }
+    }
+
+    Ok(unescaped)
+}
+
+/// decodes a single escape sequence, just after its backslash
+fn unescape_sequence(chars: &mut Peekable<CharIndices>) -> Option<char> {
+    let mut next = || chars.next().map(|(_, c)| c);
+
+    let escaped = match next()? {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        '\\' => '\\',
+        '"' => '"',
+        '\'' => '\'',
+        // \xHH - a single ascii character
+        'x' => {
+            let high = next()?.to_digit(16)?;
+            let low = next()?.to_digit(16)?;
+            let code = high * 16 + low;
+            if code > 0x7f {
+                return None;
+            }
+            char::from_u32(code)?
+        }
+        // \u{H..} - any unicode scalar value, in up to six hex digits
+        'u' => {
+            if next()? != '{' {
+                return None;
+            }
+            let mut code: u32 = 0;
+            let mut digits = 0;
+            loop {
+                match next()? {
+                    '}' if digits > 0 => break,
+                    c if digits < 6 => {
+                        code = code * 16 + c.to_digit(16)?;
+                        digits += 1;
+                    }
+                    _ => return None,
+                }
+            }
+            char::from_u32(code)?
+        }
+        _ => return None,
+    };
+
+    Some(escaped)
+}
+
+/// raw strings look like r"..." or r#"..."#, with any number of hashes, and
+/// end at the first quote followed by the same number of hashes. they can
+/// contain anything, including newlines, without escaping.
+fn load_raw_string(lex: &mut Lexer<Token>) -> Result<String, LexErrorKind> {
+    let hashes = lex.slice().len() - 2;
+    let terminator = format!("\"{}", "#".repeat(hashes));
+
+    let Some(end) = lex.remainder().find(&terminator) else {
+        // unterminated raw strings swallow the rest of the input
+        lex.bump(lex.remainder().len());
+        return Err(LexErrorKind::UnterminatedString);
+    };
+
+    let contents = lex.remainder()[..end].to_string();
+    lex.bump(end + terminator.len());
+    Ok(contents)
 }
 
 fn load_bool(lex: &mut Lexer<Token>) -> bool {
@@ -15,8 +115,10 @@ fn load_bool(lex: &mut Lexer<Token>) -> bool {
     }
 }
 
-fn load_integer(lex: &mut Lexer<Token>) -> i64 {
-    lex.slice().parse().unwrap()
+fn load_integer(lex: &mut Lexer<Token>) -> Result<i64, LexErrorKind> {
+    lex.slice()
+        .parse()
+        .map_err(|_| LexErrorKind::IntegerOverflow)
 }
 
 fn load_float(lex: &mut Lexer<Token>) -> f64 {
@@ -27,8 +129,40 @@ fn load_identifier(lex: &mut Lexer<Token>) -> String {
     lex.slice().to_string()
 }
 
+fn load_comment(lex: &mut Lexer<Token>) -> String {
+    lex.slice().to_string()
+}
+
+/// block comments nest, so the closing delimiter can't be found with a
+/// regex - instead we scan ahead, keeping track of how deeply nested we are.
+fn load_block_comment(lex: &mut Lexer<Token>) -> Result<String, LexErrorKind> {
+    let remainder = lex.remainder();
+    let mut depth = 1;
+    let mut index = 0;
+
+    while depth > 0 {
+        let rest = &remainder[index..];
+        if rest.starts_with("*/") {
+            depth -= 1;
+            index += 2;
+        } else if rest.starts_with("/*") {
+            depth += 1;
+            index += 2;
+        } else if let Some(c) = rest.chars().next() {
+            index += c.len_utf8();
+        } else {
+            // unterminated comments swallow the rest of the input
+            lex.bump(index);
+            return Err(LexErrorKind::UnterminatedComment);
+        }
+    }
+
+    lex.bump(index);
+    Ok(lex.slice().to_string())
+}
+
 #[derive(Logos, Debug, PartialEq, Clone)]
-#[logos(skip r" |\t|\n")]
+#[logos(skip r" |\t|\n", error = LexErrorKind)]
 pub enum Token {
     // starting off with parentheses
     #[token("(")]
@@ -52,25 +186,69 @@ pub enum Token {
     #[token(":")]
     Colon,
 
-    // then identifiers
-    #[regex(r#"\$(?:\d+|n)|[a-z|_][a-zA-Z0-9_]*"#, load_identifier)]
+    // then the marks of type annotations, as in `x: Integer = 3` and
+    // `|a: Integer| -> String`
+    #[token("=")]
+    Equals,
+    #[token("->")]
+    Arrow,
+
+    // then the dots, for field, index and slice access
+    #[token(".")]
+    Dot,
+    #[token("..")]
+    DotDot,
+    // which also mark the rest parameter of a block, as in `|a ...rest|`
+    #[token("...")]
+    Ellipsis,
+
+    // then identifiers, including $@ for every argument and $2.. for the
+    // arguments from the third on
+    #[regex(
+        r#"\$(?:\d+(?:\.\.)?|@|[a-z_][a-zA-Z0-9_]*)|[a-z_][a-zA-Z0-9_]*"#,
+        load_identifier
+    )]
     Identifier(String),
 
     // then the literals
     #[regex(r#""([^"\\]|\\.)*""#, load_string)]
+    #[regex(r#""([^"\\]|\\.)*\\?"#, unterminated_string)]
+    #[regex(r#"r#*""#, load_raw_string)]
     StringLiteral(String),
-    #[regex(r#"T|F"#, load_bool)]
+    #[regex(r#"T|F"#, load_bool, priority = 10)]
     BooleanLiteral(bool),
     #[regex(r#"-?[0-9]+"#, load_integer)]
     IntegerLiteral(i64),
     #[regex(r#"-?[0-9]+\.[0-9]+"#, load_float)]
     FloatLiteral(f64),
 
-    // then type and paste
+    // then type, paste, import and match
     #[token("type")]
     Type,
     #[token("paste")]
     Paste,
+    #[token("import")]
+    Import,
+    #[token("match")]
+    Match,
+    #[token("when")]
+    When,
+
+    // the names of types, which patterns match against
+    #[regex(r#"[A-Z][a-zA-Z0-9_]*"#, load_identifier)]
+    TypeName(String),
+
+    // and finally comments, which [lex] throws away
+    #[regex(r"(#|//)[^\n]*", load_comment)]
+    LineComment(String),
+    #[token("/*", load_block_comment)]
+    BlockComment(String),
+}
+
+impl Token {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Token::LineComment(_) | Token::BlockComment(_))
+    }
 }
 
 impl fmt::Display for Token {
@@ -82,8 +260,13 @@ impl fmt::Display for Token {
             Token::RightBrace => write!(f, "}}"),
             Token::Pipe => write!(f, "|"),
             Token::Colon => write!(f, ":"),
+            Token::Equals => write!(f, "="),
+            Token::Arrow => write!(f, "->"),
+            Token::Dot => write!(f, "."),
+            Token::DotDot => write!(f, ".."),
+            Token::Ellipsis => write!(f, "..."),
             Token::Identifier(s) => write!(f, "IDENTIFIER<{}>", s),
-            Token::StringLiteral(s) => write!(f, "\"{}\"", s),
+            Token::StringLiteral(s) => write!(f, "{:?}", s),
             Token::BooleanLiteral(b) => {
                 write!(f, "{}", if *b { "T" } else { "F" })
             }
@@ -91,14 +274,79 @@ impl fmt::Display for Token {
             Token::FloatLiteral(fl) => write!(f, "{}", fl),
             Token::Type => write!(f, "type"),
             Token::Paste => write!(f, "paste"),
+            Token::Import => write!(f, "import"),
+            Token::Match => write!(f, "match"),
+            Token::When => write!(f, "when"),
+            Token::TypeName(s) => write!(f, "TYPENAME<{}>", s),
             Token::PipeStar => write!(f, "|*"),
+            Token::LineComment(s) | Token::BlockComment(s) => {
+                write!(f, "{}", s)
+            }
         }
     }
 }
 
-pub fn lex(input: &str) -> Vec<(Result<Token, ()>, Span)> {
-    Token::lexer(input)
-        .spanned()
-        // .map(|(tok, span)| (tok.unwrap(), span))
+pub fn lex(input: &str) -> Vec<(Result<Token, LexError>, Span)> {
+    lex_with_comments(input)
+        .into_iter()
+        .filter(|(tok, _)| !matches!(tok, Ok(tok) if tok.is_comment()))
         .collect()
-}
\ No newline at end of file
+}
+
+/// like [lex], but keeps comments around (with their spans) for tools such
+/// as formatters that need to reproduce them.
+pub fn lex_with_comments(input: &str) -> Vec<(Result<Token, LexError>, Span)> {
+    let mut lexed: Vec<(Result<Token, LexError>, Span)> = vec![];
+
+    for (tok, span) in Token::lexer(input).spanned() {
+        let after_dot = matches!(lexed.last(), Some((Ok(Token::Dot), _)));
+        match tok {
+            // `t.0.1` lexes as `t`, `.`, `0.1`, but the float is really two
+            // indices - so it's split back up into them, digits and all
+            Ok(Token::FloatLiteral(_)) if after_dot => {
+                let dot = span.start + input[span.clone()].find('.').unwrap();
+                lexed.push(index(input, span.start..dot));
+                lexed.push((Ok(Token::Dot), dot..dot + 1));
+                lexed.push(index(input, dot + 1..span.end));
+            }
+            tok => {
+                let tok = tok
+                    .map_err(|kind| LexError::new(kind, span.clone(), input));
+                lexed.push((tok, span));
+            }
+        }
+    }
+
+    lexed
+}
+
+/// the index at the given span of the input, as an integer literal
+fn index(input: &str, span: Span) -> (Result<Token, LexError>, Span) {
+    let tok = input[span.clone()]
+        .parse()
+        .map(Token::IntegerLiteral)
+        .map_err(|_| {
+            LexError::new(LexErrorKind::IntegerOverflow, span.clone(), input)
+        });
+    (tok, span)
+}
+
+/// whether the input stops partway through an expression - inside an unclosed
+/// tuple or block, or an unterminated string or comment - such that more of
+/// it is still to come. interactive frontends use this to keep reading lines.
+pub fn is_incomplete(input: &str) -> bool {
+    let mut depth: i64 = 0;
+    for (tok, _) in Token::lexer(input).spanned() {
+        match tok {
+            Ok(Token::LeftParen | Token::LeftBrace) => depth += 1,
+            Ok(Token::RightParen | Token::RightBrace) => depth -= 1,
+            Err(
+                LexErrorKind::UnterminatedString
+                | LexErrorKind::UnterminatedComment,
+            ) => return true,
+            _ => {}
+        }
+    }
+
+    depth > 0
+}
@@ -1,27 +1,145 @@
+mod embed;
 mod interpreter;
 mod lexer;
+pub mod native;
 mod parser;
+mod vm;
 
-use interpreter::{EvaluateResult, Interpreter, Value};
-use lexer::Token;
-use logos::{Logos, Span};
-use parser::{ASTNode, ParseResult, Parser, ParserError};
+pub use interpreter::{
+    bound_names, captures, render_error, resolve_imports, Arguments, Block,
+    CallFrame, ErrorKind, EvaluateResult, EvaluationError, Interpreter, Limit,
+    Limits, ModuleLoader, NativeFunction, RuntimeCall, RuntimeRegistry, Source,
+    TypeDescriptor, Value, Warning, DEFAULT_MAX_CALL_DEPTH,
+};
+pub use vm::Machine;
+pub use embed::{BlockPipe, Program};
+pub use lexer::{LexError, LexErrorKind, Token};
+use logos::Span;
+pub use parser::{ASTNode, Arm, LiteralVariant, Pattern, PipeType};
+use std::path::Path;
+use parser::Parser;
+pub use parser::{ParserError, SyntaxError};
 use wasm_bindgen::prelude::*;
 
-pub fn lex_from_string(input: &str) -> Vec<(Result<Token, ()>, Span)> {
-    Token::lexer(input).spanned().collect()
+pub fn lex_from_string(input: &str) -> Vec<(Result<Token, LexError>, Span)> {
+    lexer::lex(input)
 }
 
-pub fn parse_from_string(input: &str) -> ParseResult {
-    Parser::new(
-        lex_from_string(input)
-            .into_iter()
-            .map(|(tok, span)| Ok((tok?, span)))
-            .collect::<Result<Vec<(Token, Span)>, ()>>()
-            .ok()
-            .ok_or(("Root".to_string(), "Parsing Failed".to_string(), 0..1))?,
-    )
-    .parse()
+/// lexes the input without discarding comments
+pub fn lex_with_comments_from_string(
+    input: &str,
+) -> Vec<(Result<Token, LexError>, Span)> {
+    lexer::lex_with_comments(input)
+}
+
+/// splits the lexer output into the valid tokens and the errors
+fn tokens_from_string(input: &str) -> (Vec<(Token, Span)>, Vec<LexError>) {
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    for (tok, span) in lex_from_string(input) {
+        match tok {
+            Ok(tok) => tokens.push((tok, span)),
+            Err(error) => errors.push(error),
+        }
+    }
+    (tokens, errors)
+}
+
+pub fn parse_from_string(input: &str) -> Result<ASTNode, SyntaxError> {
+    let (tokens, lex_errors) = tokens_from_string(input);
+    if let Some(error) = lex_errors.into_iter().next() {
+        return Err(error.into());
+    }
+
+    Ok(Parser::new(tokens).parse()?)
+}
+
+/// parses the input, recovering from syntax errors so that every one of them
+/// can be reported at once. tokens that fail to lex are reported and then
+/// left out, so the parser still gets a go at the rest of the input.
+pub fn parse_program_from_string(input: &str) -> (ASTNode, Vec<SyntaxError>) {
+    let (tokens, lex_errors) = tokens_from_string(input);
+    let (root, parse_errors) = Parser::new(tokens).parse_program();
+
+    let mut errors: Vec<SyntaxError> = lex_errors
+        .into_iter()
+        .map(SyntaxError::from)
+        .chain(parse_errors.into_iter().map(SyntaxError::from))
+        .collect();
+    errors.sort_by_key(|error| error.span().start);
+
+    (root, errors)
+}
+
+/// warnings about the input that don't stop it from running, like matches
+/// that don't handle every value they could be given. input that fails to
+/// parse is left to be reported as an error instead.
+pub fn warnings_from_string(input: &str) -> Vec<Warning> {
+    match parse_from_string(input) {
+        Ok(root) => interpreter::match_warnings(&root),
+        Err(_) => vec![],
+    }
+}
+
+/// looks for the errors the input could run into, without running it:
+/// syntax errors, unbound names and mismatched types, in the order they
+/// appear in the source. every path is checked, including those that never
+/// run - see [check](interpreter::check). imports aren't followed.
+pub fn check_from_string(input: &str) -> Vec<EvaluationError> {
+    let (root, errors) = parse_program_from_string(input);
+    if !errors.is_empty() {
+        return errors.into_iter().map(EvaluationError::from).collect();
+    }
+
+    let interpreter = toplevel_interpreter(root);
+    let runtime = interpreter.program.borrow().runtime.clone();
+    let mut errors = interpreter::check(&interpreter.root_node, &runtime);
+    errors.extend(interpreter::unbound(
+        &interpreter.root_node,
+        &interpreter.env,
+    ));
+    errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+    errors
+}
+
+/// whether the input ends partway through an expression, so that an
+/// interactive frontend should keep reading before parsing it
+pub fn input_is_incomplete(input: &str) -> bool {
+    lexer::is_incomplete(input)
+}
+
+/// creates an interpreter for the given root, with a top level stack frame
+/// holding the builtins (`plz`) already pushed
+pub fn toplevel_interpreter(root: ASTNode) -> Interpreter {
+    let mut interpreter = Interpreter::new(root);
+
+    interpreter.env.push_stack_frame();
+    interpreter
+        .env
+        .bind("plz".to_string(), Value::RuntimeInvocation);
+
+    interpreter
+}
+
+/// creates a bytecode machine for the given root, set up just like
+/// [toplevel_interpreter]
+pub fn toplevel_machine(root: ASTNode) -> Machine {
+    let mut machine = Machine::new(root);
+
+    machine.env.push_stack_frame();
+    machine.env.bind("plz".to_string(), Value::RuntimeInvocation);
+
+    machine
+}
+
+/// the ways a program can be run
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Engine {
+    /// evaluates the syntax tree directly
+    #[default]
+    TreeWalking,
+    /// compiles the syntax tree to bytecode for a virtual [Machine]
+    Bytecode,
 }
 
 pub fn interpret_from_string(
@@ -29,29 +147,87 @@ pub fn interpret_from_string(
     parameters: Option<Vec<String>>,
     execute_root: bool,
 ) -> EvaluateResult {
-    let ast = parse_from_string(input)
-        .ok()
-        .ok_or("Failed to interpret".to_string())?;
-    let mut interpreter = Interpreter::new(ast);
+    interpret_from_string_at(input, None, parameters, execute_root)
+}
 
-    interpreter.env.push_stack_frame();
-    interpreter
-        .env
-        .bind("plz".to_string(), Value::RuntimeInvocation);
+/// interprets source read from a file in the given directory, which the
+/// file's imports are then resolved against (rather than the working
+/// directory).
+pub fn interpret_from_string_at(
+    input: &str,
+    directory: Option<&Path>,
+    parameters: Option<Vec<String>>,
+    execute_root: bool,
+) -> EvaluateResult {
+    interpret_with_engine(
+        Engine::default(),
+        input,
+        directory,
+        parameters,
+        execute_root,
+    )
+}
+
+/// interprets source like [interpret_from_string_at], running it with the
+/// given engine
+pub fn interpret_with_engine(
+    engine: Engine,
+    input: &str,
+    directory: Option<&Path>,
+    parameters: Option<Vec<String>>,
+    execute_root: bool,
+) -> EvaluateResult {
+    interpret_with_limits(
+        engine,
+        Limits::default(),
+        input,
+        directory,
+        parameters,
+        execute_root,
+    )
+}
 
+/// interprets source like [interpret_with_engine], stopping with an
+/// [ErrorKind::LimitExceeded] if the program goes over the given limits
+pub fn interpret_with_limits(
+    engine: Engine,
+    limits: Limits,
+    input: &str,
+    directory: Option<&Path>,
+    parameters: Option<Vec<String>>,
+    execute_root: bool,
+) -> EvaluateResult {
+    let mut ast = parse_from_string(input)?;
+    if let Some(directory) = directory {
+        resolve_imports(&mut ast, directory);
+    }
     let transformed_parameters = parameters.map(|parameters| {
         parameters
             .into_iter()
-            .map(|parameter| Value::String(parameter))
+            .map(Value::String)
             .collect()
     });
-    let res = interpreter.evaluate_from_root(transformed_parameters.clone())?;
+    let parameters_vector = transformed_parameters.clone().unwrap_or_default();
 
-    if execute_root {
-        let parameters_vector = transformed_parameters.unwrap_or_default();
-        Ok(Interpreter::execute_closure(parameters_vector, &res)?)
-    } else {
-        Ok(res)
+    match engine {
+        Engine::TreeWalking => {
+            let mut interpreter = toplevel_interpreter(ast);
+            interpreter.set_limits(limits);
+            let res = interpreter.evaluate_from_root(transformed_parameters)?;
+            if !execute_root {
+                return Ok(res);
+            }
+            interpreter.call_closure(parameters_vector, &res)
+        }
+        Engine::Bytecode => {
+            let mut machine = toplevel_machine(ast);
+            machine.set_limits(limits);
+            let res = machine.evaluate_from_root(transformed_parameters)?;
+            if !execute_root {
+                return Ok(res);
+            }
+            machine.call_closure(parameters_vector, &res)
+        }
     }
 }
 
@@ -60,19 +236,45 @@ extern {
     fn alert(s: &str);
 }
 
+/// interprets source for the web frontend. any limit left undefined keeps its
+/// default.
 #[wasm_bindgen]
 pub fn wasm_interpret_from_string(
     input: &str,
     parameters: Option<Vec<String>>,
-    execute_root: bool
+    execute_root: bool,
+    max_call_depth: Option<u32>,
+    max_steps: Option<u32>,
+    max_tuple_size: Option<u32>,
+    max_string_length: Option<u32>,
 ) -> String {
-    let res = interpret_from_string(input, parameters, execute_root);
+    let defaults = Limits::default();
+    let limits = Limits {
+        max_call_depth: max_call_depth
+            .map(|max| max as usize)
+            .or(defaults.max_call_depth),
+        max_steps: max_steps.map(|max| max as usize).or(defaults.max_steps),
+        max_tuple_size: max_tuple_size
+            .map(|max| max as usize)
+            .or(defaults.max_tuple_size),
+        max_string_length: max_string_length
+            .map(|max| max as usize)
+            .or(defaults.max_string_length),
+    };
+    let res = interpret_with_limits(
+        Engine::default(),
+        limits,
+        input,
+        None,
+        parameters,
+        execute_root,
+    );
     match res {
         Ok(ret_value) => {
             ret_value.to_string()
         },
         Err(some_err) => {
-            some_err.to_string()
+            some_err.render(input)
         }
     }
 }
\ No newline at end of file
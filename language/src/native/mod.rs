@@ -0,0 +1,479 @@
+//! support for programs compiled to native code by the `compiler` crate.
+//! compiled code only ever hands values to the `runtime` library it's linked
+//! against, which does everything else through a [Context] - so compiled
+//! programs share the interpreter's values, runtime calls and semantics,
+//! right down to the errors they raise.
+//!
+//! an error raised in a compiled block is [raised](Context::raise) on its
+//! context, and the block returns straight away. the error is then handed to
+//! the block's caller like any other, picking up the spans and call stack an
+//! interpreted block's error would have.
+
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use logos::Span;
+
+use crate::interpreter::{
+    conforms, matches, pipe_into_runtime, resolve, Arguments, Environment,
+    ErrorKind, EvaluateResult, EvaluationError, Interpreter, Outcome,
+    SharedProgram, TailCall, TypeDescriptor, Value,
+};
+use crate::parser::{ASTNode, Accessor, Arm, Parameters, Pattern};
+
+/// the native code a block is compiled to. it runs the body of the block in
+/// the given context, and gives back the value of its last expression -
+/// which its caller takes ownership of.
+pub type NativeBlock = unsafe extern "C" fn(*mut Context) -> *mut Value;
+
+/// a compiled block along with the bindings it captured
+#[derive(Clone)]
+pub struct NativeClosure {
+    function: NativeBlock,
+    captured: Rc<HashMap<String, Value>>,
+    // the parameters the block declares, if it declares any
+    parameters: Option<Rc<Parameters>>,
+    source: Rc<Source>,
+}
+
+impl NativeClosure {
+    /// the values of the names the closure's block uses
+    pub fn image(&self) -> HashMap<String, Value> {
+        self.captured.as_ref().clone()
+    }
+
+    pub fn parameters(&self) -> Option<&Parameters> {
+        self.parameters.as_deref()
+    }
+}
+
+impl PartialEq for NativeClosure {
+    fn eq(&self, other: &NativeClosure) -> bool {
+        self.function as usize == other.function as usize
+            && self.captured == other.captured
+    }
+}
+
+impl fmt::Debug for NativeClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native block>")
+    }
+}
+
+/// the parts of a compiled program that aren't code: the arms of its
+/// matches, its annotations and accessors, and the parameters its blocks
+/// declare. compiled code refers to them by the span of the node they belong
+/// to, so they're read back from the program's source when it starts.
+#[derive(Debug, Default)]
+struct Source {
+    arms: HashMap<Span, Vec<Arm>>,
+    annotations: HashMap<Span, Pattern>,
+    accessors: HashMap<Span, Accessor>,
+    // by the span of the block that declares them
+    parameters: HashMap<Span, Rc<Parameters>>,
+    // how the stages of pipes show up in the call stacks of errors
+    callees: HashMap<Span, String>,
+    // the span of the pipe each stage is a part of
+    pipes: HashMap<Span, Span>,
+}
+
+impl Source {
+    fn new(root: &ASTNode) -> Source {
+        let mut source = Source::default();
+        source.read(root);
+        source
+    }
+
+    fn read(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Block(statements, span) => {
+                if let Some(ASTNode::Parameters(declared, _)) =
+                    statements.first()
+                {
+                    let declared = Rc::new(declared.clone());
+                    self.parameters.insert(span.clone(), declared);
+                }
+                statements.iter().for_each(|node| self.read(node));
+            }
+            ASTNode::Tuple(nodes, _) => {
+                nodes.iter().for_each(|node| self.read(node))
+            }
+            ASTNode::Pipe(stages, _, span) => {
+                for stage in stages {
+                    let callee = Interpreter::describe_callee(stage);
+                    self.callees.insert(stage.span(), callee);
+                    self.pipes.insert(stage.span(), span.clone());
+                    self.read(stage);
+                }
+            }
+            ASTNode::Record(fields, _) => {
+                fields.iter().for_each(|(_, node)| self.read(node))
+            }
+            ASTNode::Access(operand, accessor, span) => {
+                self.accessors.insert(span.clone(), accessor.clone());
+                self.read(operand);
+            }
+            ASTNode::Annotated(operand, annotation, span) => {
+                self.annotations.insert(span.clone(), annotation.clone());
+                self.read(operand);
+            }
+            ASTNode::Match(subject, arms, span) => {
+                self.arms.insert(span.clone(), arms.clone());
+                self.read(subject);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        self.read(guard);
+                    }
+                    self.read(&arm.body);
+                }
+            }
+            ASTNode::Paste(operand, _)
+            | ASTNode::Type(operand, _)
+            | ASTNode::Binding((_, operand), _) => self.read(operand),
+            ASTNode::Identifier(..)
+            | ASTNode::Parameters(..)
+            | ASTNode::Literal(..)
+            | ASTNode::Import(..)
+            | ASTNode::Error(_) => {}
+        }
+    }
+
+    fn arm(&self, span: &Span, index: usize) -> &Arm {
+        &self.arms[span][index]
+    }
+}
+
+/// what a compiled block runs in: the bindings it makes and captured, the
+/// arguments it was given, and the modules and limits of the program it's a
+/// part of
+pub struct Context {
+    program: SharedProgram,
+    source: Rc<Source>,
+    env: Environment,
+    arguments: Arguments,
+    // the closure being run, bound to rec
+    callee: Option<NativeClosure>,
+    // the call in tail position that the block left for its caller to make
+    tail: Option<TailCall>,
+    // the error the block raised, if it raised one
+    error: Option<EvaluationError>,
+}
+
+impl Context {
+    /// toplevel
+    ///
+    /// The context the root of a program runs in, with the builtins (`plz`)
+    /// and the arguments the program was run with bound. Like the
+    /// interpreter, every name the root uses is resolved before it runs, so
+    /// unbound symbols are reported up front.
+    pub fn toplevel(
+        root: &ASTNode,
+        arguments: Vec<Value>,
+    ) -> Result<Context, EvaluationError> {
+        let mut context = Context {
+            program: SharedProgram::default(),
+            source: Rc::new(Source::new(root)),
+            env: Environment::new(),
+            arguments: Arguments::default(),
+            callee: None,
+            tail: None,
+            error: None,
+        };
+        context.env.push_stack_frame();
+        context
+            .env
+            .bind("plz".to_string(), Value::RuntimeInvocation);
+        context.env.push_stack_frame();
+        for (index, argument) in arguments.iter().enumerate() {
+            context.env.bind(format!("${}", index), argument.clone());
+        }
+        let count = Value::Integer(arguments.len() as i64);
+        context.env.bind("$n".to_string(), count);
+        context.env.bind("$@".to_string(), Value::Tuple(arguments));
+
+        resolve(root, &context.env, &Rc::default())?;
+        Ok(context)
+    }
+
+    /// looks up a name. the block's own bindings come first, then rec and
+    /// its arguments, and then whatever it captured.
+    pub fn lookup(&self, name: &str) -> EvaluateResult {
+        let local = match self.env.locate(name) {
+            Some((0, _)) => self.env.lookup(name),
+            _ => None,
+        };
+        local
+            .or_else(|| match name {
+                "rec" => self.callee.clone().map(Value::NativeClosure),
+                _ => self.arguments.parameter(name),
+            })
+            .or_else(|| self.env.lookup(name))
+            .ok_or_else(|| ErrorKind::UnboundSymbol(name.to_string()).into())
+    }
+
+    /// records an error raised by the block being run, which returns
+    /// without finishing. its caller takes the error over from there.
+    pub fn raise(&mut self, error: EvaluationError) {
+        self.error = Some(error);
+    }
+
+    /// whether the block being run has raised an error
+    pub fn failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// takes the error the block raised, if it raised one
+    pub fn take_error(&mut self) -> Option<EvaluationError> {
+        self.error.take()
+    }
+
+    pub fn bind(&mut self, name: String, value: Value) {
+        self.env.bind(name, value);
+    }
+
+    /// binds the parameters declared by the block being run to the
+    /// arguments it was given
+    pub fn declare(&mut self) -> Result<(), EvaluationError> {
+        let declared = self
+            .callee
+            .as_ref()
+            .and_then(|callee| callee.parameters.clone());
+        let Some(declared) = declared else {
+            return Ok(());
+        };
+        for (name, value) in self.arguments.declare(&declared)? {
+            self.env.bind(name, value);
+        }
+        Ok(())
+    }
+
+    /// a closure over the given block, which captures nothing until it's
+    /// told to [capture](Context::capture)
+    pub fn closure(&self, function: NativeBlock, block: &Span) -> Value {
+        Value::NativeClosure(NativeClosure {
+            function,
+            captured: Rc::default(),
+            parameters: self.source.parameters.get(block).cloned(),
+            source: self.source.clone(),
+        })
+    }
+
+    /// captures the current value of a name a closure's block uses. names
+    /// that aren't bound yet are left out, and stay unbound inside the block.
+    pub fn capture(&self, closure: &mut Value, name: &str) {
+        if let (Value::NativeClosure(closure), Ok(value)) =
+            (closure, self.lookup(name))
+        {
+            Rc::make_mut(&mut closure.captured).insert(name.to_string(), value);
+        }
+    }
+
+    /// pastes a value into a tuple under construction: tuples and records
+    /// contribute their elements, while closures contribute their bindings
+    /// to the block being run instead
+    pub fn paste(
+        &mut self,
+        tuple: &mut Value,
+        value: Value,
+    ) -> Result<(), EvaluationError> {
+        let Value::Tuple(spliced) = tuple else {
+            unreachable!("pasted into a {}", TypeDescriptor::of(tuple))
+        };
+        let bindings = match value {
+            Value::Tuple(values) => {
+                spliced.extend(values);
+                return Ok(());
+            }
+            Value::Record(fields) => {
+                spliced.extend(fields.into_iter().map(|(_, value)| value));
+                return Ok(());
+            }
+            other => other.bindings().ok_or_else(|| {
+                ErrorKind::TypeMismatch(format!(
+                    "Can only paste tuples and closures, not {}",
+                    TypeDescriptor::of(&other)
+                ))
+            })?,
+        };
+        for (id, val) in bindings {
+            self.env.bind(id, val);
+        }
+        Ok(())
+    }
+
+    pub fn access(&self, value: Value, span: &Span) -> EvaluateResult {
+        value.access(&self.source.accessors[span]).map_err(|error| {
+            EvaluationError::from(error).with_span(span.clone())
+        })
+    }
+
+    pub fn annotate(&self, value: Value, span: &Span) -> EvaluateResult {
+        conforms(&self.source.annotations[span], &value)
+            .map(|()| value)
+            .map_err(|error| {
+                EvaluationError::from(error).with_span(span.clone())
+            })
+    }
+
+    /// pipes a value into the stage of a pipe with the given span
+    pub fn pipe(
+        &mut self,
+        input: Value,
+        closure: Value,
+        destructure: bool,
+        span: &Span,
+    ) -> EvaluateResult {
+        Arguments::piped(input, destructure)
+            .map_err(EvaluationError::from)
+            .and_then(|arguments| match closure {
+                Value::RuntimeInvocation => {
+                    pipe_into_runtime(&self.program, arguments.values, span)
+                }
+                other => Interpreter::execute_closure_with(
+                    &self.program,
+                    arguments,
+                    &other,
+                )
+                .map_err(|error| {
+                    let callee = &self.source.callees[span];
+                    error.in_call(callee, Some(span.clone()))
+                }),
+            })
+            .map_err(|error| self.in_pipe(error, span))
+    }
+
+    /// pipes a value into the last stage of the pipe that ends the block
+    /// being run. rather than making the call, the block leaves it for its
+    /// caller, so that loops written with rec don't grow the stack - and
+    /// comes to the empty tuple in the meantime.
+    pub fn tail_pipe(
+        &mut self,
+        input: Value,
+        closure: Value,
+        destructure: bool,
+        span: &Span,
+    ) -> EvaluateResult {
+        let outcome = Arguments::piped(input, destructure)
+            .map_err(EvaluationError::from)
+            .and_then(|arguments| {
+                Interpreter::tail_call(
+                    &self.program,
+                    closure,
+                    arguments,
+                    self.source.callees[span].clone(),
+                    span,
+                )
+            })
+            .map_err(|error| self.in_pipe(error, span))?;
+        Ok(self.finish(outcome))
+    }
+
+    /// an error raised by the stage of a pipe with the given span points at
+    /// the whole pipe, unless it already points somewhere more precise
+    fn in_pipe(&self, error: EvaluationError, span: &Span) -> EvaluationError {
+        error.with_span(self.source.pipes[span].clone())
+    }
+
+    /// what the pattern of an arm of the match with the given span captures
+    /// from the subject, if it matches
+    pub fn matches(
+        &self,
+        subject: &Value,
+        span: &Span,
+        arm: usize,
+    ) -> Option<Arguments> {
+        matches(&self.source.arm(span, arm).pattern, subject)
+    }
+
+    /// enter_arm
+    ///
+    /// Runs an arm of a match whose pattern matched, given the blocks of its
+    /// guard and body. Gives `None` if the guard turned the arm down. In tail
+    /// position, the body is left for the caller of the block being run, as
+    /// with [Context::tail_pipe].
+    pub fn enter_arm(
+        &mut self,
+        captured: Arguments,
+        guard: Option<Value>,
+        body: Value,
+        (span, arm): (&Span, usize),
+        tail: bool,
+    ) -> Result<Option<Value>, EvaluationError> {
+        let arm = self.source.arm(span, arm);
+        let guard = guard.zip(arm.guard.as_ref().map(ASTNode::span));
+        let outcome = Interpreter::enter_arm(
+            &self.program,
+            captured,
+            guard,
+            (body, arm.body.span()),
+            tail,
+        )
+        .map_err(|error| error.with_span(span.clone()))?;
+        Ok(outcome.map(|outcome| self.finish(outcome)))
+    }
+
+    /// the error raised by the match with the given span, none of whose arms
+    /// accept the subject
+    pub fn no_match(subject: &Value, span: &Span) -> EvaluationError {
+        EvaluationError::from(ErrorKind::NoMatch(TypeDescriptor::of(subject)))
+            .with_span(span.clone())
+    }
+
+    /// executes a closure, along with the tail calls it leaves behind
+    pub fn call(
+        &self,
+        closure: &Value,
+        arguments: Vec<Value>,
+    ) -> EvaluateResult {
+        Interpreter::execute_closure_with(
+            &self.program,
+            arguments.into(),
+            closure,
+        )
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Value {
+        match outcome {
+            Outcome::Done(value) => value,
+            Outcome::TailCall(call) => {
+                self.tail = Some(call);
+                Value::Tuple(vec![])
+            }
+        }
+    }
+}
+
+/// runs a compiled closure, up to the call in its tail position. see
+/// [Interpreter::execute_closure_with] for making that call.
+pub(crate) fn enter(
+    program: &SharedProgram,
+    closure: &NativeClosure,
+    arguments: Arguments,
+) -> Result<Outcome, EvaluationError> {
+    let mut context = Context {
+        program: program.clone(),
+        source: closure.source.clone(),
+        env: Environment::new(),
+        arguments,
+        callee: Some(closure.clone()),
+        tail: None,
+        error: None,
+    };
+
+    // what the closure captured sits underneath the bindings it makes
+    context.env.push_stack_frame();
+    for (name, value) in closure.captured.iter() {
+        context.env.bind(name.clone(), value.clone());
+    }
+    context.env.push_stack_frame();
+
+    let value = unsafe { (closure.function)(&mut context) };
+    if let Some(error) = context.take_error() {
+        return Err(error);
+    }
+    // SAFETY: compiled blocks give back a value they own, unless they failed
+    let value = unsafe { *Box::from_raw(value) };
+    Ok(match context.tail.take() {
+        Some(call) => Outcome::TailCall(call),
+        None => Outcome::Done(value),
+    })
+}
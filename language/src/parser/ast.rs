@@ -1,15 +1,113 @@
+use core::fmt;
+
+use logos::Span;
+
+/// a node in the syntax tree. every variant carries the byte span of the
+/// source it was parsed from as its last field.
 #[derive(Debug, PartialEq, Clone)]
 pub enum ASTNode {
-    Block(Vec<ASTNode>),
-    Tuple(Vec<ASTNode>),
-    Pipe(Vec<ASTNode>, Vec<PipeType>),
-    Paste(Box<ASTNode>),
-    Type(Box<ASTNode>),
-    Binding((String, Box<ASTNode>)),
-    Identifier(String),
-    Literal(LiteralVariant),
+    Block(Vec<ASTNode>, Span),
+    Tuple(Vec<ASTNode>, Span),
+    // a tuple whose elements are all bindings, each labelling a field
+    Record(Vec<(String, ASTNode)>, Span),
+    Pipe(Vec<ASTNode>, Vec<PipeType>, Span),
+    Paste(Box<ASTNode>, Span),
+    Type(Box<ASTNode>, Span),
+    // the path of the module to import
+    Import(String, Span),
+    // the value being matched, and the arms it's tested against in order
+    Match(Box<ASTNode>, Vec<Arm>, Span),
+    Binding((String, Box<ASTNode>), Span),
+    // a value and the type it's annotated with, which it has to match, as
+    // in the `Integer = 3` of `x: Integer = 3`
+    Annotated(Box<ASTNode>, Pattern, Span),
+    // the parameters a block declares, which can only be its first statement
+    Parameters(Parameters, Span),
+    Identifier(String, Span),
+    // a field, element or slice of a value, like `t.name`, `t.0` or `t.1..3`
+    Access(Box<ASTNode>, Accessor, Span),
+    Literal(LiteralVariant, Span),
+
+    // stands in for source that could not be parsed
+    Error(Span),
+}
+
+impl ASTNode {
+    pub fn span(&self) -> Span {
+        match self {
+            ASTNode::Block(_, span)
+            | ASTNode::Tuple(_, span)
+            | ASTNode::Record(_, span)
+            | ASTNode::Pipe(_, _, span)
+            | ASTNode::Paste(_, span)
+            | ASTNode::Type(_, span)
+            | ASTNode::Import(_, span)
+            | ASTNode::Match(_, _, span)
+            | ASTNode::Binding(_, span)
+            | ASTNode::Annotated(_, _, span)
+            | ASTNode::Parameters(_, span)
+            | ASTNode::Identifier(_, span)
+            | ASTNode::Access(_, _, span)
+            | ASTNode::Literal(_, span)
+            | ASTNode::Error(span) => span.clone(),
+        }
+    }
+}
+
+/// the names a block gives its arguments, like `|a b ...rest|`, each with its
+/// span. a block that declares parameters must be given exactly as many
+/// arguments, or at least as many if it takes the rest of them as a tuple.
+/// any of them can be annotated with a type, as can the block's output, as
+/// in `|a: Integer ...rest: Tuple| -> String`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Parameters {
+    pub names: Vec<(String, Span)>,
+    pub rest: Option<(String, Span)>,
+    // the type annotations of the parameters that have them, by name
+    pub types: Vec<(String, Pattern)>,
+    // the type annotation following `->`, and its span
+    pub output: Option<(Pattern, Span)>,
 }
 
+impl Parameters {
+    /// every name declared, along with its span
+    pub fn declared(&self) -> impl Iterator<Item = &(String, Span)> {
+        self.names.iter().chain(&self.rest)
+    }
+
+    /// the type the named parameter is annotated with, if any
+    pub fn annotation(&self, name: &str) -> Option<&Pattern> {
+        self.types
+            .iter()
+            .find(|(declared, _)| declared == name)
+            .map(|(_, pattern)| pattern)
+    }
+}
+
+/// what follows the dot of an [ASTNode::Access]. indices count from the end
+/// when negative, and slices run up to (but not including) their end, or to
+/// the end of the tuple when it's left out.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Accessor {
+    Field(String),
+    Index(i64),
+    Slice(i64, Option<i64>),
+}
+
+impl fmt::Display for Accessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Accessor::Field(label) => write!(f, "{}", label),
+            Accessor::Index(index) => write!(f, "{}", index),
+            Accessor::Slice(start, Some(end)) => {
+                write!(f, "{}..{}", start, end)
+            }
+            Accessor::Slice(start, None) => write!(f, "{}..", start),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum LiteralVariant {
     StringLiteral(String),
@@ -18,6 +116,112 @@ pub enum LiteralVariant {
     FloatLiteral(f64),
 }
 
+/// an arm of a match: when the pattern matches (and the guard, if any, gives
+/// T), the body runs. both the guard and the body are blocks, invoked with
+/// the values the pattern captured.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<ASTNode>,
+    pub body: ASTNode,
+    pub span: Span,
+}
+
+/// the type names that type patterns can use. `Tuple` and `Record` stand for
+/// any tuple and any record.
+pub const TYPE_NAMES: [&str; 9] = [
+    "Integer",
+    "Float",
+    "String",
+    "Boolean",
+    "Tuple",
+    "Record",
+    "Closure",
+    "RuntimeInvocation",
+    "Type",
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    // `_`, which matches anything
+    Wildcard,
+    // `$name`, which matches anything and captures it as name
+    Capture(String),
+    Literal(LiteralVariant),
+    // one of [TYPE_NAMES], matching any value of that type
+    Type(String),
+    // matches tuples of the same length, element by element
+    Tuple(Vec<Pattern>),
+    // matches records that have (at least) the given fields
+    Record(Vec<(String, Pattern)>),
+}
+
+impl Pattern {
+    /// the names the pattern captures, in the order it captures them
+    pub fn captures(&self) -> Vec<&str> {
+        match self {
+            Pattern::Capture(name) => vec![name],
+            Pattern::Tuple(elements) => {
+                elements.iter().flat_map(Pattern::captures).collect()
+            }
+            Pattern::Record(fields) => fields
+                .iter()
+                .flat_map(|(_, pattern)| pattern.captures())
+                .collect(),
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Type(_) => {
+                vec![]
+            }
+        }
+    }
+
+    /// whether the pattern describes a type, as type annotations have to:
+    /// it can't capture anything, or match literal values
+    pub fn is_type(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Type(_) => true,
+            Pattern::Capture(_) | Pattern::Literal(_) => false,
+            Pattern::Tuple(elements) => elements.iter().all(Pattern::is_type),
+            Pattern::Record(fields) => {
+                fields.iter().all(|(_, pattern)| pattern.is_type())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Capture(name) => write!(f, "${}", name),
+            Pattern::Literal(LiteralVariant::StringLiteral(s)) => {
+                write!(f, "{:?}", s)
+            }
+            Pattern::Literal(LiteralVariant::IntegerLiteral(i)) => {
+                write!(f, "{}", i)
+            }
+            Pattern::Literal(LiteralVariant::FloatLiteral(fl)) => {
+                write!(f, "{:?}", fl)
+            }
+            Pattern::Literal(LiteralVariant::BooleanLiteral(b)) => {
+                write!(f, "{}", if *b { "T" } else { "F" })
+            }
+            Pattern::Type(name) => write!(f, "{}", name),
+            Pattern::Tuple(elements) => {
+                let elements: Vec<_> =
+                    elements.iter().map(Pattern::to_string).collect();
+                write!(f, "({})", elements.join(" "))
+            }
+            Pattern::Record(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(label, pattern)| format!("{}: {}", label, pattern))
+                    .collect();
+                write!(f, "({})", fields.join(" "))
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum PipeType {
     Standard,
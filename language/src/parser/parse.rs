@@ -1,4 +1,7 @@
-use crate::lexer::Token;
+use core::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::lexer::{LexError, Token};
 use logos::Span;
 use super::*;
 
@@ -6,25 +9,102 @@ use super::*;
 pub struct Parser {
     tokens: Vec<(Token, Span)>,
     index: usize,
+    errors: Vec<ParserError>,
 }
 
 /// generic parser result. either an ast node or a [ParserError]
 pub type ParseResult = Result<ASTNode, ParserError>;
 
-/// the type of the error - contains the ast node that threw the error, the
-/// error message, and the byte span in the input from which the error
-/// originated.
-pub type ParserError = (String, String, Span);
+/// a syntax error - contains the ast node that was being parsed, a
+/// description of what the parser expected to see, the token that it found
+/// instead (None at the end of the input), and the byte span in the input
+/// from which the error originated.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParserError {
+    pub node: String,
+    pub expected: String,
+    pub found: Option<Token>,
+    pub span: Span,
+}
+
+impl Display for ParserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(token) => write!(
+                f,
+                "Expected {} but found {} while parsing {}",
+                self.expected, token, self.node
+            ),
+            None => write!(
+                f,
+                "Expected {} but reached the end of the input while parsing {}",
+                self.expected, self.node
+            ),
+        }
+    }
+}
+
+/// anything that can stop source text from turning into an AST - either the
+/// lexer or the parser rejecting it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxError {
+    Lex(LexError),
+    Parse(ParserError),
+}
+
+impl SyntaxError {
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxError::Lex(error) => error.span.clone(),
+            SyntaxError::Parse(error) => error.span.clone(),
+        }
+    }
+}
+
+impl From<LexError> for SyntaxError {
+    fn from(error: LexError) -> Self {
+        SyntaxError::Lex(error)
+    }
+}
+
+impl From<ParserError> for SyntaxError {
+    fn from(error: ParserError) -> Self {
+        SyntaxError::Parse(error)
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxError::Lex(error) => write!(f, "{}", error),
+            SyntaxError::Parse(error) => write!(f, "{}", error),
+        }
+    }
+}
 
 impl Parser {
     pub fn new(tokens: Vec<(Token, Span)>) -> Self {
-        Self { tokens, index: 0 }
+        // comments have no bearing on the structure of the program
+        let tokens = tokens
+            .into_iter()
+            .filter(|(tok, _)| !tok.is_comment())
+            .collect();
+
+        Self {
+            tokens,
+            index: 0,
+            errors: vec![],
+        }
     }
 
     /// current_token
     ///
     /// Obtains the current index or errors out if the index is out of bounds.
-    fn curr_index(&self, ast_node_name: &str) -> Result<usize, ParserError> {
+    fn curr_index(
+        &self,
+        ast_node_name: &str,
+        expected: &str,
+    ) -> Result<usize, ParserError> {
         if self.index < self.tokens.len() {
             Ok(self.index)
         } else {
@@ -33,28 +113,82 @@ impl Parser {
                 None => 0..1,
             };
 
-            Err((
-                ast_node_name.to_string(),
-                "Unexpected end of the input".to_string(),
-                last_token_span,
-            ))
+            Err(ParserError {
+                node: ast_node_name.to_string(),
+                expected: expected.to_string(),
+                found: None,
+                span: last_token_span,
+            })
         }
     }
 
+    /// parse
+    ///
+    /// Parses a single expression. Syntax errors nested inside tuples and
+    /// blocks are recovered from internally, but the first one (in source
+    /// order) is still what gets returned.
     pub fn parse(&mut self) -> ParseResult {
-        let expr1 = self.parse_self_contained()?;
+        let result = self.parse_expression();
+
+        match (self.errors.first(), result) {
+            (Some(first), _) => Err(first.clone()),
+            (None, result) => result,
+        }
+    }
+
+    /// parse_program
+    ///
+    /// Parses an entire input, recovering from as many syntax errors as
+    /// possible. Returns the (possibly partial) tree, in which anything that
+    /// could not be parsed is replaced by an [ASTNode::Error], along with
+    /// every error encountered on the way.
+    pub fn parse_program(&mut self) -> (ASTNode, Vec<ParserError>) {
+        let root = match self.parse_expression() {
+            Ok(root) => root,
+            Err(error) => {
+                let span = error.span.clone();
+                self.errors.push(error);
+                ASTNode::Error(span)
+            }
+        };
+
+        // a program is a single expression, so anything after it is an error
+        if let Some((token, span)) = self.tokens.get(self.index) {
+            let end = self.tokens.last().map_or(span.end, |(_, s)| s.end);
+            self.errors.push(ParserError {
+                node: "program".to_string(),
+                expected: "end of input".to_string(),
+                found: Some(token.clone()),
+                span: span.start..end,
+            });
+        }
+
+        (root, std::mem::take(&mut self.errors))
+    }
+
+    fn parse_expression(&mut self) -> ParseResult {
+        let expr1 = self.parse_access()?;
 
         // we need to handle the case in which we're done
         if self.index >= self.tokens.len() {
             return Ok(expr1);
         }
 
-        let (tok, _) = &self.tokens[self.curr_index("expression")?];
+        let (tok, _) = &self.tokens[self.index];
         match (expr1, tok) {
             // could be a binding
-            (ASTNode::Identifier(value), Token::Colon) => {
+            (ASTNode::Identifier(value, span), Token::Colon) => {
                 self.index += 1;
-                Ok(ASTNode::Binding((value, Box::new(self.parse()?))))
+                let bound = match self.binding_annotation()? {
+                    Some((annotation, annotation_span)) => {
+                        let bound = self.parse_expression()?;
+                        let span = annotation_span.start..bound.span().end;
+                        ASTNode::Annotated(Box::new(bound), annotation, span)
+                    }
+                    None => self.parse_expression()?,
+                };
+                let span = span.start..bound.span().end;
+                Ok(ASTNode::Binding((value, Box::new(bound)), span))
             }
 
             // could be a pipe
@@ -65,18 +199,20 @@ impl Parser {
                     Token::PipeStar => PipeType::Destructure,
                     _ => unreachable!(),
                 };
-                let expr2 = self.parse()?;
+                let expr2 = self.parse_expression()?;
+                let span = expr1.span().start..expr2.span().end;
 
                 // one slightly weird thing that we need to take care of is pipe
                 // chains - if the second expression is also a pipe, then we'll fold the
                 // pipe chain into a single ASTNode::Pipe
-                if let ASTNode::Pipe(mut expr_vec, mut pipetype_vec) = expr2 {
+                if let ASTNode::Pipe(mut expr_vec, mut pipetype_vec, _) = expr2
+                {
                     expr_vec.insert(0, expr1);
                     pipetype_vec.insert(0, pipe_type);
 
-                    Ok(ASTNode::Pipe(expr_vec, pipetype_vec))
+                    Ok(ASTNode::Pipe(expr_vec, pipetype_vec, span))
                 } else {
-                    Ok(ASTNode::Pipe(vec![expr1, expr2], vec![pipe_type]))
+                    Ok(ASTNode::Pipe(vec![expr1, expr2], vec![pipe_type], span))
                 }
             }
 
@@ -86,58 +222,439 @@ impl Parser {
     }
 
     fn parse_self_contained(&mut self) -> ParseResult {
-        let (tok, span) = &self.tokens[self.curr_index("self contained")?];
+        let (tok, span) =
+            &self.tokens[self.curr_index("expression", "an expression")?];
 
-        /*
-         * TODO: Handle cases q
-         */
-        let ret = match tok {
+        match tok {
             Token::StringLiteral(value) => {
                 self.index += 1;
-                Ok(ASTNode::Literal(LiteralVariant::StringLiteral(
-                    value.clone(),
-                )))
+                Ok(ASTNode::Literal(
+                    LiteralVariant::StringLiteral(value.clone()),
+                    span.clone(),
+                ))
             }
             Token::IntegerLiteral(value) => {
                 self.index += 1;
-                Ok(ASTNode::Literal(LiteralVariant::IntegerLiteral(
-                    value.clone(),
-                )))
+                Ok(ASTNode::Literal(
+                    LiteralVariant::IntegerLiteral(*value),
+                    span.clone(),
+                ))
             }
             Token::BooleanLiteral(value) => {
                 self.index += 1;
-                Ok(ASTNode::Literal(LiteralVariant::BooleanLiteral(
-                    value.clone(),
-                )))
+                Ok(ASTNode::Literal(
+                    LiteralVariant::BooleanLiteral(*value),
+                    span.clone(),
+                ))
             }
             Token::FloatLiteral(value) => {
                 self.index += 1;
-                Ok(ASTNode::Literal(LiteralVariant::FloatLiteral(
-                    value.clone(),
-                )))
+                Ok(ASTNode::Literal(
+                    LiteralVariant::FloatLiteral(*value),
+                    span.clone(),
+                ))
             }
             Token::LeftParen => self.parse_tuple(),
             Token::LeftBrace => self.parse_block(),
             Token::Type => {
+                let start = span.start;
                 self.index += 1;
-                Ok(ASTNode::Type(Box::new(self.parse_tuple()?)))
+                let operand = self.parse_keyword_operand("type")?;
+                let span = start..operand.span().end;
+                Ok(ASTNode::Type(Box::new(operand), span))
             }
             Token::Paste => {
+                let start = span.start;
+                self.index += 1;
+                let operand = self.parse_keyword_operand("paste")?;
+                let span = start..operand.span().end;
+                Ok(ASTNode::Paste(Box::new(operand), span))
+            }
+            Token::Import => {
+                let start = span.start;
+                self.index += 1;
+                self.parse_import(start)
+            }
+            Token::Match => {
+                let start = span.start;
                 self.index += 1;
-                Ok(ASTNode::Paste(Box::new(self.parse_tuple()?)))
+                self.parse_match(start)
             }
             Token::Identifier(value) => {
                 self.index += 1;
-                Ok(ASTNode::Identifier(value.clone()))
+                Ok(ASTNode::Identifier(value.clone(), span.clone()))
+            }
+            _ => Err(ParserError {
+                node: "expression".to_string(),
+                expected: "an expression".to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    /// parse_access
+    ///
+    /// Parses a self-contained expression followed by any number of
+    /// accesses, like `person.address.city`, `pairs.0.1` or `row.1..-1`.
+    fn parse_access(&mut self) -> ParseResult {
+        let mut expression = self.parse_self_contained()?;
+
+        while let Some((Token::Dot, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+            let (tok, span) = self.tokens
+                [self.curr_index("access", "a field name or index")?]
+            .clone();
+            let (accessor, end) = match tok {
+                Token::Identifier(label) if !label.starts_with('$') => {
+                    self.index += 1;
+                    (Accessor::Field(label), span.end)
+                }
+                Token::IntegerLiteral(index) => {
+                    self.index += 1;
+                    self.index_or_slice(index, span.end)
+                }
+                _ => {
+                    return Err(ParserError {
+                        node: "access".to_string(),
+                        expected: "a field name or index".to_string(),
+                        found: Some(tok),
+                        span,
+                    })
+                }
+            };
+
+            let span = expression.span().start..end;
+            expression = ASTNode::Access(Box::new(expression), accessor, span);
+        }
+
+        Ok(expression)
+    }
+
+    /// index_or_slice
+    ///
+    /// An index is the start of a slice if `..` follows it, which in turn
+    /// may be followed by the index the slice ends at. Gives back the
+    /// accessor, along with where it ends.
+    fn index_or_slice(&mut self, start: i64, end: usize) -> (Accessor, usize) {
+        let Some((Token::DotDot, dots)) = self.tokens.get(self.index) else {
+            return (Accessor::Index(start), end);
+        };
+        let dots_end = dots.end;
+        self.index += 1;
+
+        match self.tokens.get(self.index) {
+            Some((Token::IntegerLiteral(last), span)) => {
+                let accessor = Accessor::Slice(start, Some(*last));
+                let end = span.end;
+                self.index += 1;
+                (accessor, end)
+            }
+            _ => (Accessor::Slice(start, None), dots_end),
+        }
+    }
+
+    /// parse_keyword_operand
+    ///
+    /// `type` and `paste` must be followed by a tuple.
+    fn parse_keyword_operand(&mut self, keyword: &str) -> ParseResult {
+        let (tok, span) = &self.tokens[self.curr_index(keyword, "(")?];
+        if *tok != Token::LeftParen {
+            return Err(ParserError {
+                node: keyword.to_string(),
+                expected: "(".to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            });
+        }
+
+        self.parse_tuple()
+    }
+
+    /// parse_import
+    ///
+    /// `import` must be followed by a string literal naming the module, so
+    /// that the path is known before anything is evaluated.
+    fn parse_import(&mut self, start: usize) -> ParseResult {
+        let (tok, span) =
+            &self.tokens[self.curr_index("import", "a module path")?];
+        let Token::StringLiteral(path) = tok else {
+            return Err(ParserError {
+                node: "import".to_string(),
+                expected: "a module path".to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            });
+        };
+
+        self.index += 1;
+        Ok(ASTNode::Import(path.clone(), start..span.end))
+    }
+
+    /// parse_match
+    ///
+    /// `match` is followed by the value to match, in parentheses just like
+    /// the operand of `type`, and then its arms in braces. Each arm is a
+    /// pattern, optionally followed by `when` and a guard block, and then
+    /// the block to run.
+    fn parse_match(&mut self, start: usize) -> ParseResult {
+        let subject = match self.parse_keyword_operand("match")? {
+            // `match (x)` matches x itself rather than a single element tuple
+            ASTNode::Tuple(mut elements, _) if elements.len() == 1 => {
+                elements.remove(0)
+            }
+            subject => subject,
+        };
+
+        self.expect("match", Token::LeftBrace)?;
+        let mut arms = vec![];
+        let end = loop {
+            let (tok, span) =
+                &self.tokens[self.curr_index("match", "a pattern")?];
+            if *tok == Token::RightBrace {
+                let end = span.end;
+                self.index += 1;
+                break end;
+            }
+            arms.push(self.parse_arm()?);
+        };
+
+        Ok(ASTNode::Match(Box::new(subject), arms, start..end))
+    }
+
+    fn parse_arm(&mut self) -> Result<Arm, ParserError> {
+        let (pattern, pattern_span) = self.parse_pattern()?;
+
+        // every capture has to have a name of its own
+        let captures = pattern.captures();
+        for (index, name) in captures.iter().enumerate() {
+            if captures[..index].contains(name) {
+                return Err(ParserError {
+                    node: "pattern".to_string(),
+                    expected: "a capture name not already in use".to_string(),
+                    found: Some(Token::Identifier(format!("${}", name))),
+                    span: pattern_span,
+                });
+            }
+        }
+
+        let guard = match self.tokens.get(self.index) {
+            Some((Token::When, _)) => {
+                self.index += 1;
+                Some(self.parse_arm_block("a guard block")?)
+            }
+            _ => None,
+        };
+        let body = self.parse_arm_block("a block")?;
+        let span = pattern_span.start..body.span().end;
+
+        Ok(Arm {
+            pattern,
+            guard,
+            body,
+            span,
+        })
+    }
+
+    /// the guard and body of an arm must be written out as blocks
+    fn parse_arm_block(&mut self, expected: &str) -> ParseResult {
+        let (tok, span) = &self.tokens[self.curr_index("match", expected)?];
+        if *tok != Token::LeftBrace {
+            return Err(ParserError {
+                node: "match".to_string(),
+                expected: expected.to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            });
+        }
+
+        self.parse_block()
+    }
+
+    /// parse_pattern
+    ///
+    /// Parses a pattern, giving it back along with its span: `_`, a
+    /// capture like `$name`, a literal, a type name, or a tuple of patterns.
+    /// A tuple containing `label: pattern` fields is a record pattern, in
+    /// which every element must be such a field.
+    fn parse_pattern(&mut self) -> Result<(Pattern, Span), ParserError> {
+        let (tok, span) =
+            self.tokens[self.curr_index("pattern", "a pattern")?].clone();
+        let error = |expected: &str| ParserError {
+            node: "pattern".to_string(),
+            expected: expected.to_string(),
+            found: Some(tok.clone()),
+            span: span.clone(),
+        };
+
+        let pattern = match &tok {
+            Token::Identifier(name) if name == "_" => Pattern::Wildcard,
+            Token::Identifier(name) => match name.strip_prefix('$') {
+                Some(capture)
+                    if capture != "n" && capture.parse::<usize>().is_err() =>
+                {
+                    Pattern::Capture(capture.to_string())
+                }
+                Some(_) => return Err(error("a capture name")),
+                None => return Err(error("a pattern")),
+            },
+            Token::StringLiteral(value) => {
+                Pattern::Literal(LiteralVariant::StringLiteral(value.clone()))
+            }
+            Token::IntegerLiteral(value) => {
+                Pattern::Literal(LiteralVariant::IntegerLiteral(*value))
+            }
+            Token::FloatLiteral(value) => {
+                Pattern::Literal(LiteralVariant::FloatLiteral(*value))
+            }
+            Token::BooleanLiteral(value) => {
+                Pattern::Literal(LiteralVariant::BooleanLiteral(*value))
+            }
+            Token::TypeName(name) if TYPE_NAMES.contains(&name.as_str()) => {
+                Pattern::Type(name.clone())
+            }
+            Token::TypeName(_) => return Err(error("a type name")),
+            Token::LeftParen => return self.parse_tuple_pattern(),
+            _ => return Err(error("a pattern")),
+        };
+
+        self.index += 1;
+        Ok((pattern, span))
+    }
+
+    /// binding_annotation
+    ///
+    /// Parses the type annotation of a binding, like the `Integer =` of
+    /// `x: Integer = 3`, if there is one. Annotations look a lot like
+    /// values, so anything that isn't followed by `=` is left alone to be
+    /// parsed as the bound value instead.
+    fn binding_annotation(
+        &mut self,
+    ) -> Result<Option<(Pattern, Span)>, ParserError> {
+        let start = self.index;
+        let annotation = self.parse_pattern().ok().filter(|_| {
+            matches!(self.tokens.get(self.index), Some((Token::Equals, _)))
+        });
+        let Some((pattern, span)) = annotation else {
+            self.index = start;
+            return Ok(None);
+        };
+
+        self.index += 1;
+        self.type_annotation(pattern, span).map(Some)
+    }
+
+    /// parse_annotation
+    ///
+    /// Parses a type annotation, which is a pattern that only describes a
+    /// type - it can't capture anything, or match literal values.
+    fn parse_annotation(&mut self) -> Result<(Pattern, Span), ParserError> {
+        let (pattern, span) = self.parse_pattern()?;
+        self.type_annotation(pattern, span)
+    }
+
+    fn type_annotation(
+        &self,
+        pattern: Pattern,
+        span: Span,
+    ) -> Result<(Pattern, Span), ParserError> {
+        if pattern.is_type() {
+            return Ok((pattern, span));
+        }
+
+        Err(ParserError {
+            node: "annotation".to_string(),
+            expected: "a type".to_string(),
+            found: self.token_at(span.start),
+            span,
+        })
+    }
+
+    fn parse_tuple_pattern(&mut self) -> Result<(Pattern, Span), ParserError> {
+        let start = self.tokens[self.index].1.start;
+        self.index += 1;
+        let mut elements = vec![];
+        let mut fields: Vec<(String, Pattern)> = vec![];
+
+        let end = loop {
+            let (tok, span) =
+                self.tokens[self.curr_index("pattern", ")")?].clone();
+            if tok == Token::RightParen {
+                self.index += 1;
+                break span.end;
             }
-            _ => Err((
-                "expression".to_string(),
-                "undefined error".to_string(),
-                span.clone(),
-            )),
+
+            let label = match (&tok, self.tokens.get(self.index + 1)) {
+                (Token::Identifier(label), Some((Token::Colon, _))) => {
+                    Some(label.clone())
+                }
+                _ => None,
+            };
+            let Some(label) = label else {
+                elements.push(self.parse_pattern()?);
+                continue;
+            };
+
+            let expected = if label.starts_with('$') {
+                Some("a field name")
+            } else if fields.iter().any(|(used, _)| *used == label) {
+                Some("a label not already in use")
+            } else {
+                None
+            };
+            if let Some(expected) = expected {
+                return Err(ParserError {
+                    node: "pattern".to_string(),
+                    expected: expected.to_string(),
+                    found: Some(tok),
+                    span,
+                });
+            }
+
+            self.index += 2;
+            let (pattern, _) = self.parse_pattern()?;
+            fields.push((label, pattern));
         };
 
-        ret
+        let span = start..end;
+        if fields.is_empty() {
+            let elements = elements.into_iter().map(|(p, _)| p).collect();
+            return Ok((Pattern::Tuple(elements), span));
+        }
+
+        // fields can't be mixed with positional patterns
+        if let Some((_, element)) = elements.first() {
+            return Err(ParserError {
+                node: "pattern".to_string(),
+                expected: "a field".to_string(),
+                found: self.token_at(element.start),
+                span: element.clone(),
+            });
+        }
+        Ok((Pattern::Record(fields), span))
+    }
+
+    /// expect
+    ///
+    /// Consumes the given token, or errors out if it isn't the current one.
+    fn expect(
+        &mut self,
+        node: &str,
+        expected: Token,
+    ) -> Result<(), ParserError> {
+        let (tok, span) =
+            &self.tokens[self.curr_index(node, &expected.to_string())?];
+        if *tok != expected {
+            return Err(ParserError {
+                node: node.to_string(),
+                expected: expected.to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            });
+        }
+
+        self.index += 1;
+        Ok(())
     }
 
     /// parse_tuple
@@ -145,20 +662,52 @@ impl Parser {
     /// Given a situation in which the current token is a left parenthesis,
     /// starts parsing a tuple from that location.
     fn parse_tuple(&mut self) -> ParseResult {
-        self.index += 1;
-        let mut ret_vec: Vec<ASTNode> = vec![];
-        loop {
-            if let (Token::RightParen, _) =
-                &self.tokens[self.curr_index("tuple")?]
-            {
-                self.index += 1;
-                break;
-            }
+        let (elements, span) = self.parse_sequence("tuple", Token::RightParen);
+        if elements.iter().any(|e| matches!(e, ASTNode::Binding(..))) {
+            Ok(self.record(elements, span))
+        } else {
+            Ok(ASTNode::Tuple(elements, span))
+        }
+    }
+
+    /// record
+    ///
+    /// A tuple containing bindings is a record, in which each binding labels
+    /// a field rather than binding a name. Every element has to be a
+    /// binding, and no label can be used twice - offending elements are
+    /// reported and left out.
+    fn record(&mut self, elements: Vec<ASTNode>, span: Span) -> ASTNode {
+        let mut fields: Vec<(String, ASTNode)> = vec![];
 
-            ret_vec.push(self.parse()?);
+        for element in elements {
+            let error = match element {
+                ASTNode::Binding((label, value), binding_span) => {
+                    let label_span =
+                        binding_span.start..binding_span.start + label.len();
+                    if label.starts_with('$') {
+                        ("a field name", label_span)
+                    } else if fields.iter().any(|(used, _)| *used == label) {
+                        ("a label not already in use", label_span)
+                    } else {
+                        fields.push((label, *value));
+                        continue;
+                    }
+                }
+                // already reported
+                ASTNode::Error(_) => continue,
+                element => ("a field", element.span()),
+            };
+
+            let (expected, span) = error;
+            self.errors.push(ParserError {
+                node: "record".to_string(),
+                expected: expected.to_string(),
+                found: self.token_at(span.start),
+                span,
+            });
         }
 
-        Ok(ASTNode::Tuple(ret_vec))
+        ASTNode::Record(fields, span)
     }
 
     /// parse_block
@@ -166,19 +715,226 @@ impl Parser {
     /// Given a situation in which the current token is a left brace,
     /// starts parsing a block from that location.
     fn parse_block(&mut self) -> ParseResult {
+        let (expressions, span) =
+            self.parse_sequence("block", Token::RightBrace);
+        Ok(ASTNode::Block(expressions, span))
+    }
+
+    /// parse_parameters
+    ///
+    /// Parses the parameters declared at the start of a block, between a
+    /// pair of pipes: any number of names, the last of which may be preceded
+    /// by `...` to take the rest of the arguments. Each can be followed by
+    /// `: Type` to annotate it, and the pipes by `-> Type` to annotate the
+    /// output of the block.
+    fn parse_parameters(&mut self) -> ParseResult {
+        let start = self.tokens[self.index].1.start;
         self.index += 1;
-        let mut ret_vec: Vec<ASTNode> = vec![];
-        loop {
-            if let (Token::RightBrace, _) =
-                &self.tokens[self.curr_index("block")?]
+        let mut parameters = Parameters::default();
+
+        let mut end = loop {
+            let (tok, span) =
+                self.tokens[self.curr_index("parameters", "|")?].clone();
+            match tok {
+                Token::Pipe => {
+                    self.index += 1;
+                    break span.end;
+                }
+                // the rest parameter has to come last
+                _ if parameters.rest.is_some() => {
+                    return Err(ParserError {
+                        node: "parameters".to_string(),
+                        expected: "|".to_string(),
+                        found: Some(tok),
+                        span,
+                    })
+                }
+                Token::Ellipsis => {
+                    self.index += 1;
+                    parameters.rest = Some(self.parameter(&mut parameters)?);
+                }
+                _ => {
+                    let name = self.parameter(&mut parameters)?;
+                    parameters.names.push(name);
+                }
+            }
+        };
+
+        if let Some((Token::Arrow, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+            let (output, span) = self.parse_annotation()?;
+            end = span.end;
+            parameters.output = Some((output, span));
+        }
+
+        // every parameter has to have a name of its own
+        let declared: Vec<_> = parameters.declared().collect();
+        for (index, (name, span)) in declared.iter().enumerate() {
+            if declared[..index].iter().any(|(used, _)| used == name) {
+                return Err(ParserError {
+                    node: "parameters".to_string(),
+                    expected: "a name not already in use".to_string(),
+                    found: Some(Token::Identifier(name.clone())),
+                    span: span.clone(),
+                });
+            }
+        }
+
+        Ok(ASTNode::Parameters(parameters, start..end))
+    }
+
+    /// a declared parameter, recording its type annotation (if it has one)
+    /// along with the others
+    fn parameter(
+        &mut self,
+        parameters: &mut Parameters,
+    ) -> Result<(String, Span), ParserError> {
+        let (name, span) = self.parameter_name()?;
+        if let Some((Token::Colon, _)) = self.tokens.get(self.index) {
+            self.index += 1;
+            let (annotation, _) = self.parse_annotation()?;
+            parameters.types.push((name.clone(), annotation));
+        }
+        Ok((name, span))
+    }
+
+    /// the name of a declared parameter, which can't be a parameter (or rec)
+    /// itself
+    fn parameter_name(&mut self) -> Result<(String, Span), ParserError> {
+        let (tok, span) =
+            &self.tokens[self.curr_index("parameters", "a parameter name")?];
+        match tok {
+            Token::Identifier(name)
+                if !name.starts_with('$') && name != "rec" =>
             {
                 self.index += 1;
-                break;
+                Ok((name.clone(), span.clone()))
+            }
+            _ => Err(ParserError {
+                node: "parameters".to_string(),
+                expected: "a parameter name".to_string(),
+                found: Some(tok.clone()),
+                span: span.clone(),
+            }),
+        }
+    }
+
+    /// parse_sequence
+    ///
+    /// Parses the expressions between the current (opening) token and the
+    /// given closing token. This is where syntax errors are recovered from:
+    /// an expression that fails to parse is recorded, replaced by an
+    /// [ASTNode::Error], and parsing resumes from the next sensible point.
+    fn parse_sequence(
+        &mut self,
+        ast_node_name: &str,
+        closer: Token,
+    ) -> (Vec<ASTNode>, Span) {
+        let start = self.tokens[self.index].1.start;
+        self.index += 1;
+        let mut ret_vec: Vec<ASTNode> = vec![];
+
+        let end = loop {
+            let Some((tok, span)) = self.tokens.get(self.index) else {
+                // an unclosed sequence swallows the rest of the input
+                let error = self
+                    .curr_index(ast_node_name, &closer.to_string())
+                    .unwrap_err();
+                let end = error.span.end;
+                self.errors.push(error);
+                break end;
+            };
+
+            if *tok == closer {
+                self.index += 1;
+                break span.end;
             }
 
-            ret_vec.push(self.parse()?);
+            // a mismatched closer most likely closes an enclosing sequence,
+            // so this one is treated as if its closer had been forgotten
+            if matches!(tok, Token::RightParen | Token::RightBrace) {
+                self.errors.push(ParserError {
+                    node: ast_node_name.to_string(),
+                    expected: closer.to_string(),
+                    found: Some(tok.clone()),
+                    span: span.clone(),
+                });
+                break span.start;
+            }
+
+            // a block can start by declaring its parameters
+            let declares = *tok == Token::Pipe
+                && closer == Token::RightBrace
+                && ret_vec.is_empty();
+
+            let error_start = self.index;
+            let parsed = if declares {
+                self.parse_parameters()
+            } else {
+                self.parse_expression()
+            };
+            match parsed {
+                Ok(expression) => ret_vec.push(expression),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+
+                    // always make progress, even if we resynchronized on the
+                    // very token that caused the error
+                    if self.index == error_start {
+                        self.index += 1;
+                    }
+
+                    let error_span = self.span_between(error_start, self.index);
+                    ret_vec.push(ASTNode::Error(error_span));
+                }
+            }
+        };
+
+        (ret_vec, start..end)
+    }
+
+    /// synchronize
+    ///
+    /// Skips tokens until a point from which parsing can sensibly resume:
+    /// a closing parenthesis or brace of the enclosing sequence, or the
+    /// start of a binding.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some((tok, _)) = self.tokens.get(self.index) {
+            match tok {
+                Token::LeftParen | Token::LeftBrace => depth += 1,
+                Token::RightParen | Token::RightBrace if depth > 0 => {
+                    depth -= 1
+                }
+                Token::RightParen | Token::RightBrace => return,
+                Token::Identifier(_) if depth == 0 => {
+                    if let Some((Token::Colon, _)) =
+                        self.tokens.get(self.index + 1)
+                    {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.index += 1;
         }
+    }
 
-        Ok(ASTNode::Block(ret_vec))
+    /// the token starting at the given byte offset
+    fn token_at(&self, start: usize) -> Option<Token> {
+        self.tokens
+            .iter()
+            .find(|(_, span)| span.start == start)
+            .map(|(tok, _)| tok.clone())
     }
-}
\ No newline at end of file
+
+    /// the span covering the tokens in [from, to)
+    fn span_between(&self, from: usize, to: usize) -> Span {
+        let start = self.tokens.get(from).map_or(0, |(_, span)| span.start);
+        let end = self.tokens[..to.min(self.tokens.len())]
+            .last()
+            .map_or(start, |(_, span)| span.end);
+        start..end.max(start)
+    }
+}
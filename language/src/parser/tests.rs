@@ -3,8 +3,14 @@ use logos::Span;
 use crate::lexer::{lex, Token};
 
 use super::ASTNode;
+use super::Accessor;
+use super::Arm;
 use super::LiteralVariant;
+use super::Parameters;
 use super::Parser;
+use super::ParserError;
+use super::Pattern;
+use super::SyntaxError;
 use super::PipeType;
 
 fn lex_unconditionally(input: &str) -> Vec<(Token, Span)> {
@@ -14,10 +20,86 @@ fn lex_unconditionally(input: &str) -> Vec<(Token, Span)> {
         .collect()
 }
 
-fn lex_and_parse(
+fn lex_and_parse(input: &str) -> Result<super::ASTNode, super::ParserError> {
+    Parser::new(lex_unconditionally(input)).parse()
+}
+
+/// placeholder span for tests that only care about the shape of the tree
+const NO_SPAN: Span = 0..0;
+
+/// parses the input and replaces every span with [NO_SPAN]
+fn lex_and_parse_shape(
     input: &str,
 ) -> Result<super::ASTNode, super::ParserError> {
-    Parser::new(lex_unconditionally(input)).parse()
+    lex_and_parse(input).map(erase_spans)
+}
+
+fn erase_spans(node: ASTNode) -> ASTNode {
+    let erase_all = |nodes: Vec<ASTNode>| -> Vec<ASTNode> {
+        nodes.into_iter().map(erase_spans).collect()
+    };
+
+    match node {
+        ASTNode::Block(body, _) => ASTNode::Block(erase_all(body), NO_SPAN),
+        ASTNode::Tuple(body, _) => ASTNode::Tuple(erase_all(body), NO_SPAN),
+        ASTNode::Record(fields, _) => ASTNode::Record(
+            fields
+                .into_iter()
+                .map(|(label, value)| (label, erase_spans(value)))
+                .collect(),
+            NO_SPAN,
+        ),
+        ASTNode::Access(operand, accessor, _) => {
+            ASTNode::Access(Box::new(erase_spans(*operand)), accessor, NO_SPAN)
+        }
+        ASTNode::Pipe(stages, pipe_types, _) => {
+            ASTNode::Pipe(erase_all(stages), pipe_types, NO_SPAN)
+        }
+        ASTNode::Paste(operand, _) => {
+            ASTNode::Paste(Box::new(erase_spans(*operand)), NO_SPAN)
+        }
+        ASTNode::Type(operand, _) => {
+            ASTNode::Type(Box::new(erase_spans(*operand)), NO_SPAN)
+        }
+        ASTNode::Annotated(value, annotation, _) => ASTNode::Annotated(
+            Box::new(erase_spans(*value)),
+            annotation,
+            NO_SPAN,
+        ),
+        ASTNode::Binding((id, value), _) => {
+            ASTNode::Binding((id, Box::new(erase_spans(*value))), NO_SPAN)
+        }
+        ASTNode::Match(subject, arms, _) => ASTNode::Match(
+            Box::new(erase_spans(*subject)),
+            arms.into_iter()
+                .map(|arm| Arm {
+                    pattern: arm.pattern,
+                    guard: arm.guard.map(erase_spans),
+                    body: erase_spans(arm.body),
+                    span: NO_SPAN,
+                })
+                .collect(),
+            NO_SPAN,
+        ),
+        ASTNode::Parameters(parameters, _) => {
+            let erase = |(name, _)| (name, NO_SPAN);
+            ASTNode::Parameters(
+                Parameters {
+                    names: parameters.names.into_iter().map(erase).collect(),
+                    rest: parameters.rest.map(erase),
+                    types: parameters.types,
+                    output: parameters
+                        .output
+                        .map(|(output, _)| (output, NO_SPAN)),
+                },
+                NO_SPAN,
+            )
+        }
+        ASTNode::Import(path, _) => ASTNode::Import(path, NO_SPAN),
+        ASTNode::Identifier(id, _) => ASTNode::Identifier(id, NO_SPAN),
+        ASTNode::Literal(literal, _) => ASTNode::Literal(literal, NO_SPAN),
+        ASTNode::Error(_) => ASTNode::Error(NO_SPAN),
+    }
 }
 
 #[test]
@@ -25,9 +107,10 @@ fn test_integer() {
     let code = "1 12 -1 -12";
 
     assert_eq!(
-        lex_and_parse(code),
+        lex_and_parse_shape(code),
         Ok(super::ASTNode::Literal(
-            super::LiteralVariant::IntegerLiteral(1)
+            super::LiteralVariant::IntegerLiteral(1),
+            NO_SPAN
         ))
     );
 }
@@ -36,7 +119,10 @@ fn test_integer() {
 fn test_empty_tuple() {
     let code = "()";
 
-    assert_eq!(lex_and_parse(code), Ok(super::ASTNode::Tuple(vec![])));
+    assert_eq!(
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Tuple(vec![], NO_SPAN))
+    );
 }
 
 #[test]
@@ -53,27 +139,36 @@ fn test_literal_tuple() {
     "#;
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Tuple(vec![
-            super::ASTNode::Literal(super::LiteralVariant::StringLiteral(
-                "hello".to_string()
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::StringLiteral(
-                "world".to_string()
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::IntegerLiteral(
-                2
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::FloatLiteral(
-                2.0
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::IntegerLiteral(
-                -2
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::FloatLiteral(
-                -2.0
-            )),
-        ]))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Tuple(
+            vec![
+                super::ASTNode::Literal(
+                    super::LiteralVariant::StringLiteral("hello".to_string()),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::StringLiteral("world".to_string()),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::IntegerLiteral(2),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::FloatLiteral(2.0),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::IntegerLiteral(-2),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::FloatLiteral(-2.0),
+                    NO_SPAN
+                ),
+            ],
+            NO_SPAN
+        ))
     );
 }
 
@@ -82,8 +177,11 @@ fn test_nested_tuple() {
     let code = r"(())";
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Tuple(vec![super::ASTNode::Tuple(vec![])]))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Tuple(
+            vec![super::ASTNode::Tuple(vec![], NO_SPAN)],
+            NO_SPAN
+        ))
     )
 }
 
@@ -91,7 +189,10 @@ fn test_nested_tuple() {
 fn test_empty_block() {
     let code = "{}";
 
-    assert_eq!(lex_and_parse(code), Ok(super::ASTNode::Block(vec![])));
+    assert_eq!(
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Block(vec![], NO_SPAN))
+    );
 }
 
 #[test]
@@ -108,27 +209,36 @@ fn test_literal_block() {
     "#;
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Block(vec![
-            super::ASTNode::Literal(super::LiteralVariant::StringLiteral(
-                "hello".to_string()
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::StringLiteral(
-                "world".to_string()
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::IntegerLiteral(
-                2
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::FloatLiteral(
-                2.0
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::IntegerLiteral(
-                -2
-            )),
-            super::ASTNode::Literal(super::LiteralVariant::FloatLiteral(
-                -2.0
-            )),
-        ]))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Block(
+            vec![
+                super::ASTNode::Literal(
+                    super::LiteralVariant::StringLiteral("hello".to_string()),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::StringLiteral("world".to_string()),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::IntegerLiteral(2),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::FloatLiteral(2.0),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::IntegerLiteral(-2),
+                    NO_SPAN
+                ),
+                super::ASTNode::Literal(
+                    super::LiteralVariant::FloatLiteral(-2.0),
+                    NO_SPAN
+                ),
+            ],
+            NO_SPAN
+        ))
     );
 }
 
@@ -137,11 +247,14 @@ fn test_nested_block() {
     let code = r"{{} {}}";
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Block(vec![
-            super::ASTNode::Block(vec![]),
-            super::ASTNode::Block(vec![])
-        ]))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Block(
+            vec![
+                super::ASTNode::Block(vec![], NO_SPAN),
+                super::ASTNode::Block(vec![], NO_SPAN)
+            ],
+            NO_SPAN
+        ))
     )
 }
 
@@ -150,8 +263,8 @@ fn test_identifier() {
     let code = "hello world";
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Identifier("hello".to_string()))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Identifier("hello".to_string(), NO_SPAN))
     );
 }
 
@@ -163,23 +276,28 @@ fn test_binding_literal() {
     "#;
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Tuple(vec![
-            super::ASTNode::Binding((
-                "a".to_string(),
-                Box::new(super::ASTNode::Literal(
-                    super::LiteralVariant::StringLiteral(
-                        "hello".to_string()
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Record(
+            vec![
+                (
+                    "a".to_string(),
+                    super::ASTNode::Literal(
+                        super::LiteralVariant::StringLiteral(
+                            "hello".to_string()
+                        ),
+                        NO_SPAN
                     )
-                ))
-            )),
-            super::ASTNode::Binding((
-                "b".to_string(),
-                Box::new(super::ASTNode::Literal(
-                    super::LiteralVariant::IntegerLiteral(2)
-                ))
-            )),
-        ]))
+                ),
+                (
+                    "b".to_string(),
+                    super::ASTNode::Literal(
+                        super::LiteralVariant::IntegerLiteral(2),
+                        NO_SPAN
+                    )
+                ),
+            ],
+            NO_SPAN
+        ))
     );
 }
 
@@ -193,17 +311,20 @@ fn test_bind_identifier() {
     "#;
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Tuple(vec![
-            super::ASTNode::Binding((
-                "a".to_string(),
-                Box::new(super::ASTNode::Identifier("bruh".to_string()))
-            )),
-            super::ASTNode::Binding((
-                "b".to_string(),
-                Box::new(super::ASTNode::Identifier("string".to_string()))
-            )),
-        ]))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Record(
+            vec![
+                (
+                    "a".to_string(),
+                    super::ASTNode::Identifier("bruh".to_string(), NO_SPAN)
+                ),
+                (
+                    "b".to_string(),
+                    super::ASTNode::Identifier("string".to_string(), NO_SPAN)
+                ),
+            ],
+            NO_SPAN
+        ))
     )
 }
 
@@ -215,17 +336,29 @@ fn test_type() {
     "#;
 
     assert_eq!(
-        lex_and_parse(code),
-        Ok(super::ASTNode::Type(Box::new(super::ASTNode::Tuple(vec![
-            super::ASTNode::Binding((
-                "a".to_string(),
-                Box::new(super::ASTNode::Identifier("string".to_string()))
-            )),
-            super::ASTNode::Binding((
-                "b".to_string(),
-                Box::new(super::ASTNode::Identifier("integer".to_string()))
+        lex_and_parse_shape(code),
+        Ok(super::ASTNode::Type(
+            Box::new(super::ASTNode::Record(
+                vec![
+                    (
+                        "a".to_string(),
+                        super::ASTNode::Identifier(
+                            "string".to_string(),
+                            NO_SPAN
+                        )
+                    ),
+                    (
+                        "b".to_string(),
+                        super::ASTNode::Identifier(
+                            "integer".to_string(),
+                            NO_SPAN
+                        )
+                    ),
+                ],
+                NO_SPAN
             )),
-        ]))))
+            NO_SPAN
+        ))
     );
 }
 
@@ -237,13 +370,14 @@ fn test_basic_pipe() {
 
     let expected_ast = super::ASTNode::Pipe(
         vec![
-            super::ASTNode::Identifier("a".to_string()),
-            super::ASTNode::Identifier("b".to_string()),
+            super::ASTNode::Identifier("a".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("b".to_string(), NO_SPAN),
         ],
         vec![super::PipeType::Standard],
+        NO_SPAN,
     );
 
-    assert_eq!(lex_and_parse(code), Ok(expected_ast));
+    assert_eq!(lex_and_parse_shape(code), Ok(expected_ast));
 }
 
 #[test]
@@ -254,14 +388,15 @@ fn test_basic_pipe_chain() {
 
     let expected_ast = super::ASTNode::Pipe(
         vec![
-            super::ASTNode::Identifier("a".to_string()),
-            super::ASTNode::Identifier("b".to_string()),
-            super::ASTNode::Identifier("c".to_string()),
+            super::ASTNode::Identifier("a".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("b".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("c".to_string(), NO_SPAN),
         ],
         vec![super::PipeType::Standard, super::PipeType::Standard],
+        NO_SPAN,
     );
 
-    assert_eq!(lex_and_parse(code), Ok(expected_ast));
+    assert_eq!(lex_and_parse_shape(code), Ok(expected_ast));
 }
 
 #[test]
@@ -272,19 +407,20 @@ fn test_mixed_pipe_chain() {
 
     let expected_ast = super::ASTNode::Pipe(
         vec![
-            super::ASTNode::Identifier("a".to_string()),
-            super::ASTNode::Identifier("b".to_string()),
-            super::ASTNode::Identifier("c".to_string()),
-            super::ASTNode::Identifier("d".to_string()),
+            super::ASTNode::Identifier("a".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("b".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("c".to_string(), NO_SPAN),
+            super::ASTNode::Identifier("d".to_string(), NO_SPAN),
         ],
         vec![
             super::PipeType::Destructure,
             super::PipeType::Standard,
             super::PipeType::Destructure,
         ],
+        NO_SPAN,
     );
 
-    assert_eq!(lex_and_parse(code), Ok(expected_ast));
+    assert_eq!(lex_and_parse_shape(code), Ok(expected_ast));
 }
 
 #[test]
@@ -306,75 +442,807 @@ fn parse_complete_blockpipe() {
             (a b) |* add
         }"#;
 
-    let expected_ast = ASTNode::Binding((
-        "main".to_string(),
-        Box::new(ASTNode::Block(vec![
-            ASTNode::Paste(Box::new(ASTNode::Tuple(vec![
-                ASTNode::Literal(LiteralVariant::StringLiteral(
-                    "declarations.blkp".to_string(),
-                )),
-            ]))),
-            ASTNode::Binding((
-                "add".to_string(),
-                Box::new(ASTNode::Block(vec![
-                    ASTNode::Binding((
-                        "in".to_string(),
-                        Box::new(ASTNode::Type(Box::new(ASTNode::Tuple(
-                            vec![
-                                ASTNode::Binding((
+    let expected_ast = ASTNode::Binding(
+        (
+            "main".to_string(),
+            Box::new(ASTNode::Block(
+                vec![
+                    ASTNode::Paste(
+                        Box::new(ASTNode::Tuple(
+                            vec![ASTNode::Literal(
+                                LiteralVariant::StringLiteral(
+                                    "declarations.blkp".to_string(),
+                                ),
+                                NO_SPAN,
+                            )],
+                            NO_SPAN,
+                        )),
+                        NO_SPAN,
+                    ),
+                    ASTNode::Binding(
+                        (
+                            "add".to_string(),
+                            Box::new(ASTNode::Block(
+                                vec![
+                                    ASTNode::Binding(
+                                        (
+                                            "in".to_string(),
+                                            Box::new(ASTNode::Type(
+                                                Box::new(ASTNode::Record(
+                                                    vec![
+                                (
                                     "arg1".to_string(),
-                                    Box::new(ASTNode::Identifier(
-                                        "integer".to_string(),
-                                    )),
-                                )),
-                                ASTNode::Binding((
+                                    ASTNode::Identifier(
+                                        "integer".to_string(), NO_SPAN
+                                    ),
+                                ),
+                                (
                                     "arg2".to_string(),
-                                    Box::new(ASTNode::Identifier(
-                                        "integer".to_string(),
-                                    )),
-                                )),
+                                    ASTNode::Identifier(
+                                        "integer".to_string(), NO_SPAN
+                                    ),
+                                ),
                             ],
-                        )))),
-                    )),
-                    ASTNode::Binding((
-                        "out".to_string(),
-                        Box::new(ASTNode::Type(Box::new(ASTNode::Tuple(
-                            vec![ASTNode::Binding((
+                                                    NO_SPAN,
+                                                )),
+                                                NO_SPAN,
+                                            )),
+                                        ),
+                                        NO_SPAN,
+                                    ),
+                                    ASTNode::Binding(
+                                        (
+                                            "out".to_string(),
+                                            Box::new(ASTNode::Type(
+                                                Box::new(ASTNode::Record(
+                                                    vec![(
                                 "out1".to_string(),
-                                Box::new(ASTNode::Identifier(
-                                    "integer".to_string(),
-                                )),
-                            ))],
-                        )))),
-                    )),
-                    ASTNode::Pipe(
-                        vec![
-                            ASTNode::Tuple(vec![
-                                ASTNode::Identifier("in".to_string()),
+                                ASTNode::Identifier(
+                                    "integer".to_string(), NO_SPAN
+                                ),
+                            )],
+                                                    NO_SPAN,
+                                                )),
+                                                NO_SPAN,
+                                            )),
+                                        ),
+                                        NO_SPAN,
+                                    ),
+                                    ASTNode::Pipe(
+                                        vec![
+                                            ASTNode::Tuple(
+                                                vec![
+                                ASTNode::Identifier("in".to_string(), NO_SPAN),
                                 ASTNode::Literal(
                                     LiteralVariant::StringLiteral(
                                         "add".to_string(),
-                                    ),
+                                    ), NO_SPAN
                                 ),
-                            ]),
-                            ASTNode::Identifier("plz".to_string()),
+                            ],
+                                                NO_SPAN,
+                                            ),
+                                            ASTNode::Identifier(
+                                                "plz".to_string(),
+                                                NO_SPAN,
+                                            ),
+                                        ],
+                                        vec![PipeType::Destructure],
+                                        NO_SPAN,
+                                    ),
+                                ],
+                                NO_SPAN,
+                            )),
+                        ),
+                        NO_SPAN,
+                    ),
+                    ASTNode::Pipe(
+                        vec![
+                            ASTNode::Tuple(
+                                vec![
+                                    ASTNode::Identifier(
+                                        "a".to_string(),
+                                        NO_SPAN,
+                                    ),
+                                    ASTNode::Identifier(
+                                        "b".to_string(),
+                                        NO_SPAN,
+                                    ),
+                                ],
+                                NO_SPAN,
+                            ),
+                            ASTNode::Identifier("add".to_string(), NO_SPAN),
                         ],
                         vec![PipeType::Destructure],
+                        NO_SPAN,
                     ),
-                ])),
+                ],
+                NO_SPAN,
             )),
-            ASTNode::Pipe(
-                vec![
-                    ASTNode::Tuple(vec![
-                        ASTNode::Identifier("a".to_string()),
-                        ASTNode::Identifier("b".to_string()),
+        ),
+        NO_SPAN,
+    );
+
+    assert_eq!(lex_and_parse_shape(code), Ok(expected_ast));
+}
+#[test]
+fn test_tuple_span() {
+    let code = r#"(1 "a")"#;
+
+    assert_eq!(
+        lex_and_parse(code),
+        Ok(ASTNode::Tuple(
+            vec![
+                ASTNode::Literal(LiteralVariant::IntegerLiteral(1), 1..2),
+                ASTNode::Literal(
+                    LiteralVariant::StringLiteral("a".to_string()),
+                    3..6
+                ),
+            ],
+            0..7
+        ))
+    );
+}
+
+#[test]
+fn test_block_span() {
+    let code = "{ a {} }";
+
+    assert_eq!(
+        lex_and_parse(code),
+        Ok(ASTNode::Block(
+            vec![
+                ASTNode::Identifier("a".to_string(), 2..3),
+                ASTNode::Block(vec![], 4..6),
+            ],
+            0..8
+        ))
+    );
+}
+
+#[test]
+fn test_pipe_span() {
+    let code = "a |* b | c";
+
+    assert_eq!(
+        lex_and_parse(code),
+        Ok(ASTNode::Pipe(
+            vec![
+                ASTNode::Identifier("a".to_string(), 0..1),
+                ASTNode::Identifier("b".to_string(), 5..6),
+                ASTNode::Identifier("c".to_string(), 9..10),
+            ],
+            vec![PipeType::Destructure, PipeType::Standard],
+            0..10
+        ))
+    );
+}
+
+#[test]
+fn test_binding_span() {
+    let code = "x: (1 2) | f";
+
+    assert_eq!(
+        lex_and_parse(code),
+        Ok(ASTNode::Binding(
+            (
+                "x".to_string(),
+                Box::new(ASTNode::Pipe(
+                    vec![
+                        ASTNode::Tuple(
+                            vec![
+                                ASTNode::Literal(
+                                    LiteralVariant::IntegerLiteral(1),
+                                    4..5
+                                ),
+                                ASTNode::Literal(
+                                    LiteralVariant::IntegerLiteral(2),
+                                    6..7
+                                ),
+                            ],
+                            3..8
+                        ),
+                        ASTNode::Identifier("f".to_string(), 11..12),
+                    ],
+                    vec![PipeType::Standard],
+                    3..12
+                ))
+            ),
+            0..12
+        ))
+    );
+}
+
+#[test]
+fn test_type_and_paste_span() {
+    let code = "(type (a) paste (b))";
+
+    assert_eq!(
+        lex_and_parse(code),
+        Ok(ASTNode::Tuple(
+            vec![
+                ASTNode::Type(
+                    Box::new(ASTNode::Tuple(
+                        vec![ASTNode::Identifier("a".to_string(), 7..8)],
+                        6..9
+                    )),
+                    1..9
+                ),
+                ASTNode::Paste(
+                    Box::new(ASTNode::Tuple(
+                        vec![ASTNode::Identifier("b".to_string(), 17..18)],
+                        16..19
+                    )),
+                    10..19
+                ),
+            ],
+            0..20
+        ))
+    );
+}
+
+#[test]
+fn test_import() {
+    assert_eq!(
+        lex_and_parse(r#"math: import "lib/math.blkp""#),
+        Ok(ASTNode::Binding(
+            (
+                "math".to_string(),
+                Box::new(ASTNode::Import("lib/math.blkp".to_string(), 6..28))
+            ),
+            0..28
+        ))
+    );
+
+    assert_eq!(
+        lex_and_parse("import (a)"),
+        Err(ParserError {
+            node: "import".to_string(),
+            expected: "a module path".to_string(),
+            found: Some(Token::LeftParen),
+            span: 7..8,
+        })
+    );
+}
+
+#[test]
+fn test_field_access() {
+    assert_eq!(
+        lex_and_parse("person.address.city | f"),
+        Ok(ASTNode::Pipe(
+            vec![
+                ASTNode::Access(
+                    Box::new(ASTNode::Access(
+                        Box::new(ASTNode::Identifier(
+                            "person".to_string(),
+                            0..6
+                        )),
+                        Accessor::Field("address".to_string()),
+                        0..14
+                    )),
+                    Accessor::Field("city".to_string()),
+                    0..19
+                ),
+                ASTNode::Identifier("f".to_string(), 22..23),
+            ],
+            vec![PipeType::Standard],
+            0..23
+        ))
+    );
+
+    assert_eq!(
+        lex_and_parse("person.$0"),
+        Err(ParserError {
+            node: "access".to_string(),
+            expected: "a field name or index".to_string(),
+            found: Some(Token::Identifier("$0".to_string())),
+            span: 7..9,
+        })
+    );
+}
+
+#[test]
+fn test_index_and_slice_access() {
+    let access = |operand: ASTNode, accessor: Accessor, span: Span| {
+        ASTNode::Access(Box::new(operand), accessor, span)
+    };
+    let rows = ASTNode::Identifier("rows".to_string(), 0..4);
+
+    assert_eq!(
+        lex_and_parse("rows.-1"),
+        Ok(access(rows.clone(), Accessor::Index(-1), 0..7))
+    );
+    assert_eq!(
+        lex_and_parse("rows.2.."),
+        Ok(access(rows.clone(), Accessor::Slice(2, None), 0..8))
+    );
+    // 0.10 is two indices, the second of them 10 rather than 1
+    assert_eq!(
+        lex_and_parse("rows.0.10..-1"),
+        Ok(access(
+            access(rows.clone(), Accessor::Index(0), 0..6),
+            Accessor::Slice(10, Some(-1)),
+            0..13
+        ))
+    );
+    assert_eq!(
+        lex_and_parse("rows.-3.5.name"),
+        Ok(access(
+            access(
+                access(rows, Accessor::Index(-3), 0..7),
+                Accessor::Index(5),
+                0..9
+            ),
+            Accessor::Field("name".to_string()),
+            0..14
+        ))
+    );
+}
+
+#[test]
+fn test_record_errors() {
+    let (ast, errors) = lex_and_parse_program("(a: 1 2 a: 3 $0: 4)");
+
+    assert_eq!(
+        errors,
+        vec![
+            ParserError {
+                node: "record".to_string(),
+                expected: "a field".to_string(),
+                found: Some(Token::IntegerLiteral(2)),
+                span: 6..7,
+            },
+            ParserError {
+                node: "record".to_string(),
+                expected: "a label not already in use".to_string(),
+                found: Some(Token::Identifier("a".to_string())),
+                span: 8..9,
+            },
+            ParserError {
+                node: "record".to_string(),
+                expected: "a field name".to_string(),
+                found: Some(Token::Identifier("$0".to_string())),
+                span: 13..15,
+            },
+        ]
+    );
+    assert_eq!(
+        ast,
+        ASTNode::Record(
+            vec![(
+                "a".to_string(),
+                ASTNode::Literal(LiteralVariant::IntegerLiteral(1), 4..5)
+            )],
+            0..19
+        )
+    );
+}
+
+#[test]
+fn test_match() {
+    let code = r#"match (x) {
+        0 { "zero" }
+        (String $rest) when { T } { $rest }
+        (name: $name age: _) { $name }
+        _ { () }
+    }"#;
+
+    let block = |node: ASTNode| ASTNode::Block(vec![node], NO_SPAN);
+    let string = |s: &str| {
+        ASTNode::Literal(LiteralVariant::StringLiteral(s.to_string()), NO_SPAN)
+    };
+    let arm = |pattern, guard, body| Arm {
+        pattern,
+        guard,
+        body,
+        span: NO_SPAN,
+    };
+
+    assert_eq!(
+        lex_and_parse_shape(code),
+        Ok(ASTNode::Match(
+            Box::new(ASTNode::Identifier("x".to_string(), NO_SPAN)),
+            vec![
+                arm(
+                    Pattern::Literal(LiteralVariant::IntegerLiteral(0)),
+                    None,
+                    block(string("zero")),
+                ),
+                arm(
+                    Pattern::Tuple(vec![
+                        Pattern::Type("String".to_string()),
+                        Pattern::Capture("rest".to_string()),
                     ]),
-                    ASTNode::Identifier("add".to_string()),
-                ],
-                vec![PipeType::Destructure],
+                    Some(block(ASTNode::Literal(
+                        LiteralVariant::BooleanLiteral(true),
+                        NO_SPAN
+                    ))),
+                    block(ASTNode::Identifier("$rest".to_string(), NO_SPAN)),
+                ),
+                arm(
+                    Pattern::Record(vec![
+                        (
+                            "name".to_string(),
+                            Pattern::Capture("name".to_string())
+                        ),
+                        ("age".to_string(), Pattern::Wildcard),
+                    ]),
+                    None,
+                    block(ASTNode::Identifier("$name".to_string(), NO_SPAN)),
+                ),
+                arm(
+                    Pattern::Wildcard,
+                    None,
+                    block(ASTNode::Tuple(vec![], NO_SPAN)),
+                ),
+            ],
+            NO_SPAN
+        ))
+    );
+}
+
+#[test]
+fn test_match_spans() {
+    let ast = lex_and_parse("match ((1 2)) { ($a _) { $a } }").unwrap();
+
+    let ASTNode::Match(subject, arms, span) = ast else {
+        panic!("expected a match");
+    };
+    assert_eq!(span, 0..31);
+    assert_eq!(subject.span(), 7..12);
+    assert_eq!(arms[0].span, 16..29);
+}
+
+#[test]
+fn test_pattern_errors() {
+    let error = |code: &str| lex_and_parse(code).unwrap_err();
+
+    assert_eq!(
+        error("match (x) { a { 1 } }"),
+        ParserError {
+            node: "pattern".to_string(),
+            expected: "a pattern".to_string(),
+            found: Some(Token::Identifier("a".to_string())),
+            span: 12..13,
+        }
+    );
+    assert_eq!(error("match (x) { $0 { 1 } }").expected, "a capture name");
+    assert_eq!(error("match (x) { Int { 1 } }").expected, "a type name");
+    assert_eq!(
+        error("match (x) { ($a $a) { 1 } }").expected,
+        "a capture name not already in use"
+    );
+    assert_eq!(
+        error("match (x) { (a: _ 1) { 1 } }"),
+        ParserError {
+            node: "pattern".to_string(),
+            expected: "a field".to_string(),
+            found: Some(Token::IntegerLiteral(1)),
+            span: 18..19,
+        }
+    );
+    assert_eq!(
+        error("match (x) { _ 1 }"),
+        ParserError {
+            node: "match".to_string(),
+            expected: "a block".to_string(),
+            found: Some(Token::IntegerLiteral(1)),
+            span: 14..15,
+        }
+    );
+    assert_eq!(
+        error("match (x) { _ when T { 1 } }").expected,
+        "a guard block"
+    );
+    assert_eq!(error("match x { _ { 1 } }").expected, "(");
+}
+
+#[test]
+fn test_parameters() {
+    assert_eq!(
+        lex_and_parse("{ |a b ...rest| ($@ $1..) }"),
+        Ok(ASTNode::Block(
+            vec![
+                ASTNode::Parameters(
+                    Parameters {
+                        names: vec![
+                            ("a".to_string(), 3..4),
+                            ("b".to_string(), 5..6),
+                        ],
+                        rest: Some(("rest".to_string(), 10..14)),
+                        ..Parameters::default()
+                    },
+                    2..15
+                ),
+                ASTNode::Tuple(
+                    vec![
+                        ASTNode::Identifier("$@".to_string(), 17..19),
+                        ASTNode::Identifier("$1..".to_string(), 20..24),
+                    ],
+                    16..25
+                ),
+            ],
+            0..27
+        ))
+    );
+
+    // parameters can only be declared at the start of a block
+    assert_eq!(
+        lex_and_parse_shape("{ || }"),
+        Ok(ASTNode::Block(
+            vec![ASTNode::Parameters(Parameters::default(), NO_SPAN)],
+            NO_SPAN
+        ))
+    );
+    assert!(lex_and_parse("{ a |b| }").is_err());
+    assert!(lex_and_parse("(|a| a)").is_err());
+}
+
+#[test]
+fn test_annotations() {
+    let integer = || Pattern::Type("Integer".to_string());
+    assert_eq!(
+        lex_and_parse("x: Integer = 3"),
+        Ok(ASTNode::Binding(
+            (
+                "x".to_string(),
+                Box::new(ASTNode::Annotated(
+                    Box::new(ASTNode::Literal(
+                        LiteralVariant::IntegerLiteral(3),
+                        13..14
+                    )),
+                    integer(),
+                    3..14
+                ))
             ),
-        ])),
-    ));
+            0..14
+        ))
+    );
 
-    assert_eq!(lex_and_parse(code), Ok(expected_ast));
-}
\ No newline at end of file
+    // without an `=`, what follows the colon is the bound value
+    assert_eq!(
+        lex_and_parse_shape("x: (_ 1)"),
+        Ok(ASTNode::Binding(
+            (
+                "x".to_string(),
+                Box::new(ASTNode::Tuple(
+                    vec![
+                        ASTNode::Identifier("_".to_string(), NO_SPAN),
+                        ASTNode::Literal(
+                            LiteralVariant::IntegerLiteral(1),
+                            NO_SPAN
+                        ),
+                    ],
+                    NO_SPAN
+                ))
+            ),
+            NO_SPAN
+        ))
+    );
+
+    assert_eq!(
+        lex_and_parse_shape("{ |a: Integer ...rest: (_ String)| -> Tuple }"),
+        Ok(ASTNode::Block(
+            vec![ASTNode::Parameters(
+                Parameters {
+                    names: vec![("a".to_string(), NO_SPAN)],
+                    rest: Some(("rest".to_string(), NO_SPAN)),
+                    types: vec![
+                        ("a".to_string(), integer()),
+                        (
+                            "rest".to_string(),
+                            Pattern::Tuple(vec![
+                                Pattern::Wildcard,
+                                Pattern::Type("String".to_string()),
+                            ])
+                        ),
+                    ],
+                    output: Some((Pattern::Type("Tuple".to_string()), NO_SPAN)),
+                },
+                NO_SPAN
+            )],
+            NO_SPAN
+        ))
+    );
+
+    // annotations are types, so they can't capture or match literals
+    let error = |code| lex_and_parse(code).unwrap_err();
+    assert_eq!(error("x: (Integer $y) = (1 2)").expected, "a type");
+    assert_eq!(error("{ |a: 1| a }").expected, "a type");
+    assert_eq!(error("{ |a| -> \"b\" a }").node, "annotation");
+    assert_eq!(error("{ |a| -> }").node, "pattern");
+}
+
+#[test]
+fn test_parameter_errors() {
+    let error = |code: &str| lex_and_parse(code).unwrap_err();
+
+    assert_eq!(
+        error("{ |a $0| }"),
+        ParserError {
+            node: "parameters".to_string(),
+            expected: "a parameter name".to_string(),
+            found: Some(Token::Identifier("$0".to_string())),
+            span: 5..7,
+        }
+    );
+    assert_eq!(error("{ |rec| }").expected, "a parameter name");
+    assert_eq!(
+        error("{ |...rest a| }"),
+        ParserError {
+            node: "parameters".to_string(),
+            expected: "|".to_string(),
+            found: Some(Token::Identifier("a".to_string())),
+            span: 11..12,
+        }
+    );
+    assert_eq!(
+        error("{ |a ...a| }"),
+        ParserError {
+            node: "parameters".to_string(),
+            expected: "a name not already in use".to_string(),
+            found: Some(Token::Identifier("a".to_string())),
+            span: 8..9,
+        }
+    );
+}
+
+fn lex_and_parse_program(input: &str) -> (ASTNode, Vec<ParserError>) {
+    Parser::new(lex_unconditionally(input)).parse_program()
+}
+
+#[test]
+fn test_recover_multiple_errors() {
+    let code = "{ a: (1 : 2) b: type x c: 3 }";
+
+    let (ast, errors) = lex_and_parse_program(code);
+
+    assert_eq!(
+        errors,
+        vec![
+            ParserError {
+                node: "expression".to_string(),
+                expected: "an expression".to_string(),
+                found: Some(Token::Colon),
+                span: 8..9,
+            },
+            ParserError {
+                node: "type".to_string(),
+                expected: "(".to_string(),
+                found: Some(Token::Identifier("x".to_string())),
+                span: 21..22,
+            },
+        ]
+    );
+
+    assert_eq!(
+        erase_spans(ast),
+        ASTNode::Block(
+            vec![
+                ASTNode::Binding(
+                    (
+                        "a".to_string(),
+                        Box::new(ASTNode::Tuple(
+                            vec![
+                                ASTNode::Literal(
+                                    LiteralVariant::IntegerLiteral(1),
+                                    NO_SPAN
+                                ),
+                                ASTNode::Error(NO_SPAN),
+                            ],
+                            NO_SPAN
+                        ))
+                    ),
+                    NO_SPAN
+                ),
+                ASTNode::Error(NO_SPAN),
+                ASTNode::Binding(
+                    (
+                        "c".to_string(),
+                        Box::new(ASTNode::Literal(
+                            LiteralVariant::IntegerLiteral(3),
+                            NO_SPAN
+                        ))
+                    ),
+                    NO_SPAN
+                ),
+            ],
+            NO_SPAN
+        )
+    );
+}
+
+#[test]
+fn test_recover_error_spans() {
+    let code = "(1 | | 2 x: 3)";
+
+    let (ast, errors) = lex_and_parse_program(code);
+
+    // the broken pipe has been reported already, so the record doesn't
+    // report it again as an element that isn't a field
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].span, 5..6);
+    assert_eq!(
+        ast,
+        ASTNode::Record(
+            vec![(
+                "x".to_string(),
+                ASTNode::Literal(LiteralVariant::IntegerLiteral(3), 12..13)
+            )],
+            0..14
+        )
+    );
+}
+
+#[test]
+fn test_recover_mismatched_closers() {
+    let code = "{ (1 2 } (3";
+
+    let (ast, errors) = lex_and_parse_program(code);
+
+    assert_eq!(
+        errors,
+        vec![
+            ParserError {
+                node: "tuple".to_string(),
+                expected: ")".to_string(),
+                found: Some(Token::RightBrace),
+                span: 7..8,
+            },
+            ParserError {
+                node: "program".to_string(),
+                expected: "end of input".to_string(),
+                found: Some(Token::LeftParen),
+                span: 9..11,
+            },
+        ]
+    );
+    assert_eq!(ast.span(), 0..8);
+}
+
+#[test]
+fn test_recover_unexpected_end() {
+    let (_, errors) = lex_and_parse_program("{ a: ");
+
+    assert_eq!(
+        errors,
+        vec![
+            ParserError {
+                node: "expression".to_string(),
+                expected: "an expression".to_string(),
+                found: None,
+                span: 3..4,
+            },
+            ParserError {
+                node: "block".to_string(),
+                expected: "}".to_string(),
+                found: None,
+                span: 3..4,
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_parse_returns_first_error() {
+    assert_eq!(
+        lex_and_parse("(: :)").map_err(|error| error.span),
+        Err(1..2)
+    );
+}
+
+#[test]
+fn test_program_reports_lex_and_parse_errors() {
+    let (_, errors) = crate::parse_program_from_string("{ a: ; b: (1 }");
+
+    // the bad character is dropped, so `a` binds `b: (1`, whose tuple is
+    // then cut short by the closing brace
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(
+        &errors[0],
+        SyntaxError::Lex(error) if error.span == (5..6)
+    ));
+    assert!(matches!(
+        &errors[1],
+        SyntaxError::Parse(error) if error.span == (13..14)
+    ));
+}
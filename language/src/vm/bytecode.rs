@@ -0,0 +1,108 @@
+use std::rc::Rc;
+
+use logos::Span;
+
+use crate::interpreter::Value;
+use crate::parser::{Accessor, Parameters, Pattern};
+
+/// where the compiler found the binding for a name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    // a slot of the running block's frame
+    Local(usize),
+    // a slot of an enclosing block's frame, so many frames up the chain
+    Outer(usize, usize),
+    // not bound by any enclosing block - at the top level, this is the
+    // environment, and inside blocks the globals they captured
+    Global(String),
+    // a name that a paste may have bound, which can only be found by
+    // searching the frames at runtime
+    Dynamic(String),
+}
+
+/// a single bytecode instruction. instructions operate on a stack of values,
+/// and a block's code leaves exactly one value - its result - behind.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Constant(usize),
+    Load(Address),
+    // $0, $1, ..., $n and $ followed by a label, which blocks get from their
+    // arguments
+    Parameter(usize),
+    ParameterCount,
+    NamedParameter(String),
+    // the running block itself, as bound to rec
+    Recurse,
+    // pops a value and binds it, leaving () in its place
+    Bind(Address),
+    // binds the parameters a block declares to its arguments, after checking
+    // there are the right number of them, leaving ()
+    Declare(Parameters),
+    // pops the given number of values into a tuple
+    Tuple(usize),
+    // pops a value for each label into a record
+    Record(Vec<String>),
+    // replaces the value on top of the stack with a field, element or slice
+    // of it
+    Access(Accessor),
+    // checks the value on top of the stack against the type it was
+    // annotated with, leaving it in place
+    Annotate(Pattern),
+    // pops a value and appends it to the tuple beneath it
+    Append,
+    // pops a value and pastes it into the tuple beneath it
+    Splice,
+    // replaces the tuple on top of the stack with its last element
+    Last,
+    Closure(usize),
+    // pops a block and its input, and pipes one into the other
+    Call { destructure: bool, callee: usize },
+    // the same, but as the last instruction of a block: the call is left for
+    // the caller of the block to make
+    TailCall { destructure: bool, callee: usize },
+    // pops the blocks of the arms (each guard just before its body) and the
+    // value beneath them, and runs the first arm that accepts the value. in
+    // tail position, the arm is left for the caller of the block to run.
+    Match { branches: Vec<Branch>, tail: bool },
+    Type,
+    Import(String),
+    Pop,
+    // stands in for source that could not be parsed
+    Fail,
+}
+
+/// an arm of a compiled match, with the spans of its guard (if it has one)
+/// and body for error call stacks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub pattern: Pattern,
+    pub guard: Option<Span>,
+    pub body: Span,
+}
+
+/// a compiled block (or top level expression)
+#[derive(Debug, Default)]
+pub struct Function {
+    pub code: Vec<Instruction>,
+    // the span of the expression each instruction was compiled from
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+    // the blocks defined within this one
+    pub functions: Vec<Rc<Function>>,
+    // descriptions of the pipe stages called into, for error call stacks
+    pub callees: Vec<(String, Span)>,
+    // the names of the frame's slots - one for each name bound in the block
+    pub slots: Vec<String>,
+    // the names used by the block (or the blocks within it), which closures
+    // over it capture
+    pub captures: Vec<String>,
+    // the names bound by the code that creates closures over the block,
+    // which they capture too, so pasting one brings them into scope
+    pub bound: Vec<String>,
+}
+
+impl Function {
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|slot| slot == name)
+    }
+}
@@ -0,0 +1,394 @@
+use std::rc::Rc;
+
+use logos::Span;
+
+use super::{Address, Branch, Function, Instruction};
+use crate::interpreter::{always_given, bound_names, Interpreter, Value};
+use crate::parser::{ASTNode, Arm, LiteralVariant, PipeType};
+
+/// compile
+///
+/// Compiles an expression evaluated at the top level, where names are looked
+/// up in (and bound into) the environment of the machine running it. The
+/// blocks within it are compiled along the way, with every name they use
+/// resolved to a slot of the frame that binds it.
+pub fn compile(node: &ASTNode) -> Function {
+    let mut compiler = Compiler {
+        function: Function::default(),
+        scopes: vec![],
+        toplevel: bound_names(std::slice::from_ref(node)),
+    };
+    compiler.expression(node);
+    compiler.function
+}
+
+/// the names bound by a block, as seen by the blocks nested within it
+#[derive(Debug, Clone)]
+struct Scope {
+    slots: Vec<String>,
+    // whether the block pastes, which can bind names we know nothing about
+    pastes: bool,
+}
+
+impl Scope {
+    fn of(statements: &[ASTNode]) -> Scope {
+        let mut scope = Scope {
+            slots: vec![],
+            pastes: false,
+        };
+        for statement in statements {
+            scope.collect(statement);
+        }
+        scope
+    }
+
+    // nested blocks get scopes of their own, so they aren't searched
+    fn collect(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Binding((identifier, value), _) => {
+                if !self.slots.contains(identifier) {
+                    self.slots.push(identifier.clone());
+                }
+                self.collect(value);
+            }
+            ASTNode::Parameters(parameters, _) => {
+                for (identifier, _) in parameters.declared() {
+                    if !self.slots.contains(identifier) {
+                        self.slots.push(identifier.clone());
+                    }
+                }
+            }
+            ASTNode::Paste(operand, _) => {
+                self.pastes = true;
+                self.collect(operand);
+            }
+            ASTNode::Tuple(nodes, _) | ASTNode::Pipe(nodes, _, _) => {
+                for node in nodes {
+                    self.collect(node);
+                }
+            }
+            ASTNode::Record(fields, _) => {
+                for (_, value) in fields {
+                    self.collect(value);
+                }
+            }
+            // the guards and bodies of arms are all blocks
+            ASTNode::Type(operand, _)
+            | ASTNode::Annotated(operand, _, _)
+            | ASTNode::Access(operand, _, _)
+            | ASTNode::Match(operand, _, _) => self.collect(operand),
+            ASTNode::Block(..)
+            | ASTNode::Identifier(..)
+            | ASTNode::Literal(..)
+            | ASTNode::Import(..)
+            | ASTNode::Error(_) => {}
+        }
+    }
+}
+
+struct Compiler {
+    function: Function,
+    // the scopes of the blocks being compiled, innermost last. empty at the
+    // top level.
+    scopes: Vec<Scope>,
+    // the names the top level expression binds
+    toplevel: Vec<String>,
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction, span: &Span) {
+        self.function.code.push(instruction);
+        self.function.spans.push(span.clone());
+    }
+
+    fn constant(&mut self, value: Value, span: &Span) {
+        self.function.constants.push(value);
+        let index = self.function.constants.len() - 1;
+        self.emit(Instruction::Constant(index), span);
+    }
+
+    fn expression(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Literal(literal, span) => {
+                let value = match literal {
+                    LiteralVariant::IntegerLiteral(i) => Value::Integer(*i),
+                    LiteralVariant::BooleanLiteral(b) => Value::Boolean(*b),
+                    LiteralVariant::StringLiteral(s) => {
+                        Value::String(s.clone())
+                    }
+                    LiteralVariant::FloatLiteral(f) => Value::Float(*f),
+                };
+                self.constant(value, span);
+            }
+            ASTNode::Tuple(elements, span) => self.tuple(elements, span),
+            ASTNode::Record(fields, span) => {
+                for (_, value) in fields {
+                    self.expression(value);
+                }
+                let labels = fields.iter().map(|(label, _)| label.clone());
+                self.emit(Instruction::Record(labels.collect()), span);
+            }
+            ASTNode::Identifier(id, span) => self.identifier(id, span),
+            ASTNode::Access(operand, accessor, span) => {
+                self.expression(operand);
+                self.emit(Instruction::Access(accessor.clone()), span);
+            }
+            ASTNode::Annotated(value, annotation, span) => {
+                self.expression(value);
+                self.emit(Instruction::Annotate(annotation.clone()), span);
+            }
+            ASTNode::Block(statements, span) => {
+                let mut function = self.block(statements);
+                for name in &function.captures {
+                    self.capture(name);
+                }
+                function.bound = match self.scopes.last() {
+                    Some(scope) => scope.slots.clone(),
+                    None => self.toplevel.clone(),
+                };
+                self.function.functions.push(Rc::new(function));
+                let index = self.function.functions.len() - 1;
+                self.emit(Instruction::Closure(index), span);
+            }
+            ASTNode::Binding((identifier, value), span) => {
+                self.expression(value);
+                let address = match self.scopes.last() {
+                    Some(scope) => Address::Local(
+                        scope
+                            .slots
+                            .iter()
+                            .position(|slot| slot == identifier)
+                            .expect("binding without a slot"),
+                    ),
+                    None => Address::Global(identifier.clone()),
+                };
+                self.emit(Instruction::Bind(address), span);
+            }
+            ASTNode::Parameters(parameters, span) => {
+                self.emit(Instruction::Declare(parameters.clone()), span)
+            }
+            ASTNode::Pipe(stages, pipe_types, span) => {
+                self.pipe(stages, pipe_types, span, false)
+            }
+            ASTNode::Match(subject, arms, span) => {
+                self.match_arms(subject, arms, span, false)
+            }
+            // `type (x)` describes x itself rather than the single element
+            // tuple wrapped around it
+            ASTNode::Type(operand, span) => {
+                match operand.as_ref() {
+                    ASTNode::Tuple(elements, _) if elements.len() == 1 => {
+                        self.expression(&elements[0])
+                    }
+                    _ => self.expression(operand),
+                }
+                self.emit(Instruction::Type, span);
+            }
+            ASTNode::Import(path, span) => {
+                self.emit(Instruction::Import(path.clone()), span)
+            }
+            // outside of a tuple, the last spliced value is the one that
+            // sticks
+            ASTNode::Paste(operand, span) => {
+                self.emit(Instruction::Tuple(0), span);
+                self.paste(operand, span);
+                self.emit(Instruction::Last, span);
+            }
+            ASTNode::Error(span) => self.emit(Instruction::Fail, span),
+        }
+    }
+
+    fn tuple(&mut self, elements: &[ASTNode], span: &Span) {
+        if !elements
+            .iter()
+            .any(|node| matches!(node, ASTNode::Paste(..)))
+        {
+            for element in elements {
+                self.expression(element);
+            }
+            self.emit(Instruction::Tuple(elements.len()), span);
+            return;
+        }
+
+        // pastes splice in any number of values, so the tuple is built up
+        // one element at a time instead
+        self.emit(Instruction::Tuple(0), span);
+        for element in elements {
+            if let ASTNode::Paste(operand, _) = element {
+                self.paste(operand, span);
+            } else {
+                self.expression(element);
+                self.emit(Instruction::Append, span);
+            }
+        }
+    }
+
+    // errors from pasting point at the tuple (or paste) being built, just as
+    // they do when interpreting
+    fn paste(&mut self, operand: &ASTNode, span: &Span) {
+        let pasted = match operand {
+            ASTNode::Tuple(elements, _) => elements.as_slice(),
+            _ => std::slice::from_ref(operand),
+        };
+        for node in pasted {
+            self.expression(node);
+            self.emit(Instruction::Splice, span);
+        }
+    }
+
+    fn pipe(
+        &mut self,
+        stages: &[ASTNode],
+        pipe_types: &[PipeType],
+        span: &Span,
+        tail: bool,
+    ) {
+        self.expression(&stages[0]);
+        let last = pipe_types.len() - 1;
+        for (index, (stage, pipe_type)) in
+            stages[1..].iter().zip(pipe_types).enumerate()
+        {
+            self.expression(stage);
+            self.function
+                .callees
+                .push((Interpreter::describe_callee(stage), stage.span()));
+            let callee = self.function.callees.len() - 1;
+            let destructure = matches!(pipe_type, PipeType::Destructure);
+            let instruction = if tail && index == last {
+                Instruction::TailCall {
+                    destructure,
+                    callee,
+                }
+            } else {
+                Instruction::Call {
+                    destructure,
+                    callee,
+                }
+            };
+            self.emit(instruction, span);
+        }
+    }
+
+    fn match_arms(
+        &mut self,
+        subject: &ASTNode,
+        arms: &[Arm],
+        span: &Span,
+        tail: bool,
+    ) {
+        self.expression(subject);
+        let mut branches = vec![];
+        for arm in arms {
+            if let Some(guard) = &arm.guard {
+                self.expression(guard);
+            }
+            self.expression(&arm.body);
+            branches.push(Branch {
+                pattern: arm.pattern.clone(),
+                guard: arm.guard.as_ref().map(ASTNode::span),
+                body: arm.body.span(),
+            });
+        }
+        self.emit(Instruction::Match { branches, tail }, span);
+    }
+
+    fn capture(&mut self, id: &str) {
+        let captures = &mut self.function.captures;
+        if !always_given(id) && !captures.iter().any(|name| name == id) {
+            captures.push(id.to_string());
+        }
+    }
+
+    fn identifier(&mut self, id: &str, span: &Span) {
+        self.capture(id);
+        let bound_locally = self
+            .scopes
+            .last()
+            .is_some_and(|scope| scope.slots.iter().any(|slot| slot == id));
+
+        // parameters and rec come from the invocation, unless the block has
+        // rebound them itself
+        if !self.scopes.is_empty() && !bound_locally {
+            let special = match id {
+                "rec" => Some(Instruction::Recurse),
+                "$n" => Some(Instruction::ParameterCount),
+                _ => id.strip_prefix('$').map(|name| match name.parse() {
+                    Ok(index) => Instruction::Parameter(index),
+                    Err(_) => Instruction::NamedParameter(id.to_string()),
+                }),
+            };
+            if let Some(instruction) = special {
+                return self.emit(instruction, span);
+            }
+        }
+
+        let address = self.resolve(id);
+        self.emit(Instruction::Load(address), span);
+    }
+
+    /// finds the frame that binds a name. a paste in any block searched on
+    /// the way could bind the name first, so those names are left to be
+    /// found at runtime.
+    fn resolve(&self, id: &str) -> Address {
+        if self.scopes.is_empty() {
+            return Address::Global(id.to_string());
+        }
+
+        let mut pasted = false;
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.slots.iter().position(|slot| slot == id) {
+                return match (pasted, depth) {
+                    (true, _) => Address::Dynamic(id.to_string()),
+                    (false, 0) => Address::Local(slot),
+                    (false, _) => Address::Outer(depth, slot),
+                };
+            }
+            pasted |= scope.pastes;
+        }
+
+        if pasted {
+            Address::Dynamic(id.to_string())
+        } else {
+            Address::Global(id.to_string())
+        }
+    }
+
+    fn block(&mut self, statements: &[ASTNode]) -> Function {
+        let scope = Scope::of(statements);
+        let mut scopes = self.scopes.clone();
+        scopes.push(scope.clone());
+
+        let mut compiler = Compiler {
+            function: Function {
+                slots: scope.slots,
+                ..Function::default()
+            },
+            scopes,
+            toplevel: self.toplevel.clone(),
+        };
+
+        // empty blocks just evaluate to the empty tuple
+        if statements.is_empty() {
+            compiler.constant(Value::Tuple(vec![]), &(0..0));
+        }
+        for (index, statement) in statements.iter().enumerate() {
+            if index != 0 {
+                compiler.emit(Instruction::Pop, &statement.span());
+            }
+            // the last stage of a block's last pipe is a tail call, as is
+            // the arm picked by a match in last place
+            let last = index == statements.len() - 1;
+            match statement {
+                ASTNode::Pipe(stages, pipe_types, span) if last => {
+                    compiler.pipe(stages, pipe_types, span, true)
+                }
+                ASTNode::Match(subject, arms, span) if last => {
+                    compiler.match_arms(subject, arms, span, true)
+                }
+                _ => compiler.expression(statement),
+            }
+        }
+
+        compiler.function
+    }
+}
@@ -0,0 +1,656 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use logos::Span;
+
+use super::{compile, Address, Branch, Function, Instruction};
+use crate::interpreter::{
+    conforms, matches, pipe_into_runtime, resolve, Arguments, Environment,
+    ErrorKind, EvaluateResult, EvaluationError, Interpreter, Limits, Meter,
+    ModuleLoader, Outcome, ProgramState, RuntimeRegistry, SharedProgram,
+    Source, TypeDescriptor, Value,
+};
+use crate::parser::{ASTNode, Parameters};
+use crate::Engine;
+
+/// a block created by compiled code. rather than an image of the whole
+/// environment, it holds on to the frames it was created in, which only ever
+/// get copied if a binding is made after the closure has been created.
+#[derive(Clone)]
+pub struct Closure {
+    function: Rc<Function>,
+    parent: Option<Rc<Frame>>,
+    globals: Rc<HashMap<String, Value>>,
+    // what the block was written in
+    pub(crate) source: Rc<Source>,
+}
+
+impl Closure {
+    /// the values of the names the closure's block uses, and of those bound
+    /// by the code that created it, as an interpreted closure would have
+    /// captured them
+    pub fn image(&self) -> HashMap<String, Value> {
+        let lookup = |name: &str| {
+            let mut frame = self.parent.as_deref();
+            while let Some(current) = frame {
+                if let Some(value) = current.lookup(name) {
+                    return Some(value);
+                }
+                frame = current.parent.as_deref();
+            }
+            self.globals.get(name).cloned()
+        };
+
+        self.function
+            .captures
+            .iter()
+            .chain(&self.function.bound)
+            .filter_map(|name| Some((name.clone(), lookup(name)?)))
+            .collect()
+    }
+
+    /// the parameters the closure's block declares, if it declares any
+    pub fn parameters(&self) -> Option<&Parameters> {
+        match self.function.code.first() {
+            Some(Instruction::Declare(parameters)) => Some(parameters),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        let same_parent = match (&self.parent, &other.parent) {
+            (Some(parent), Some(other)) => Rc::ptr_eq(parent, other),
+            (None, None) => true,
+            _ => false,
+        };
+        Rc::ptr_eq(&self.function, &other.function)
+            && same_parent
+            && Rc::ptr_eq(&self.globals, &other.globals)
+    }
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<compiled block>")
+    }
+}
+
+/// the bindings of a running block
+#[derive(Clone)]
+struct Frame {
+    function: Rc<Function>,
+    slots: Vec<Option<Value>>,
+    // bindings pasted in that the block has no slot for
+    pasted: HashMap<String, Value>,
+    arguments: Rc<Arguments>,
+    // the closure being run, bound to rec
+    callee: Closure,
+    parent: Option<Rc<Frame>>,
+    globals: Rc<HashMap<String, Value>>,
+}
+
+impl Frame {
+    fn lookup(&self, name: &str) -> Option<Value> {
+        if let Some(Some(value)) =
+            self.function.slot(name).map(|slot| &self.slots[slot])
+        {
+            return Some(value.clone());
+        }
+        if let Some(value) = self.pasted.get(name) {
+            return Some(value.clone());
+        }
+
+        match name {
+            "rec" => Some(Value::CompiledClosure(self.callee.clone())),
+            _ => self.arguments.parameter(name),
+        }
+    }
+
+    fn bind(&mut self, name: String, value: Value) {
+        match self.function.slot(&name) {
+            Some(slot) => self.slots[slot] = Some(value),
+            None => {
+                self.pasted.insert(name, value);
+            }
+        }
+    }
+}
+
+fn unbound(name: &str) -> EvaluationError {
+    ErrorKind::UnboundSymbol(name.to_string()).into()
+}
+
+/// runs bytecode. code compiled at the top level reads and writes the
+/// environment by name, while blocks keep their bindings in frames.
+struct Executor<'a> {
+    env: &'a mut Environment,
+    program: &'a SharedProgram,
+    // what the code being run was written in
+    source: Rc<Source>,
+}
+
+impl Executor<'_> {
+    fn run(
+        &mut self,
+        function: &Function,
+        mut frame: Option<Rc<Frame>>,
+    ) -> Result<Outcome, EvaluationError> {
+        let mut stack = vec![];
+        for (instruction, span) in function.code.iter().zip(&function.spans) {
+            // errors point at the innermost expression that produced them
+            let with_span =
+                |error: EvaluationError| error.with_span(span.clone());
+            let metered = self.program.borrow_mut().meter.step();
+            metered.map_err(with_span)?;
+
+            match instruction {
+                // a tail call is always the last instruction, and is handed
+                // back rather than made
+                Instruction::TailCall {
+                    destructure,
+                    callee,
+                } => {
+                    let closure = stack.pop().expect("nothing to call");
+                    let input = stack.pop().expect("nothing to pipe");
+                    let (callee, callee_span) = &function.callees[*callee];
+                    return Arguments::piped(input, *destructure)
+                        .map_err(EvaluationError::from)
+                        .and_then(|arguments| {
+                            Interpreter::tail_call(
+                                self.program,
+                                closure,
+                                arguments,
+                                callee.clone(),
+                                callee_span,
+                            )
+                        })
+                        .map_err(with_span);
+                }
+                // calls are made from here rather than from step, whose frame
+                // is a lot bigger, to keep the stack small when they nest
+                Instruction::Call {
+                    destructure,
+                    callee,
+                } => {
+                    let closure = stack.pop().expect("nothing to call");
+                    let input = stack.pop().expect("nothing to pipe");
+                    let value = self
+                        .pipe(
+                            input,
+                            closure,
+                            *destructure,
+                            &function.callees[*callee],
+                        )
+                        .map_err(with_span)?;
+                    stack.push(value);
+                }
+                // so are the arms of a match, which may be tail calls too
+                Instruction::Match { branches, tail } => {
+                    let blocks = branches
+                        .iter()
+                        .map(|branch| 1 + usize::from(branch.guard.is_some()))
+                        .sum::<usize>();
+                    let blocks = stack.split_off(stack.len() - blocks);
+                    let subject = stack.pop().expect("nothing to match");
+                    match self
+                        .select(subject, branches, blocks, *tail)
+                        .map_err(with_span)?
+                    {
+                        Outcome::Done(value) => stack.push(value),
+                        call => return Ok(call),
+                    }
+                }
+                _ => self
+                    .step(function, instruction, &mut frame, &mut stack)
+                    .map_err(with_span)?,
+            }
+
+            if let Some(value) = stack.last() {
+                self.program
+                    .borrow()
+                    .meter
+                    .check(value)
+                    .map_err(with_span)?;
+            }
+        }
+
+        Ok(Outcome::Done(stack.pop().expect("bytecode left no result")))
+    }
+
+    fn step(
+        &mut self,
+        function: &Function,
+        instruction: &Instruction,
+        frame: &mut Option<Rc<Frame>>,
+        stack: &mut Vec<Value>,
+    ) -> Result<(), EvaluationError> {
+        let value = match instruction {
+            Instruction::Constant(index) => function.constants[*index].clone(),
+            Instruction::Load(address) => {
+                self.load(frame.as_deref(), address)?
+            }
+            Instruction::Parameter(index) => {
+                let current =
+                    frame.as_deref().expect("parameter outside a block");
+                match current.arguments.values.get(*index) {
+                    Some(value) => value.clone(),
+                    None => {
+                        self.lookup(Some(current), &format!("${}", index))?
+                    }
+                }
+            }
+            Instruction::ParameterCount => {
+                let current =
+                    frame.as_deref().expect("parameter outside a block");
+                Value::Integer(current.arguments.values.len() as i64)
+            }
+            Instruction::NamedParameter(name) => {
+                let current =
+                    frame.as_deref().expect("parameter outside a block");
+                self.lookup(Some(current), name)?
+            }
+            Instruction::Recurse => {
+                let current = frame.as_deref().expect("rec outside a block");
+                Value::CompiledClosure(current.callee.clone())
+            }
+            Instruction::Bind(address) => {
+                let value = stack.pop().expect("nothing to bind");
+                match (address, frame) {
+                    (Address::Local(slot), Some(frame)) => {
+                        Rc::make_mut(frame).slots[*slot] = Some(value)
+                    }
+                    (Address::Global(name), None) => {
+                        self.env.bind(name.clone(), value)
+                    }
+                    _ => unreachable!("binding to {:?}", address),
+                }
+                Value::Tuple(vec![])
+            }
+            Instruction::Declare(parameters) => {
+                let declared = match frame.as_deref() {
+                    Some(frame) => frame.arguments.declare(parameters)?,
+                    None => self.env.arguments().declare(parameters)?,
+                };
+                for (name, value) in declared {
+                    match frame {
+                        Some(frame) => Rc::make_mut(frame).bind(name, value),
+                        None => self.env.bind(name, value),
+                    }
+                }
+                Value::Tuple(vec![])
+            }
+            Instruction::Tuple(length) => {
+                Value::Tuple(stack.split_off(stack.len() - length))
+            }
+            Instruction::Record(labels) => {
+                let values = stack.split_off(stack.len() - labels.len());
+                Value::Record(labels.iter().cloned().zip(values).collect())
+            }
+            Instruction::Access(accessor) => {
+                let value = stack.pop().expect("nothing to access");
+                value.access(accessor)?
+            }
+            Instruction::Annotate(annotation) => {
+                let value = stack.pop().expect("nothing to annotate");
+                conforms(annotation, &value)?;
+                value
+            }
+            Instruction::Append => {
+                let value = stack.pop().expect("nothing to append");
+                if let Some(Value::Tuple(values)) = stack.last_mut() {
+                    values.push(value);
+                }
+                return Ok(());
+            }
+            Instruction::Splice => {
+                let value = stack.pop().expect("nothing to paste");
+                self.paste(value, frame, stack)?;
+                return Ok(());
+            }
+            Instruction::Last => match stack.pop() {
+                Some(Value::Tuple(mut values)) => {
+                    values.pop().unwrap_or(Value::Tuple(vec![]))
+                }
+                _ => unreachable!("last of a non-tuple"),
+            },
+            Instruction::Closure(index) => {
+                let function = function.functions[*index].clone();
+                Value::CompiledClosure(match frame {
+                    Some(frame) => Closure {
+                        function,
+                        parent: Some(frame.clone()),
+                        globals: frame.globals.clone(),
+                        source: self.source.clone(),
+                    },
+                    None => {
+                        let globals = function
+                            .captures
+                            .iter()
+                            .chain(&function.bound)
+                            .filter_map(|name| {
+                                Some((name.clone(), self.env.lookup(name)?))
+                            })
+                            .collect();
+                        Closure {
+                            function,
+                            parent: None,
+                            globals: Rc::new(globals),
+                            source: self.source.clone(),
+                        }
+                    }
+                })
+            }
+            Instruction::Call { .. }
+            | Instruction::TailCall { .. }
+            | Instruction::Match { .. } => {
+                unreachable!("calls are made by run")
+            }
+            Instruction::Type => {
+                let value = stack.pop().expect("nothing to describe");
+                Value::Type(TypeDescriptor::of(&value))
+            }
+            Instruction::Import(path) => {
+                ModuleLoader::import(self.program, path)?
+            }
+            Instruction::Pop => {
+                stack.pop();
+                return Ok(());
+            }
+            Instruction::Fail => {
+                return Err(ErrorKind::ParseError(
+                    "Cannot evaluate source that failed to parse".to_string(),
+                )
+                .into())
+            }
+        };
+
+        stack.push(value);
+        Ok(())
+    }
+
+    fn load(&self, frame: Option<&Frame>, address: &Address) -> EvaluateResult {
+        let (frame, slot) = match address {
+            Address::Local(slot) => {
+                (frame.expect("local outside a block"), *slot)
+            }
+            Address::Outer(depth, slot) => {
+                let mut frame = frame.expect("outer outside a block");
+                for _ in 0..*depth {
+                    frame =
+                        frame.parent.as_deref().expect("missing outer frame");
+                }
+                (frame, *slot)
+            }
+            Address::Global(name) => {
+                let value = match frame {
+                    Some(frame) => frame.globals.get(name).cloned(),
+                    None => self.env.lookup(name),
+                };
+                return value.ok_or_else(|| unbound(name));
+            }
+            Address::Dynamic(name) => return self.lookup(frame, name),
+        };
+
+        // slots are empty until the block binds them, and until then the
+        // name still refers to whatever it did outside the block
+        match &frame.slots[slot] {
+            Some(value) => Ok(value.clone()),
+            None => self.lookup(Some(frame), &frame.function.slots[slot]),
+        }
+    }
+
+    /// searches the frames for a name, starting from the given one
+    fn lookup(&self, mut frame: Option<&Frame>, name: &str) -> EvaluateResult {
+        while let Some(current) = frame {
+            if let Some(value) = current.lookup(name) {
+                return Ok(value);
+            }
+            if current.parent.is_none() {
+                return current
+                    .globals
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| unbound(name));
+            }
+            frame = current.parent.as_deref();
+        }
+
+        self.env.lookup(name).ok_or_else(|| unbound(name))
+    }
+
+    /// tuples and records contribute their elements to the tuple on top of the
+    /// stack, while closures contribute their captured bindings to the current
+    /// frame
+    fn paste(
+        &mut self,
+        value: Value,
+        frame: &mut Option<Rc<Frame>>,
+        stack: &mut [Value],
+    ) -> Result<(), EvaluationError> {
+        let image = match value {
+            Value::Tuple(values) => {
+                if let Some(Value::Tuple(spliced)) = stack.last_mut() {
+                    spliced.extend(values);
+                }
+                return Ok(());
+            }
+            Value::Record(fields) => {
+                if let Some(Value::Tuple(spliced)) = stack.last_mut() {
+                    spliced.extend(fields.into_iter().map(|(_, value)| value));
+                }
+                return Ok(());
+            }
+            other => other.bindings().ok_or_else(|| {
+                ErrorKind::TypeMismatch(format!(
+                    "Can only paste tuples and closures, not {}",
+                    TypeDescriptor::of(&other)
+                ))
+            })?,
+        };
+
+        for (id, val) in image {
+            match frame {
+                Some(frame) => Rc::make_mut(frame).bind(id, val),
+                None => self.env.bind(id, val),
+            }
+        }
+        Ok(())
+    }
+
+    fn pipe(
+        &mut self,
+        input: Value,
+        closure: Value,
+        destructure: bool,
+        (callee, span): &(String, Span),
+    ) -> EvaluateResult {
+        let arguments = Arguments::piped(input, destructure)?;
+        match closure {
+            Value::RuntimeInvocation => {
+                pipe_into_runtime(self.program, arguments.values, span)
+            }
+            other => Interpreter::execute_closure_with(
+                self.program,
+                arguments,
+                &other,
+            )
+            .map_err(|error| error.in_call(callee, Some(span.clone()))),
+        }
+    }
+
+    /// runs the first arm of a match that accepts the subject, given the
+    /// blocks of every arm
+    fn select(
+        &mut self,
+        subject: Value,
+        branches: &[Branch],
+        blocks: Vec<Value>,
+        tail: bool,
+    ) -> Result<Outcome, EvaluationError> {
+        let mut blocks = blocks.into_iter();
+        for branch in branches {
+            let guard = branch.guard.as_ref().map(|span| {
+                (blocks.next().expect("match arm without a guard"), span)
+            });
+            let body = blocks.next().expect("match arm without a body");
+            let Some(captured) = matches(&branch.pattern, &subject) else {
+                continue;
+            };
+
+            let outcome = Interpreter::enter_arm(
+                self.program,
+                captured,
+                guard.map(|(guard, span)| (guard, span.clone())),
+                (body, branch.body.clone()),
+                tail,
+            )?;
+            if let Some(outcome) = outcome {
+                return Ok(outcome);
+            }
+        }
+
+        Err(ErrorKind::NoMatch(TypeDescriptor::of(&subject)).into())
+    }
+
+    /// runs a compiled closure, up to the call in its tail position
+    fn enter(
+        &mut self,
+        closure: &Closure,
+        arguments: Arguments,
+    ) -> Result<Outcome, EvaluationError> {
+        let function = closure.function.clone();
+        let frame = Frame {
+            function: function.clone(),
+            slots: vec![None; function.slots.len()],
+            pasted: HashMap::new(),
+            arguments: Rc::new(arguments),
+            callee: closure.clone(),
+            parent: closure.parent.clone(),
+            globals: closure.globals.clone(),
+        };
+        self.run(&function, Some(Rc::new(frame)))
+    }
+}
+
+/// runs a compiled closure from outside of the machine, up to the call in
+/// its tail position. see [Interpreter::execute_closure_with] for making
+/// that call.
+pub(crate) fn enter(
+    program: &SharedProgram,
+    closure: &Closure,
+    arguments: Arguments,
+) -> Result<Outcome, EvaluationError> {
+    let mut env = Environment::new();
+    Executor {
+        env: &mut env,
+        program,
+        source: closure.source.clone(),
+    }
+    .enter(closure, arguments)
+}
+
+/// a virtual machine with the same interface as [Interpreter]. expressions
+/// are compiled to bytecode before they run, with every name a block uses
+/// resolved to a slot of the frame that binds it ahead of time.
+pub struct Machine {
+    pub root_node: ASTNode,
+    pub env: Environment,
+    pub program: SharedProgram,
+    /// what the code being evaluated was written in, as for [Interpreter]
+    pub source: Rc<Source>,
+}
+
+impl Machine {
+    pub fn new(root_node: ASTNode) -> Machine {
+        Machine {
+            root_node,
+            env: Environment::new(),
+            program: Rc::new(RefCell::new(ProgramState::with_engine(
+                Engine::Bytecode,
+            ))),
+            source: Rc::default(),
+        }
+    }
+
+    /// caps what the program may use from here on, starting afresh
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.program.borrow_mut().meter = Meter::new(limits);
+    }
+
+    /// replaces the runtime calls the program can make through `plz`
+    pub fn set_runtime(&mut self, runtime: RuntimeRegistry) {
+        self.program.borrow_mut().runtime = runtime;
+    }
+
+    pub fn bind_parameters(&mut self, parameters: Vec<Value>) {
+        for (index, parameter) in parameters.iter().enumerate() {
+            self.env.bind(format!("${}", index), parameter.clone());
+        }
+
+        self.env
+            .bind("$n".to_string(), Value::Integer(parameters.len() as i64));
+        self.env.bind("$@".to_string(), Value::Tuple(parameters));
+    }
+
+    pub fn evaluate_from_root(
+        &mut self,
+        parameters: Option<Vec<Value>>,
+    ) -> EvaluateResult {
+        self.env.push_stack_frame();
+        let root = self.root_node.clone();
+        if let Some(parameters) = parameters {
+            self.bind_parameters(parameters)
+        }
+        let result = self.evaluate(&root);
+        self.env
+            .pop_stack_frame()
+            .expect("stack corruption in eval from root");
+        result
+    }
+
+    /// evaluates an expression at the top level. like the interpreter, it
+    /// reports unbound symbols before any of the expression runs.
+    pub fn evaluate(&mut self, node: &ASTNode) -> EvaluateResult {
+        resolve(node, &self.env, &self.source)?;
+        let function = compile(node);
+        Executor {
+            env: &mut self.env,
+            program: &self.program,
+            source: self.source.clone(),
+        }
+        .run(&function, None)
+        .map(|outcome| match outcome {
+            Outcome::Done(value) => value,
+            Outcome::TailCall(_) => unreachable!("tail call at the top level"),
+        })
+    }
+
+    /// executes a closure with fresh program state. prefer
+    /// [Machine::call_closure] when there is a machine at hand, so that
+    /// modules imported by the closure are shared with the program.
+    /// only the tests need it; hosts call closures through
+    /// [crate::BlockPipe::call_value].
+    #[cfg(test)]
+    pub(crate) fn execute_closure(
+        parameters: Vec<Value>,
+        closure: &Value,
+    ) -> EvaluateResult {
+        let program =
+            Rc::new(RefCell::new(ProgramState::with_engine(Engine::Bytecode)));
+        Interpreter::execute_closure_with(&program, parameters.into(), closure)
+    }
+
+    /// executes a closure, sharing this machine's program state
+    pub fn call_closure(
+        &self,
+        parameters: Vec<Value>,
+        closure: &Value,
+    ) -> EvaluateResult {
+        Interpreter::execute_closure_with(
+            &self.program,
+            parameters.into(),
+            closure,
+        )
+    }
+}
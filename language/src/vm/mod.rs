@@ -0,0 +1,25 @@
+//! a bytecode compiler and the virtual machine that runs its output - an
+//! alternative to the tree walking [Interpreter](crate::Interpreter) that
+//! resolves names ahead of time instead of searching the environment.
+
+mod bytecode;
+mod compile;
+mod machine;
+#[cfg(test)]
+mod tests;
+
+pub use bytecode::*;
+pub use compile::*;
+pub use machine::*;
+
+// the interpreter's tests double as the machine's, so that the two engines
+// are held to the same behaviour
+#[cfg(test)]
+#[allow(unused_imports)]
+mod interpreter_tests {
+    use super::Machine as Interpreter;
+    use crate::interpreter::Address;
+    use crate::interpreter::*;
+
+    include!("../interpreter/tests.rs");
+}
@@ -0,0 +1,231 @@
+use indoc::indoc;
+
+use super::*;
+use crate::interpreter::{ErrorKind, EvaluateResult, Value};
+use crate::parser::{Accessor, Parameters, Pattern};
+use crate::{interpret_with_engine, parse_from_string, Engine};
+
+fn run(engine: Engine, source: &str) -> EvaluateResult {
+    interpret_with_engine(engine, source, None, None, true)
+}
+
+/// runs the source on both engines, which should always agree
+fn run_both(source: &str) -> EvaluateResult {
+    let interpreted = run(Engine::TreeWalking, source);
+    let compiled = run(Engine::Bytecode, source);
+    assert_eq!(interpreted, compiled);
+    compiled
+}
+
+/// the code of the first block defined by the given function
+fn block_code(function: &Function) -> &[Instruction] {
+    &function.functions[0].code
+}
+
+#[test]
+fn test_compile_resolves_slots() {
+    let function =
+        compile(&parse_from_string("{ a: 1 { (a b $0) } }").unwrap());
+    let outer = &function.functions[0];
+    assert_eq!(outer.slots, vec!["a".to_string()]);
+    assert_eq!(
+        block_code(outer),
+        &[
+            Instruction::Load(Address::Outer(1, 0)),
+            Instruction::Load(Address::Global("b".to_string())),
+            Instruction::Parameter(0),
+            Instruction::Tuple(3),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_pasted_names_are_dynamic() {
+    let source = "{ a: 1 paste (p) { (a b) } }";
+    let function = compile(&parse_from_string(source).unwrap());
+    assert_eq!(
+        block_code(&function.functions[0]),
+        &[
+            // pastes write to the slots of the names a block binds
+            Instruction::Load(Address::Outer(1, 0)),
+            Instruction::Load(Address::Dynamic("b".to_string())),
+            Instruction::Tuple(2),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_tail_calls() {
+    let function = compile(&parse_from_string("{ 1 | f | g }").unwrap());
+    assert_eq!(
+        &block_code(&function)[2..],
+        &[
+            Instruction::Call {
+                destructure: false,
+                callee: 0,
+            },
+            Instruction::Load(Address::Global("g".to_string())),
+            Instruction::TailCall {
+                destructure: false,
+                callee: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_compile_records() {
+    let function =
+        compile(&parse_from_string("{ ((a: $0 b: 2).a $b) }").unwrap());
+    assert_eq!(
+        block_code(&function),
+        &[
+            Instruction::Parameter(0),
+            Instruction::Constant(0),
+            Instruction::Record(vec!["a".to_string(), "b".to_string()]),
+            Instruction::Access(Accessor::Field("a".to_string())),
+            Instruction::NamedParameter("$b".to_string()),
+            Instruction::Tuple(2),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_match() {
+    let function = compile(
+        &parse_from_string("{ match ($0) { _ when { T } { 1 } $x { $x } } }")
+            .unwrap(),
+    );
+    assert_eq!(
+        block_code(&function),
+        &[
+            Instruction::Parameter(0),
+            Instruction::Closure(0),
+            Instruction::Closure(1),
+            Instruction::Closure(2),
+            Instruction::Match {
+                branches: vec![
+                    Branch {
+                        pattern: Pattern::Wildcard,
+                        guard: Some(22..27),
+                        body: 28..33,
+                    },
+                    Branch {
+                        pattern: Pattern::Capture("x".to_string()),
+                        guard: None,
+                        body: 37..43,
+                    },
+                ],
+                tail: true,
+            },
+        ]
+    );
+
+    // only a match in last place is left for the caller to run
+    let function =
+        compile(&parse_from_string("{ match (1) { _ { 1 } } 2 }").unwrap());
+    assert!(matches!(
+        block_code(&function)[2],
+        Instruction::Match { tail: false, .. }
+    ));
+}
+
+#[test]
+fn test_compile_parameters() {
+    let function =
+        compile(&parse_from_string("{ |a| (a $@ { ($1.. $0) }) }").unwrap());
+    let outer = &function.functions[0];
+    assert_eq!(outer.slots, vec!["a".to_string()]);
+    assert_eq!(
+        block_code(&function),
+        &[
+            Instruction::Declare(Parameters {
+                names: vec![("a".to_string(), 3..4)],
+                ..Parameters::default()
+            }),
+            Instruction::Pop,
+            Instruction::Load(Address::Local(0)),
+            Instruction::NamedParameter("$@".to_string()),
+            Instruction::Closure(0),
+            Instruction::Tuple(3),
+        ]
+    );
+    // every invocation has its own $1.., so only $0 is captured
+    assert_eq!(outer.functions[0].captures, vec!["$0".to_string()]);
+}
+
+#[test]
+fn test_closures_capture_bindings_at_creation() {
+    assert_eq!(
+        run_both(
+            r#"{
+                a: 1
+                get: { a }
+                a: 2
+                (() | get  a)
+            }"#
+        ),
+        Ok(Value::Tuple(vec![Value::Integer(1), Value::Integer(2)]))
+    );
+}
+
+#[test]
+fn test_rebinding_sees_outer_value_first() {
+    assert_eq!(
+        run_both(
+            r#"{
+                x: 1
+                () | {
+                    y: x
+                    x: 2
+                    (y x)
+                }
+            }"#
+        ),
+        Ok(Value::Tuple(vec![Value::Integer(1), Value::Integer(2)]))
+    );
+}
+
+#[test]
+fn test_missing_parameters_come_from_outer_blocks() {
+    assert_eq!(
+        run_both("{ 7 | { () |* { ($0 $n) } } }"),
+        Ok(Value::Tuple(vec![Value::Integer(7), Value::Integer(0)]))
+    );
+}
+
+#[test]
+fn test_paste_compiled_closure() {
+    assert_eq!(
+        run_both(
+            r#"{
+                point: () | { x: 1 y: 2 { (x y) } }
+                paste (point)
+                nested: { paste (point) { (x y) } }
+                () | nested | { () | $0 }
+            }"#
+        ),
+        Ok(Value::Tuple(vec![Value::Integer(1), Value::Integer(2)]))
+    );
+}
+
+#[test]
+fn test_errors_match_interpreter() {
+    let source = indoc! {"
+        {
+            check: {
+                $0 |* { missing }
+            }
+
+            (1 2) | check
+        }
+    "};
+
+    let interpreted = run(Engine::TreeWalking, source).unwrap_err();
+    let compiled = run(Engine::Bytecode, source).unwrap_err();
+    assert_eq!(compiled.render(source), interpreted.render(source));
+    assert_eq!(
+        compiled.kind,
+        ErrorKind::UnboundSymbol("missing".to_string())
+    );
+}
@@ -1,10 +1,455 @@
-mod interpreter_runtime;
+//! the runtime that compiled BlockPipe programs link against.
+//!
+//! compiled code handles values through pointers to [Value]s, which it only
+//! ever gets from (and passes back to) the `bp_*` functions below. a value
+//! handed to one of them is consumed, and freed once it's no longer needed,
+//! unless it's said to be borrowed. functions that can fail raise their error
+//! on the context instead of giving back a value, which compiled code checks
+//! for with [bp_failed]. everything the values go through - pipes,
+//! runtime calls, matches and the rest - is left to the language's
+//! [native](language::native) support, so compiled programs behave just like
+//! interpreted ones.
 
+#[cfg(test)]
+mod tests;
+
+use std::{
+    ffi::{c_char, c_int, CStr},
+    ptr, slice, str,
+};
+
+use language::native::{Context, NativeBlock};
+use language::{Arguments, EvaluationError, Value};
+
+/// raises an error on the context, giving back null in place of a value
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed
+unsafe fn raise(context: *mut Context, error: EvaluationError) -> *mut Value {
+    (*context).raise(error);
+    ptr::null_mut()
+}
+
+fn allocate(value: Value) -> *mut Value {
+    Box::into_raw(Box::new(value))
+}
+
+/// # Safety
+///
+/// the value must come from the runtime, and not have been consumed yet
+unsafe fn take(value: *mut Value) -> Value {
+    *Box::from_raw(value)
+}
+
+/// # Safety
+///
+/// the pointer must refer to `len` bytes of valid utf-8
+unsafe fn load_str<'a>(ptr: *const u8, len: usize) -> &'a str {
+    str::from_utf8_unchecked(slice::from_raw_parts(ptr, len))
+}
+
+fn unit() -> *mut Value {
+    allocate(Value::Tuple(vec![]))
+}
+
+#[no_mangle]
+pub extern "C" fn bp_unit() -> *mut Value {
+    unit()
+}
+
+#[no_mangle]
+pub extern "C" fn bp_integer(value: i64) -> *mut Value {
+    allocate(Value::Integer(value))
+}
+
+#[no_mangle]
+pub extern "C" fn bp_float(value: f64) -> *mut Value {
+    allocate(Value::Float(value))
+}
+
+#[no_mangle]
+pub extern "C" fn bp_boolean(value: u8) -> *mut Value {
+    allocate(Value::Boolean(value != 0))
+}
+
+/// # Safety
+///
+/// the pointer must refer to `len` bytes of valid utf-8
+#[no_mangle]
+pub unsafe extern "C" fn bp_string(ptr: *const u8, len: usize) -> *mut Value {
+    allocate(Value::String(load_str(ptr, len).to_string()))
+}
+
+/// frees a value that compiled code has no further use for
+///
+/// # Safety
+///
+/// the value must come from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_drop(value: *mut Value) {
+    drop(take(value));
+}
+
+/// creates an empty tuple, to be filled in with [bp_tuple_push] and
+/// [bp_paste] before it is used as a value.
+#[no_mangle]
+pub extern "C" fn bp_tuple() -> *mut Value {
+    unit()
+}
+
+/// # Safety
+///
+/// the tuple (which is borrowed) must come from [bp_tuple], and the value
+/// from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_tuple_push(tuple: *mut Value, value: *mut Value) {
+    if let Value::Tuple(values) = &mut *tuple {
+        values.push(take(value));
+    }
+}
+
+/// creates an empty record, to be filled in with [bp_field] before it is
+/// used as a value.
+#[no_mangle]
+pub extern "C" fn bp_record() -> *mut Value {
+    allocate(Value::Record(vec![]))
+}
+
+/// # Safety
+///
+/// the record (which is borrowed) must come from [bp_record], the label
+/// must refer to `len` bytes of valid utf-8, and the value must come from
+/// the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_field(
+    record: *mut Value,
+    label: *const u8,
+    len: usize,
+    value: *mut Value,
+) {
+    if let Value::Record(fields) = &mut *record {
+        fields.push((load_str(label, len).to_string(), take(value)));
+    }
+}
+
+/// pastes a value into a tuple under construction: tuples and records
+/// contribute their elements, while closures contribute their bindings to
+/// the current block instead of any values.
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed, the tuple
+/// (which is borrowed) must come from [bp_tuple], and the value from the
+/// runtime. the span is the one of the tuple, or of the paste itself
+/// outside of a tuple.
+#[no_mangle]
+pub unsafe extern "C" fn bp_paste(
+    context: *mut Context,
+    tuple: *mut Value,
+    value: *mut Value,
+    start: usize,
+    end: usize,
+) {
+    if let Err(error) = (*context).paste(&mut *tuple, take(value)) {
+        raise(context, error.with_span(start..end));
+    }
+}
+
+/// outside of a tuple, pasting behaves like a sequence of expressions: the
+/// last spliced value is the one that sticks
+///
+/// # Safety
+///
+/// the tuple must come from [bp_tuple]
+#[no_mangle]
+pub unsafe extern "C" fn bp_last(tuple: *mut Value) -> *mut Value {
+    match take(tuple) {
+        Value::Tuple(mut values) => {
+            allocate(values.pop().unwrap_or(Value::Tuple(vec![])))
+        }
+        _ => unit(),
+    }
+}
+
+/// looks up the name of the identifier with the given span
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed, and the name
+/// must refer to `len` bytes of valid utf-8
+#[no_mangle]
+pub unsafe extern "C" fn bp_lookup(
+    context: *mut Context,
+    name: *const u8,
+    len: usize,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    match (*context).lookup(load_str(name, len)) {
+        Ok(value) => allocate(value),
+        Err(error) => raise(context, error.with_span(start..end)),
+    }
+}
+
+/// # Safety
+///
+/// as for [bp_lookup], and the value must come from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_bind(
+    context: *mut Context,
+    name: *const u8,
+    len: usize,
+    value: *mut Value,
+) -> *mut Value {
+    (*context).bind(load_str(name, len).to_string(), take(value));
+    unit()
+}
+
+/// binds the parameters the current block declares, given the span of the
+/// declaration, to its arguments
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed
+#[no_mangle]
+pub unsafe extern "C" fn bp_declare(
+    context: *mut Context,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    match (*context).declare() {
+        Ok(()) => unit(),
+        Err(error) => raise(context, error.with_span(start..end)),
+    }
+}
+
+/// creates a closure over a compiled block, given the span of the block, to
+/// be filled in with [bp_capture] before it is used as a value
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed
+#[no_mangle]
+pub unsafe extern "C" fn bp_closure(
+    context: *mut Context,
+    function: NativeBlock,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    allocate((*context).closure(function, &(start..end)))
+}
+
+/// captures the current value of a name the block uses, if it is bound
+///
+/// # Safety
+///
+/// as for [bp_lookup], and the closure (which is borrowed) must come from
+/// [bp_closure]
+#[no_mangle]
+pub unsafe extern "C" fn bp_capture(
+    context: *mut Context,
+    closure: *mut Value,
+    name: *const u8,
+    len: usize,
+) {
+    (*context).capture(&mut *closure, load_str(name, len));
+}
+
+/// pipes a value into the stage of a pipe whose span is given. in tail
+/// position, the call is left for the caller of the current block to make.
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed, and both values
+/// must come from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_pipe(
+    context: *mut Context,
+    input: *mut Value,
+    callee: *mut Value,
+    destructure: u8,
+    tail: u8,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    let (input, callee) = (take(input), take(callee));
+    let span = start..end;
+    let result = match tail {
+        0 => (*context).pipe(input, callee, destructure != 0, &span),
+        _ => (*context).tail_pipe(input, callee, destructure != 0, &span),
+    };
+    match result {
+        Ok(value) => allocate(value),
+        Err(error) => raise(context, error),
+    }
+}
+
+/// # Safety
+///
+/// as for [bp_pipe]
+#[no_mangle]
+pub unsafe extern "C" fn bp_access(
+    context: *mut Context,
+    value: *mut Value,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    match (*context).access(take(value), &(start..end)) {
+        Ok(value) => allocate(value),
+        Err(error) => raise(context, error),
+    }
+}
+
+/// checks a value against the annotation with the given span
+///
+/// # Safety
+///
+/// as for [bp_pipe]
+#[no_mangle]
+pub unsafe extern "C" fn bp_annotate(
+    context: *mut Context,
+    value: *mut Value,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    match (*context).annotate(take(value), &(start..end)) {
+        Ok(value) => allocate(value),
+        Err(error) => raise(context, error),
+    }
+}
+
+/// # Safety
+///
+/// the value must come from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_type(value: *mut Value) -> *mut Value {
+    allocate(Value::Type(language::TypeDescriptor::of(&take(value))))
+}
+
+/// tests the subject of the match with the given span against the pattern
+/// of one of its arms, giving back what the pattern captured - or null, if
+/// it doesn't match
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed, and the
+/// subject (which is borrowed) must come from the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_match(
+    context: *mut Context,
+    subject: *mut Value,
+    start: usize,
+    end: usize,
+    arm: usize,
+) -> *mut Arguments {
+    match (*context).matches(&*subject, &(start..end), arm) {
+        Some(captured) => Box::into_raw(Box::new(captured)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// runs an arm of a match whose pattern matched, given what it captured and
+/// the blocks of its guard (null if it has none) and body. gives back null
+/// if the guard turned the arm down.
+///
+/// # Safety
+///
+/// as for [bp_match], the captures must come from it, and the blocks from
+/// the runtime
+#[no_mangle]
+pub unsafe extern "C" fn bp_arm(
+    context: *mut Context,
+    captured: *mut Arguments,
+    guard: *mut Value,
+    body: *mut Value,
+    start: usize,
+    end: usize,
+    arm: usize,
+    tail: u8,
+) -> *mut Value {
+    let captured = *Box::from_raw(captured);
+    let guard = (!guard.is_null()).then(|| take(guard));
+    let arm = (&(start..end), arm);
+    match (*context).enter_arm(captured, guard, take(body), arm, tail != 0) {
+        Ok(Some(value)) => allocate(value),
+        Ok(None) => ptr::null_mut(),
+        Err(error) => raise(context, error),
+    }
+}
+
+/// raises the error for the match with the given span, none of whose arms
+/// accept the subject
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed, and the
+/// subject (which is borrowed) must come from the runtime
 #[no_mangle]
-pub extern "C" fn add_integers(left: i64, right: i64) -> i64 {
-    left + right
+pub unsafe extern "C" fn bp_no_match(
+    context: *mut Context,
+    subject: *mut Value,
+    start: usize,
+    end: usize,
+) -> *mut Value {
+    raise(context, Context::no_match(&*subject, &(start..end)))
 }
 
-pub extern "C" fn add_floats(left: f64, right: f64) -> f64 {
-    left + right
-}
\ No newline at end of file
+/// whether the current block has raised an error, in which case compiled
+/// code returns from it straight away, leaving the error to its caller
+///
+/// # Safety
+///
+/// the context must be the one the compiled code was handed
+#[no_mangle]
+pub unsafe extern "C" fn bp_failed(context: *mut Context) -> u8 {
+    (*context).failed() as u8
+}
+
+/// the entry point of a compiled program, given its root and its source.
+/// evaluates the root expression with the command line arguments bound as
+/// parameters, then executes the resulting block with them and prints the
+/// outcome, just like the interpreter does.
+///
+/// # Safety
+///
+/// the source must refer to `len` bytes of valid utf-8 - the source the
+/// program was compiled from - and the arguments must be the ones handed to
+/// `main`
+#[no_mangle]
+pub unsafe extern "C" fn bp_main(
+    root: NativeBlock,
+    source: *const u8,
+    len: usize,
+    argc: c_int,
+    argv: *const *const c_char,
+) -> c_int {
+    let parameters: Vec<Value> = (1..argc.max(1) as usize)
+        .map(|index| {
+            let argument = CStr::from_ptr(*argv.add(index));
+            Value::String(argument.to_string_lossy().into_owned())
+        })
+        .collect();
+
+    let source = load_str(source, len);
+    let result = language::parse_from_string(source)
+        .map_err(EvaluationError::from)
+        .and_then(|ast| Context::toplevel(&ast, parameters.clone()))
+        .and_then(|mut context| {
+            let root = root(&mut context);
+            match context.take_error() {
+                Some(error) => Err(error),
+                None => context.call(&take(root), parameters),
+            }
+        });
+    match result {
+        Ok(value) => {
+            println!("Ok({:?})", value);
+            0
+        }
+        Err(error) => {
+            eprint!("{}", error.render(source));
+            1
+        }
+    }
+}
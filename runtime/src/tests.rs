@@ -0,0 +1,157 @@
+use language::{ASTNode, ErrorKind, TypeDescriptor};
+
+use super::*;
+
+fn integers(values: &[i64]) -> Value {
+    Value::Tuple(values.iter().copied().map(Value::Integer).collect())
+}
+
+/// the root of a program, along with the context it runs in
+fn toplevel(source: &str) -> (ASTNode, Context) {
+    let root = language::parse_from_string(source).unwrap();
+    let context = Context::toplevel(&root, vec![]).unwrap();
+    (root, context)
+}
+
+unsafe extern "C" fn second_parameter(context: *mut Context) -> *mut Value {
+    bp_lookup(context, "$1".as_ptr(), 2, 0, 0)
+}
+
+#[test]
+fn test_build_values() {
+    unsafe {
+        let tuple = bp_tuple();
+        bp_tuple_push(tuple, bp_integer(1));
+        bp_tuple_push(tuple, bp_boolean(1));
+        let record = bp_record();
+        bp_field(record, "x".as_ptr(), 1, bp_float(0.5));
+        bp_tuple_push(tuple, record);
+
+        assert_eq!(
+            take(tuple),
+            Value::Tuple(vec![
+                Value::Integer(1),
+                Value::Boolean(true),
+                Value::Record(vec![("x".to_string(), Value::Float(0.5))]),
+            ])
+        );
+        assert_eq!(
+            take(bp_type(bp_string("hi".as_ptr(), 2))),
+            Value::Type(TypeDescriptor::String)
+        );
+        bp_drop(bp_unit());
+    }
+}
+
+#[test]
+fn test_paste() {
+    let (_, mut context) = toplevel("()");
+    unsafe {
+        let tuple = bp_tuple();
+        let one = Box::into_raw(Box::new(integers(&[1])));
+        bp_paste(&mut context, tuple, one, 0, 2);
+        bp_paste(&mut context, tuple, bp_tuple(), 0, 2);
+        let more = Box::into_raw(Box::new(integers(&[2, 3])));
+        bp_paste(&mut context, tuple, more, 0, 2);
+        assert_eq!(take(bp_last(tuple)), Value::Integer(3));
+        assert_eq!(bp_failed(&mut context), 0);
+    }
+
+    assert_eq!(
+        context
+            .paste(&mut Value::Tuple(vec![]), Value::Integer(1))
+            .map_err(|error| error.kind),
+        Err(ErrorKind::TypeMismatch(
+            "Can only paste tuples and closures, not Integer".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_bindings_shadow() {
+    let (_, mut context) = toplevel("()");
+    unsafe {
+        bp_drop(bp_bind(&mut context, "a".as_ptr(), 1, bp_integer(1)));
+        bp_drop(bp_bind(&mut context, "a".as_ptr(), 1, bp_integer(2)));
+        let value = bp_lookup(&mut context, "a".as_ptr(), 1, 0, 1);
+        assert_eq!(take(value), Value::Integer(2));
+
+        // a failed lookup raises its error on the context instead
+        assert!(bp_lookup(&mut context, "b".as_ptr(), 1, 0, 1).is_null());
+        assert_eq!(bp_failed(&mut context), 1);
+        let error = context.take_error().unwrap();
+        assert_eq!(error.kind, ErrorKind::UnboundSymbol("b".to_string()));
+        assert_eq!(error.span, Some(0..1));
+    }
+
+    assert_eq!(
+        context.lookup("b").map_err(|error| error.kind),
+        Err(ErrorKind::UnboundSymbol("b".to_string()))
+    );
+}
+
+#[test]
+fn test_pipe_into_closure() {
+    let source = "(1 2) |* { $1 }";
+    let (root, mut context) = toplevel(source);
+    let ASTNode::Pipe(stages, _, _) = root else {
+        unreachable!()
+    };
+    let span = stages[1].span();
+
+    let closure = context.closure(second_parameter, &span);
+    unsafe {
+        let result = bp_pipe(
+            &mut context,
+            Box::into_raw(Box::new(integers(&[1, 2]))),
+            Box::into_raw(Box::new(closure.clone())),
+            1,
+            0,
+            span.start,
+            span.end,
+        );
+        assert_eq!(take(result), Value::Integer(2));
+    }
+
+    assert!(context
+        .pipe(Value::Integer(1), closure, true, &span)
+        .is_err());
+    assert_eq!(
+        context
+            .pipe(Value::Integer(1), Value::Integer(2), false, &span)
+            .map_err(|error| error.kind),
+        Err(ErrorKind::TypeMismatch(
+            "Cannot pipe into a value of type Integer".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_pipe_into_runtime_invocation() {
+    let (_, mut context) = toplevel("()");
+    let arguments = Value::Tuple(vec![
+        Value::Tuple(vec![
+            Value::String("a".to_string()),
+            Value::String("b".to_string()),
+        ]),
+        Value::String("strcat".to_string()),
+    ]);
+
+    assert_eq!(
+        context.pipe(arguments, Value::RuntimeInvocation, true, &(0..0)),
+        Ok(Value::String("ab".to_string()))
+    );
+}
+
+#[test]
+fn test_match() {
+    let (root, context) = toplevel("match (3) { 1 { () } Integer { 0 } }");
+    let span = root.span();
+
+    let subject = Value::Integer(3);
+    assert_eq!(context.matches(&subject, &span, 0), None);
+    assert!(context.matches(&subject, &span, 1).is_some());
+    let error = Context::no_match(&subject, &span);
+    assert_eq!(error.kind, ErrorKind::NoMatch(TypeDescriptor::Integer));
+    assert_eq!(error.span, Some(span));
+}